@@ -143,6 +143,18 @@ class LocalCommandExecutor : CommandExecutor {
 							}
 						}
 
+						// NOTE: lines weren't requested
+						is Response.ProcessEvent.ConsoleLine -> Unit
+
+						is Response.ProcessEvent.Dropped -> {
+							val marker = "\n[${event.bytes} bytes dropped]\n"
+							when (event.kind) {
+								Response.ProcessEvent.ConsoleKind.Stdout -> out.add(marker)
+								Response.ProcessEvent.ConsoleKind.Stderr -> err.add(marker)
+							}
+							combined.add(marker)
+						}
+
 						is Response.ProcessEvent.Fin -> {
 							exitCode = event.exitCode
 								?: -1024
@@ -151,6 +163,12 @@ class LocalCommandExecutor : CommandExecutor {
 								// (eg, because of a signal), then pick an arbitrary error code
 							break
 						}
+
+						is Response.ProcessEvent.Detached -> {
+							// the host processor shut down, so we'll never know how the command exited
+							exitCode = -1024
+							break
+						}
 					}
 				}
 
@@ -17,6 +17,7 @@ import java.nio.BufferUnderflowException
 import java.nio.channels.SocketChannel
 import java.nio.file.Path
 import kotlin.io.path.div
+import kotlin.time.Duration
 
 
 private val log = LoggerFactory.getLogger("HostProcessor")
@@ -201,7 +202,13 @@ class HostProcessor(
 	/**
 	 * Launch a process
 	 */
-	suspend fun exec(cmd: Command, dir: Path? = null, outPath: Path? = null): Process =
+	suspend fun exec(
+		cmd: Command,
+		dir: Path? = null,
+		outPath: Path? = null,
+		env: Request.Exec.Env? = null,
+		container: Request.Exec.Container? = null
+	): Process =
 		connectionOrThrow
 			.request(
 				Request.Exec(
@@ -224,7 +231,9 @@ class HostProcessor(
 						} else {
 							Request.Exec.Stderr.Ignore
 						},
-					streamFin = false
+					streamFin = false,
+					env = env,
+					container = container
 				)
 			)
 			.use { responder ->
@@ -239,8 +248,11 @@ class HostProcessor(
 	interface Process {
 		val pid: UInt
 		suspend fun status(): Response.Status
+		suspend fun wait(timeout: Duration? = null): Response.Wait.Response
 		suspend fun interrupt(processGroup: Boolean = false)
 		suspend fun kill(processGroup: Boolean = false)
+		suspend fun signal(signal: Request.Kill.Signal, processGroup: Boolean = false): Response.Kill.Response
+		suspend fun killGracefully(timeout: Duration): Response.KillGracefully.Response
 	}
 
 	open inner class ProcessImpl(override val pid: UInt) : Process {
@@ -252,6 +264,17 @@ class HostProcessor(
 					responder.recv<Response.Status>()
 				}
 
+		/**
+		 * Waits for the process to exit, instead of polling the status
+		 */
+		override suspend fun wait(timeout: Duration?): Response.Wait.Response =
+			connectionOrThrow
+				.request(Request.Wait(pid, timeout?.inWholeMilliseconds?.toUInt()))
+				.use { responder ->
+					responder.recv<Response.Wait>()
+						.response
+				}
+
 		/** shells usually respond to SIGINT rather than SIGTERM */
 		override suspend fun interrupt(processGroup: Boolean) =
 			connectionOrThrow
@@ -260,6 +283,25 @@ class HostProcessor(
 		override suspend fun kill(processGroup: Boolean) =
 			connectionOrThrow
 				.send(Request.Kill(Request.Kill.Signal.Kill, pid, processGroup))
+
+		override suspend fun signal(signal: Request.Kill.Signal, processGroup: Boolean): Response.Kill.Response =
+			connectionOrThrow
+				.request(Request.Kill(signal, pid, processGroup))
+				.use { responder ->
+					responder.recv<Response.Kill>()
+						.response
+				}
+
+		/**
+		 * Sends SIGTERM to the process group, then SIGKILL if the process doesn't exit before the timeout
+		 */
+		override suspend fun killGracefully(timeout: Duration): Response.KillGracefully.Response =
+			connectionOrThrow
+				.request(Request.KillGracefully(pid, timeout.inWholeMilliseconds.toUInt()))
+				.use { responder ->
+					responder.recv<Response.KillGracefully>()
+						.response
+				}
 	}
 
 
@@ -271,7 +313,11 @@ class HostProcessor(
 		dir: Path? = null,
 		stdin: Boolean = false,
 		stdout: Boolean = false,
-		stderr: Boolean = false
+		stderr: Boolean = false,
+		pty: Boolean = false,
+		lines: Request.Exec.Lines? = null,
+		env: Request.Exec.Env? = null,
+		container: Request.Exec.Container? = null
 	): StreamingProcess {
 
 		val responder = connectionOrThrow
@@ -282,16 +328,69 @@ class HostProcessor(
 					dir?.toString(),
 					cmd.envvars.map { it.name to it.value },
 					if (stdin) Request.Exec.Stdin.Stream else Request.Exec.Stdin.Ignore,
+					when {
+						pty -> Request.Exec.Stdout.Pty
+						stdout -> Request.Exec.Stdout.Stream
+						else -> Request.Exec.Stdout.Ignore
+					},
+					when {
+						pty -> Request.Exec.Stderr.Merge
+						stderr -> Request.Exec.Stderr.Stream
+						else -> Request.Exec.Stderr.Ignore
+					},
+					streamFin = true,
+					lines = lines,
+					env = env,
+					container = container
+				)
+			)
+
+		// handle the launch response
+		when (val response = responder.recv<Response.Exec>().response) {
+			is Response.Exec.Success -> return StreamingProcessImpl(response.pid, responder)
+			is Response.Exec.Failure -> throw Error("Failed to launch process: ${response.reason}")
+			else -> throw Error("Unexpected response: $response")
+		}
+	}
+
+	/**
+	 * Launch a pipeline of processes, where each command's stdout feeds the next command's stdin, and stream to and/or from it.
+	 * Stdin goes to the first command, stdout comes from the last command, and stderr comes from all of them.
+	 */
+	suspend fun execPipelineStream(
+		cmds: List<Command>,
+		dir: Path? = null,
+		stdin: Boolean = false,
+		stdout: Boolean = false,
+		stderr: Boolean = false,
+		lines: Request.Exec.Lines? = null,
+		env: Request.Exec.Env? = null
+	): StreamingProcess {
+
+		val responder = connectionOrThrow
+			.request(
+				Request.ExecPipeline(
+					cmds.map { cmd ->
+						Request.ExecPipeline.Stage(
+							cmd.program,
+							cmd.args,
+							cmd.envvars.map { it.name to it.value }
+						)
+					},
+					dir?.toString(),
+					if (stdin) Request.Exec.Stdin.Stream else Request.Exec.Stdin.Ignore,
 					if (stdout) Request.Exec.Stdout.Stream else Request.Exec.Stdout.Ignore,
 					if (stderr) Request.Exec.Stderr.Stream else Request.Exec.Stderr.Ignore,
-					streamFin = true
+					streamFin = true,
+					lines = lines,
+					env = env
 				)
 			)
 
 		// handle the launch response
 		when (val response = responder.recv<Response.Exec>().response) {
 			is Response.Exec.Success -> return StreamingProcessImpl(response.pid, responder)
-			is Response.Exec.Failure -> throw Error("Failed to launch process: ${response.reason}")
+			is Response.Exec.Failure -> throw Error("Failed to launch pipeline: ${response.reason}")
 			else -> throw Error("Unexpected response: $response")
 		}
 	}
@@ -304,9 +403,26 @@ class HostProcessor(
 		interface Stdin {
 			suspend fun write(bytes: ByteArray)
 			suspend fun close()
+
+			/**
+			 * Writes the bytes and waits for the host processor to say how it went.
+			 * Each acked write fits in the connection's stdin window, so don't send more than the last credit
+			 * without waiting for other acks first.
+			 */
+			suspend fun writeAcked(bytes: ByteArray): Response.Stdin.Response
+
+			/**
+			 * Closes stdin and waits for the host processor to say how it went
+			 */
+			suspend fun closeAcked(): Response.Stdin.Response
 		}
 		val stdin: Stdin
 
+		/**
+		 * Resizes the pseudo-terminal, if the process was started with one
+		 */
+		suspend fun resizePty(rows: UShort, cols: UShort)
+
 		data class ProcessRun(
 			val exitCode: Int?,
 			val console: String
@@ -325,11 +441,24 @@ class HostProcessor(
 					is Response.ProcessEvent.Console ->
 						console.append(event.chunk.toString(Charsets.UTF_8))
 
+					is Response.ProcessEvent.ConsoleLine ->
+						console.append(event.text)
+
+					is Response.ProcessEvent.Dropped ->
+						console.append("\n[${event.bytes} bytes of ${event.kind.name.lowercase()} dropped]\n")
+
 					is Response.ProcessEvent.Fin ->
 						return ProcessRun(
 							event.exitCode,
 							console.toString()
 						)
+
+					// the host processor shut down, so we'll never know how the process exited
+					is Response.ProcessEvent.Detached ->
+						return ProcessRun(
+							null,
+							console.toString()
+						)
 				}
 			}
 		}
@@ -362,7 +491,27 @@ class HostProcessor(
 			override suspend fun close() {
 				responder.send(Request.CloseStdin(pid))
 			}
+
+			// NOTE: the acks need their own request ids, since the exec request id already gets the process events
+			override suspend fun writeAcked(bytes: ByteArray): Response.Stdin.Response =
+				connectionOrThrow
+					.request(Request.WriteStdin(pid, bytes, ack = true))
+					.use { responder ->
+						responder.recv<Response.Stdin>()
+							.response
+					}
+
+			override suspend fun closeAcked(): Response.Stdin.Response =
+				connectionOrThrow
+					.request(Request.CloseStdin(pid, ack = true))
+					.use { responder ->
+						responder.recv<Response.Stdin>()
+							.response
+					}
 		}
+
+		override suspend fun resizePty(rows: UShort, cols: UShort) =
+			responder.send(Request.ResizePty(pid, rows, cols))
 	}
 
 	suspend fun username(uid: UInt): String? =
@@ -404,6 +553,21 @@ class HostProcessor(
 				responder.recv<Response.Gids>()
 					.gids
 			}
+
+	suspend fun listProcesses(): List<Response.ListProcesses.ProcessInfo> =
+		connectionOrThrow
+			.request(Request.ListProcesses())
+			.use { responder ->
+				responder.recv<Response.ListProcesses>()
+					.processes
+			}
+
+	suspend fun stats(): Response.Stats =
+		connectionOrThrow
+			.request(Request.Stats())
+			.use { responder ->
+				responder.recv<Response.Stats>()
+			}
 }
 
 
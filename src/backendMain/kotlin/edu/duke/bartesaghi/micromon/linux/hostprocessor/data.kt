@@ -12,6 +12,15 @@ fun DataInput.readU8(): UByte =
 	readByte().toUByte()
 
 
+fun DataOutput.writeU16(i: UShort) =
+	// NOTE: writeShort() is big-endian
+	writeShort(i.toInt())
+
+fun DataInput.readU16(): UShort =
+	// NOTE: readUnsignedShort() is big-endian
+	readUnsignedShort().toUShort()
+
+
 fun DataOutput.writeU32(i: UInt) =
 	// NOTE: writeInt() is big-endian
 	writeInt(i.toInt())
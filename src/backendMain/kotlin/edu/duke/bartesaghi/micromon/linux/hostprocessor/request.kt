@@ -22,12 +22,138 @@ sealed interface Request {
 		val stdin: Stdin,
 		val stdout: Stdout,
 		val stderr: Stderr,
-		val streamFin: Boolean
+		val streamFin: Boolean,
+		val limits: Limits? = null,
+		val uid: UInt? = null,
+		val gid: UInt? = null,
+		val gids: List<UInt>? = null,
+		val persist: Boolean = false,
+		/** stream the console as whole lines, in ProcessEvent.ConsoleLine, instead of raw chunks */
+		val lines: Lines? = null,
+		/** which of the host processor's environment variables the process gets before envvars, or null for all of them */
+		val env: Env? = null,
+		/** run the program inside this container, with apptainer, instead of directly on the host */
+		val container: Container? = null
 	) : Request {
 		companion object {
 			const val ID: UInt = 2u
 		}
 
+		data class Limits(
+			/** in bytes */
+			val addressSpace: ULong? = null,
+			/** in seconds */
+			val cpuTime: ULong? = null,
+			val openFiles: ULong? = null,
+			val processes: ULong? = null,
+			val cgroup: Cgroup? = null,
+			/** in milliseconds, the host processor stops the process group after this long */
+			val wallTime: ULong? = null,
+			/** in milliseconds, the host processor stops the process group after this long without any console output */
+			val idleTime: ULong? = null
+		)
+
+		data class Cgroup(
+			/** in bytes */
+			val memoryMax: ULong? = null,
+			val cpuMax: CpuMax? = null
+		)
+
+		data class CpuMax(
+			/** in microseconds */
+			val quota: ULong,
+			/** in microseconds */
+			val period: ULong
+		)
+
+		data class Lines(
+			/** longer lines are cut off at this many bytes and the rest of the line is dropped */
+			val maxLineLength: UInt,
+			/** once this many bytes of a line are waiting for a newline, send them as a partial line */
+			val maxPartial: UInt
+		)
+
+		data class Env(
+			val mode: EnvMode,
+			/** variables to remove from the environment, after applying the mode */
+			val unset: List<String> = emptyList()
+		)
+
+		sealed interface EnvMode {
+
+			object Inherit : EnvMode {
+				const val ID: UInt = 1u
+			}
+
+			object Clear : EnvMode {
+				const val ID: UInt = 2u
+			}
+
+			data class Allow(val names: List<String>) : EnvMode {
+				companion object {
+					const val ID: UInt = 3u
+				}
+			}
+		}
+
+		data class Container(
+			/** eg the path to a .sif file */
+			val image: String,
+			/** host folders to mount in the container, which must be inside the host processor's allowed_binds */
+			val binds: List<Bind> = emptyList(),
+			/** variables to set inside the container, in addition to the ones apptainer passes through from the host */
+			val envvars: List<Pair<String,String>> = emptyList(),
+			val flags: List<Flag> = emptyList(),
+			/** the working folder inside the container, or null for apptainer's default */
+			val workdir: String? = null
+		) {
+
+			data class Bind(
+				val src: String,
+				/** where to mount it in the container, or null for the same path as the host */
+				val dst: String? = null,
+				val readOnly: Boolean = false
+			)
+
+			enum class Flag(val id: UInt) {
+
+				/** --nv */
+				Nv(1u),
+				/** --rocm */
+				Rocm(2u),
+				/** --cleanenv */
+				CleanEnv(3u),
+				/** --contain */
+				Contain(4u),
+				/** --no-home */
+				NoHome(5u);
+
+				companion object {
+					operator fun get(id: UInt): Flag =
+						values().find { it.id == id }
+							?: throw NoSuchElementException("unrecognized container flag: $id")
+				}
+			}
+		}
+
+		data class LogFile(
+			val path: String,
+			/** append to the file if it already exists, instead of truncating it */
+			val append: Boolean = false,
+			val rotation: LogRotation? = null,
+			/** prefix each line with the local time */
+			val timestamps: Boolean = false
+		)
+
+		data class LogRotation(
+			/** rotate the file once it reaches this size, in bytes */
+			val maxSize: ULong,
+			/** how many rotated files to keep, named eg path.1, path.2, etc */
+			val generations: UInt,
+			/** compress the rotated files with gzip */
+			val gzip: Boolean = false
+		)
+
 		sealed interface Stdin {
 
 			object Stream : Stdin {
@@ -37,6 +163,29 @@ sealed interface Request {
 			object Ignore : Stdin {
 				const val ID: UInt = 2u
 			}
+
+			/**
+			 * Uses a file descriptor sent with the request in SCM_RIGHTS ancillary data.
+			 * NOTE: SocketChannel can't send file descriptors, so only other clients can use this,
+			 *       but processes launched that way can still show up in ListProcesses
+			 */
+			object Fd : Stdin {
+				const val ID: UInt = 3u
+			}
+
+			/** reads from a file, which the host processor opens */
+			data class File(val path: String) : Stdin {
+				companion object {
+					const val ID: UInt = 4u
+				}
+			}
+
+			/** reads these bytes, then end of file, for small payloads */
+			class Bytes(val bytes: ByteArray) : Stdin {
+				companion object {
+					const val ID: UInt = 5u
+				}
+			}
 		}
 
 		sealed interface Stdout {
@@ -58,6 +207,25 @@ sealed interface Request {
 			object Ignore : Stdout {
 				const val ID: UInt = 4u
 			}
+
+			/**
+			 * Runs the process in a pseudo-terminal and streams the terminal output.
+			 * The terminal is also used for stdin when stdin is Stream, and for stderr when stderr is Merge.
+			 */
+			object Pty : Stdout {
+				const val ID: UInt = 5u
+			}
+
+			data class LogFile(val logFile: Exec.LogFile) : Stdout {
+				companion object {
+					const val ID: UInt = 6u
+				}
+			}
+
+			/** Uses a file descriptor sent with the request, see Stdin.Fd */
+			object Fd : Stdout {
+				const val ID: UInt = 7u
+			}
 		}
 
 		sealed interface Stderr {
@@ -83,6 +251,17 @@ sealed interface Request {
 			object Ignore : Stderr {
 				const val ID: UInt = 5u
 			}
+
+			data class LogFile(val logFile: Exec.LogFile) : Stderr {
+				companion object {
+					const val ID: UInt = 6u
+				}
+			}
+
+			/** Uses a file descriptor sent with the request, see Stdin.Fd */
+			object Fd : Stderr {
+				const val ID: UInt = 7u
+			}
 		}
 	}
 
@@ -96,7 +275,9 @@ sealed interface Request {
 
 	class WriteStdin(
 		val pid: UInt,
-		val chunk: ByteArray
+		val chunk: ByteArray,
+		/** respond with a Stdin response, otherwise errors are only logged by the host processor */
+		val ack: Boolean = false
 	) : Request {
 		companion object {
 			const val ID: UInt = 4u
@@ -104,7 +285,9 @@ sealed interface Request {
 	}
 
 	class CloseStdin(
-		val pid: UInt
+		val pid: UInt,
+		/** respond with a Stdin response, otherwise errors are only logged by the host processor */
+		val ack: Boolean = false
 	) : Request {
 		companion object {
 			const val ID: UInt = 5u
@@ -123,7 +306,14 @@ sealed interface Request {
 		enum class Signal(val id: String) {
 
 			Interrupt("SIGINT"),
-			Kill("SIGKILL");
+			Kill("SIGKILL"),
+			Terminate("SIGTERM"),
+			Hangup("SIGHUP"),
+			User1("SIGUSR1"),
+			User2("SIGUSR2"),
+			Stop("SIGSTOP"),
+			Continue("SIGCONT"),
+			Quit("SIGQUIT");
 
 			companion object {
 				operator fun get(id: String): Signal? =
@@ -171,6 +361,87 @@ sealed interface Request {
 			const val ID: UInt = 11u
 		}
 	}
+
+	class Attach(
+		val pid: UInt
+	) : Request {
+		companion object {
+			const val ID: UInt = 12u
+		}
+	}
+
+	class ListProcesses : Request {
+		companion object {
+			const val ID: UInt = 13u
+		}
+	}
+
+	class KillGracefully(
+		val pid: UInt,
+		/** in milliseconds */
+		val timeout: UInt
+	) : Request {
+		companion object {
+			const val ID: UInt = 14u
+		}
+	}
+
+	class ResizePty(
+		val pid: UInt,
+		val rows: UShort,
+		val cols: UShort
+	) : Request {
+		companion object {
+			const val ID: UInt = 15u
+		}
+	}
+
+	class Stats : Request {
+		companion object {
+			const val ID: UInt = 16u
+		}
+	}
+
+	/** waits for a process launched with Exec to exit, from any connection */
+	class Wait(
+		val pid: UInt,
+		/** in milliseconds, or null to wait as long as it takes */
+		val timeout: UInt?
+	) : Request {
+		companion object {
+			const val ID: UInt = 17u
+		}
+	}
+
+	/** launches processes where each stage's stdout feeds the next stage's stdin, like a shell pipeline, in one process group */
+	class ExecPipeline(
+		val stages: List<Stage>,
+		val dir: String?,
+		/** for the first stage */
+		val stdin: Exec.Stdin,
+		/** for the last stage, anything except Pty */
+		val stdout: Exec.Stdout,
+		/** for every stage */
+		val stderr: Exec.Stderr,
+		val streamFin: Boolean,
+		/** for the whole pipeline */
+		val limits: Exec.Limits? = null,
+		val uid: UInt? = null,
+		val gid: UInt? = null,
+		val gids: List<UInt>? = null,
+		val lines: Exec.Lines? = null,
+		val env: Exec.Env? = null
+	) : Request {
+		companion object {
+			const val ID: UInt = 18u
+		}
+
+		data class Stage(
+			val program: String,
+			val args: List<String> = emptyList(),
+			val envvars: List<Pair<String,String>> = emptyList()
+		)
+	}
 }
 
 
@@ -205,48 +476,49 @@ class RequestEnvelope(
 					out.writeUtf8(k)
 					out.writeUtf8(v)
 				}
-				when (request.stdin) {
-					Request.Exec.Stdin.Stream -> {
-						out.writeU32(Request.Exec.Stdin.Stream.ID)
-					}
-					Request.Exec.Stdin.Ignore -> {
-						out.writeU32(Request.Exec.Stdin.Ignore.ID)
-					}
+				out.writeStdin(request.stdin)
+				out.writeStdout(request.stdout)
+				out.writeStderr(request.stderr)
+				out.writeBoolean(request.streamFin)
+				out.writeOption(request.limits) { out.writeLimits(it) }
+				out.writeOption(request.uid) { out.writeU32(it) }
+				out.writeOption(request.gid) { out.writeU32(it) }
+				out.writeOption(request.gids) { gids ->
+					out.writeArray(gids) { out.writeU32(it) }
 				}
-				when (request.stdout) {
-					Request.Exec.Stdout.Stream -> {
-						out.writeU32(Request.Exec.Stdout.Stream.ID)
-					}
-					is Request.Exec.Stdout.Write -> {
-						out.writeU32(Request.Exec.Stdout.Write.ID)
-						out.writeUtf8(request.stdout.path)
-					}
-					Request.Exec.Stdout.Log -> {
-						out.writeU32(Request.Exec.Stdout.Log.ID)
+				out.writeBoolean(request.persist)
+				out.writeOption(request.lines) { out.writeLines(it) }
+				out.writeOption(request.env) { out.writeEnv(it) }
+				out.writeOption(request.container) { out.writeContainer(it) }
+			}
+
+			is Request.ExecPipeline -> {
+				out.writeU32(Request.ExecPipeline.ID)
+				out.writeArray(request.stages) { stage ->
+					out.writeUtf8(stage.program)
+					out.writeArray(stage.args) {
+						out.writeUtf8(it)
 					}
-					Request.Exec.Stdout.Ignore -> {
-						out.writeU32(Request.Exec.Stdout.Ignore.ID)
+					out.writeArray(stage.envvars) { (k, v) ->
+						out.writeUtf8(k)
+						out.writeUtf8(v)
 					}
 				}
-				when (request.stderr) {
-					Request.Exec.Stderr.Stream -> {
-						out.writeU32(Request.Exec.Stderr.Stream.ID)
-					}
-					is Request.Exec.Stderr.Write -> {
-						out.writeU32(Request.Exec.Stderr.Write.ID)
-						out.writeUtf8(request.stderr.path)
-					}
-					Request.Exec.Stderr.Merge -> {
-						out.writeU32(Request.Exec.Stderr.Merge.ID)
-					}
-					Request.Exec.Stderr.Log -> {
-						out.writeU32(Request.Exec.Stderr.Log.ID)
-					}
-					Request.Exec.Stderr.Ignore -> {
-						out.writeU32(Request.Exec.Stderr.Ignore.ID)
-					}
+				out.writeOption(request.dir) {
+					out.writeUtf8(it)
 				}
+				out.writeStdin(request.stdin)
+				out.writeStdout(request.stdout)
+				out.writeStderr(request.stderr)
 				out.writeBoolean(request.streamFin)
+				out.writeOption(request.limits) { out.writeLimits(it) }
+				out.writeOption(request.uid) { out.writeU32(it) }
+				out.writeOption(request.gid) { out.writeU32(it) }
+				out.writeOption(request.gids) { gids ->
+					out.writeArray(gids) { out.writeU32(it) }
+				}
+				out.writeOption(request.lines) { out.writeLines(it) }
+				out.writeOption(request.env) { out.writeEnv(it) }
 			}
 
 			is Request.Status -> {
@@ -258,11 +530,13 @@ class RequestEnvelope(
 				out.writeU32(Request.WriteStdin.ID)
 				out.writeU32(request.pid)
 				out.writeBytes(request.chunk)
+				out.writeBoolean(request.ack)
 			}
 
 			is Request.CloseStdin -> {
 				out.writeU32(Request.CloseStdin.ID)
 				out.writeU32(request.pid)
+				out.writeBoolean(request.ack)
 			}
 
 			is Request.Kill -> {
@@ -296,6 +570,40 @@ class RequestEnvelope(
 				out.writeU32(Request.Gids.ID)
 				out.writeU32(request.uid)
 			}
+
+			is Request.Attach -> {
+				out.writeU32(Request.Attach.ID)
+				out.writeU32(request.pid)
+			}
+
+			is Request.ListProcesses -> {
+				// only the id needed here
+				out.writeU32(Request.ListProcesses.ID)
+			}
+
+			is Request.KillGracefully -> {
+				out.writeU32(Request.KillGracefully.ID)
+				out.writeU32(request.pid)
+				out.writeU32(request.timeout)
+			}
+
+			is Request.ResizePty -> {
+				out.writeU32(Request.ResizePty.ID)
+				out.writeU32(request.pid)
+				out.writeU16(request.rows)
+				out.writeU16(request.cols)
+			}
+
+			is Request.Stats -> {
+				// only the id needed here
+				out.writeU32(Request.Stats.ID)
+			}
+
+			is Request.Wait -> {
+				out.writeU32(Request.Wait.ID)
+				out.writeU32(request.pid)
+				out.writeOption(request.timeout) { out.writeU32(it) }
+			}
 		}
 
 		return bos.toByteArray()
@@ -324,31 +632,49 @@ class RequestEnvelope(
 					envvars = input.readArray {
 						input.readUtf8() to input.readUtf8()
 					},
-					stdin = when (val stdinTypeId = input.readU32()) {
-						Request.Exec.Stdin.Stream.ID -> Request.Exec.Stdin.Stream
-						Request.Exec.Stdin.Ignore.ID -> Request.Exec.Stdin.Ignore
-						else -> throw NoSuchElementException("unrecognized exec stdin type id: $stdinTypeId")
+					stdin = input.readStdin(),
+					stdout = input.readStdout(),
+					stderr = input.readStderr(),
+					streamFin = input.readBoolean(),
+					limits = input.readOption { input.readLimits() },
+					uid = input.readOption { input.readU32() },
+					gid = input.readOption { input.readU32() },
+					gids = input.readOption {
+						input.readArray { input.readU32() }
 					},
-					stdout = when (val stdoutTypeId = input.readU32()) {
-						Request.Exec.Stdout.Stream.ID -> Request.Exec.Stdout.Stream
-						Request.Exec.Stdout.Write.ID -> Request.Exec.Stdout.Write(
-							path = input.readUtf8()
+					persist = input.readBoolean(),
+					lines = input.readOption { input.readLines() },
+					env = input.readOption { input.readEnv() },
+					container = input.readOption { input.readContainer() }
+				)
+
+				Request.ExecPipeline.ID -> Request.ExecPipeline(
+					stages = input.readArray {
+						Request.ExecPipeline.Stage(
+							program = input.readUtf8(),
+							args = input.readArray {
+								input.readUtf8()
+							},
+							envvars = input.readArray {
+								input.readUtf8() to input.readUtf8()
+							}
 						)
-						Request.Exec.Stdout.Log.ID -> Request.Exec.Stdout.Log
-						Request.Exec.Stdout.Ignore.ID -> Request.Exec.Stdout.Ignore
-						else -> throw NoSuchElementException("unrecognized exec stdout type id: $stdoutTypeId")
 					},
-					stderr = when (val stderrTypeId = input.readU32()) {
-						Request.Exec.Stderr.Stream.ID -> Request.Exec.Stderr.Stream
-						Request.Exec.Stderr.Write.ID -> Request.Exec.Stderr.Write(
-							path = input.readUtf8()
-						)
-						Request.Exec.Stderr.Merge.ID -> Request.Exec.Stderr.Merge
-						Request.Exec.Stderr.Log.ID -> Request.Exec.Stderr.Log
-						Request.Exec.Stderr.Ignore.ID -> Request.Exec.Stderr.Ignore
-						else -> throw NoSuchElementException("unrecognized exec stderr type id: $stderrTypeId")
+					dir = input.readOption {
+						input.readUtf8()
 					},
-					streamFin = input.readBoolean()
+					stdin = input.readStdin(),
+					stdout = input.readStdout(),
+					stderr = input.readStderr(),
+					streamFin = input.readBoolean(),
+					limits = input.readOption { input.readLimits() },
+					uid = input.readOption { input.readU32() },
+					gid = input.readOption { input.readU32() },
+					gids = input.readOption {
+						input.readArray { input.readU32() }
+					},
+					lines = input.readOption { input.readLines() },
+					env = input.readOption { input.readEnv() }
 				)
 
 				Request.Status.ID -> Request.Status(
@@ -357,11 +683,13 @@ class RequestEnvelope(
 
 				Request.WriteStdin.ID -> Request.WriteStdin(
 					pid = input.readU32(),
-					chunk = input.readBytes()
+					chunk = input.readBytes(),
+					ack = input.readBoolean()
 				)
 
 				Request.CloseStdin.ID -> Request.CloseStdin(
-					pid = input.readU32()
+					pid = input.readU32(),
+					ack = input.readBoolean()
 				)
 
 				Request.Kill.ID -> Request.Kill(
@@ -393,6 +721,30 @@ class RequestEnvelope(
 					uid = input.readU32()
 				)
 
+				Request.Attach.ID -> Request.Attach(
+					pid = input.readU32()
+				)
+
+				Request.ListProcesses.ID -> Request.ListProcesses()
+
+				Request.KillGracefully.ID -> Request.KillGracefully(
+					pid = input.readU32(),
+					timeout = input.readU32()
+				)
+
+				Request.ResizePty.ID -> Request.ResizePty(
+					pid = input.readU32(),
+					rows = input.readU16(),
+					cols = input.readU16()
+				)
+
+				Request.Stats.ID -> Request.Stats()
+
+				Request.Wait.ID -> Request.Wait(
+					pid = input.readU32(),
+					timeout = input.readOption { input.readU32() }
+				)
+
 				else -> throw NoSuchElementException("unrecognized request type id: $typeId")
 			}
 
@@ -400,3 +752,259 @@ class RequestEnvelope(
 		}
 	}
 }
+
+
+internal fun DataOutputStream.writeStdin(stdin: Request.Exec.Stdin) {
+	when (stdin) {
+		Request.Exec.Stdin.Stream -> {
+			writeU32(Request.Exec.Stdin.Stream.ID)
+		}
+		Request.Exec.Stdin.Ignore -> {
+			writeU32(Request.Exec.Stdin.Ignore.ID)
+		}
+		Request.Exec.Stdin.Fd -> {
+			writeU32(Request.Exec.Stdin.Fd.ID)
+		}
+		is Request.Exec.Stdin.File -> {
+			writeU32(Request.Exec.Stdin.File.ID)
+			writeUtf8(stdin.path)
+		}
+		is Request.Exec.Stdin.Bytes -> {
+			writeU32(Request.Exec.Stdin.Bytes.ID)
+			writeBytes(stdin.bytes)
+		}
+	}
+}
+
+internal fun DataOutputStream.writeStdout(stdout: Request.Exec.Stdout) {
+	when (stdout) {
+		Request.Exec.Stdout.Stream -> {
+			writeU32(Request.Exec.Stdout.Stream.ID)
+		}
+		is Request.Exec.Stdout.Write -> {
+			writeU32(Request.Exec.Stdout.Write.ID)
+			writeUtf8(stdout.path)
+		}
+		Request.Exec.Stdout.Log -> {
+			writeU32(Request.Exec.Stdout.Log.ID)
+		}
+		Request.Exec.Stdout.Ignore -> {
+			writeU32(Request.Exec.Stdout.Ignore.ID)
+		}
+		Request.Exec.Stdout.Pty -> {
+			writeU32(Request.Exec.Stdout.Pty.ID)
+		}
+		is Request.Exec.Stdout.LogFile -> {
+			writeU32(Request.Exec.Stdout.LogFile.ID)
+			writeLogFile(stdout.logFile)
+		}
+		Request.Exec.Stdout.Fd -> {
+			writeU32(Request.Exec.Stdout.Fd.ID)
+		}
+	}
+}
+
+internal fun DataOutputStream.writeStderr(stderr: Request.Exec.Stderr) {
+	when (stderr) {
+		Request.Exec.Stderr.Stream -> {
+			writeU32(Request.Exec.Stderr.Stream.ID)
+		}
+		is Request.Exec.Stderr.Write -> {
+			writeU32(Request.Exec.Stderr.Write.ID)
+			writeUtf8(stderr.path)
+		}
+		Request.Exec.Stderr.Merge -> {
+			writeU32(Request.Exec.Stderr.Merge.ID)
+		}
+		Request.Exec.Stderr.Log -> {
+			writeU32(Request.Exec.Stderr.Log.ID)
+		}
+		Request.Exec.Stderr.Ignore -> {
+			writeU32(Request.Exec.Stderr.Ignore.ID)
+		}
+		is Request.Exec.Stderr.LogFile -> {
+			writeU32(Request.Exec.Stderr.LogFile.ID)
+			writeLogFile(stderr.logFile)
+		}
+		Request.Exec.Stderr.Fd -> {
+			writeU32(Request.Exec.Stderr.Fd.ID)
+		}
+	}
+}
+
+internal fun DataInputStream.readStdin(): Request.Exec.Stdin =
+	when (val stdinTypeId = readU32()) {
+		Request.Exec.Stdin.Stream.ID -> Request.Exec.Stdin.Stream
+		Request.Exec.Stdin.Ignore.ID -> Request.Exec.Stdin.Ignore
+		Request.Exec.Stdin.Fd.ID -> Request.Exec.Stdin.Fd
+		Request.Exec.Stdin.File.ID -> Request.Exec.Stdin.File(
+			path = readUtf8()
+		)
+		Request.Exec.Stdin.Bytes.ID -> Request.Exec.Stdin.Bytes(readBytes())
+		else -> throw NoSuchElementException("unrecognized exec stdin type id: $stdinTypeId")
+	}
+
+internal fun DataInputStream.readStdout(): Request.Exec.Stdout =
+	when (val stdoutTypeId = readU32()) {
+		Request.Exec.Stdout.Stream.ID -> Request.Exec.Stdout.Stream
+		Request.Exec.Stdout.Write.ID -> Request.Exec.Stdout.Write(
+			path = readUtf8()
+		)
+		Request.Exec.Stdout.Log.ID -> Request.Exec.Stdout.Log
+		Request.Exec.Stdout.Ignore.ID -> Request.Exec.Stdout.Ignore
+		Request.Exec.Stdout.Pty.ID -> Request.Exec.Stdout.Pty
+		Request.Exec.Stdout.LogFile.ID -> Request.Exec.Stdout.LogFile(readLogFile())
+		Request.Exec.Stdout.Fd.ID -> Request.Exec.Stdout.Fd
+		else -> throw NoSuchElementException("unrecognized exec stdout type id: $stdoutTypeId")
+	}
+
+internal fun DataInputStream.readStderr(): Request.Exec.Stderr =
+	when (val stderrTypeId = readU32()) {
+		Request.Exec.Stderr.Stream.ID -> Request.Exec.Stderr.Stream
+		Request.Exec.Stderr.Write.ID -> Request.Exec.Stderr.Write(
+			path = readUtf8()
+		)
+		Request.Exec.Stderr.Merge.ID -> Request.Exec.Stderr.Merge
+		Request.Exec.Stderr.Log.ID -> Request.Exec.Stderr.Log
+		Request.Exec.Stderr.Ignore.ID -> Request.Exec.Stderr.Ignore
+		Request.Exec.Stderr.LogFile.ID -> Request.Exec.Stderr.LogFile(readLogFile())
+		Request.Exec.Stderr.Fd.ID -> Request.Exec.Stderr.Fd
+		else -> throw NoSuchElementException("unrecognized exec stderr type id: $stderrTypeId")
+	}
+
+private fun DataOutputStream.writeEnvMode(mode: Request.Exec.EnvMode) {
+	when (mode) {
+		Request.Exec.EnvMode.Inherit -> {
+			writeU32(Request.Exec.EnvMode.Inherit.ID)
+		}
+		Request.Exec.EnvMode.Clear -> {
+			writeU32(Request.Exec.EnvMode.Clear.ID)
+		}
+		is Request.Exec.EnvMode.Allow -> {
+			writeU32(Request.Exec.EnvMode.Allow.ID)
+			writeArray(mode.names) { writeUtf8(it) }
+		}
+	}
+}
+
+private fun DataInputStream.readEnvMode(): Request.Exec.EnvMode =
+	when (val modeTypeId = readU32()) {
+		Request.Exec.EnvMode.Inherit.ID -> Request.Exec.EnvMode.Inherit
+		Request.Exec.EnvMode.Clear.ID -> Request.Exec.EnvMode.Clear
+		Request.Exec.EnvMode.Allow.ID -> Request.Exec.EnvMode.Allow(readArray { readUtf8() })
+		else -> throw NoSuchElementException("unrecognized env mode type id: $modeTypeId")
+	}
+
+private fun DataOutputStream.writeLimits(limits: Request.Exec.Limits) {
+	writeOption(limits.addressSpace) { writeU64(it) }
+	writeOption(limits.cpuTime) { writeU64(it) }
+	writeOption(limits.openFiles) { writeU64(it) }
+	writeOption(limits.processes) { writeU64(it) }
+	writeOption(limits.cgroup) { cgroup ->
+		writeOption(cgroup.memoryMax) { writeU64(it) }
+		writeOption(cgroup.cpuMax) { cpuMax ->
+			writeU64(cpuMax.quota)
+			writeU64(cpuMax.period)
+		}
+	}
+	writeOption(limits.wallTime) { writeU64(it) }
+	writeOption(limits.idleTime) { writeU64(it) }
+}
+
+private fun DataInputStream.readLimits(): Request.Exec.Limits =
+	Request.Exec.Limits(
+		addressSpace = readOption { readU64() },
+		cpuTime = readOption { readU64() },
+		openFiles = readOption { readU64() },
+		processes = readOption { readU64() },
+		cgroup = readOption {
+			Request.Exec.Cgroup(
+				memoryMax = readOption { readU64() },
+				cpuMax = readOption {
+					Request.Exec.CpuMax(
+						quota = readU64(),
+						period = readU64()
+					)
+				}
+			)
+		},
+		wallTime = readOption { readU64() },
+		idleTime = readOption { readU64() }
+	)
+
+private fun DataOutputStream.writeLines(lines: Request.Exec.Lines) {
+	writeU32(lines.maxLineLength)
+	writeU32(lines.maxPartial)
+}
+
+private fun DataInputStream.readLines(): Request.Exec.Lines =
+	Request.Exec.Lines(
+		maxLineLength = readU32(),
+		maxPartial = readU32()
+	)
+
+private fun DataOutputStream.writeEnv(env: Request.Exec.Env) {
+	writeEnvMode(env.mode)
+	writeArray(env.unset) { writeUtf8(it) }
+}
+
+private fun DataInputStream.readEnv(): Request.Exec.Env =
+	Request.Exec.Env(
+		mode = readEnvMode(),
+		unset = readArray { readUtf8() }
+	)
+
+private fun DataOutputStream.writeContainer(container: Request.Exec.Container) {
+	writeUtf8(container.image)
+	writeArray(container.binds) { bind ->
+		writeUtf8(bind.src)
+		writeOption(bind.dst) { writeUtf8(it) }
+		writeBoolean(bind.readOnly)
+	}
+	writeArray(container.envvars) { (k, v) ->
+		writeUtf8(k)
+		writeUtf8(v)
+	}
+	writeArray(container.flags) { writeU32(it.id) }
+	writeOption(container.workdir) { writeUtf8(it) }
+}
+
+private fun DataInputStream.readContainer(): Request.Exec.Container =
+	Request.Exec.Container(
+		image = readUtf8(),
+		binds = readArray {
+			Request.Exec.Container.Bind(
+				src = readUtf8(),
+				dst = readOption { readUtf8() },
+				readOnly = readBoolean()
+			)
+		},
+		envvars = readArray { readUtf8() to readUtf8() },
+		flags = readArray { Request.Exec.Container.Flag[readU32()] },
+		workdir = readOption { readUtf8() }
+	)
+
+private fun DataOutputStream.writeLogFile(logFile: Request.Exec.LogFile) {
+	writeUtf8(logFile.path)
+	writeBoolean(logFile.append)
+	writeOption(logFile.rotation) { rotation ->
+		writeU64(rotation.maxSize)
+		writeU32(rotation.generations)
+		writeBoolean(rotation.gzip)
+	}
+	writeBoolean(logFile.timestamps)
+}
+
+private fun DataInputStream.readLogFile() =
+	Request.Exec.LogFile(
+		path = readUtf8(),
+		append = readBoolean(),
+		rotation = readOption {
+			Request.Exec.LogRotation(
+				maxSize = readU64(),
+				generations = readU32(),
+				gzip = readBoolean()
+			)
+		},
+		timestamps = readBoolean()
+	)
@@ -53,12 +53,65 @@ sealed interface Response {
 			}
 		}
 
-		class Fin(val exitCode: Int?) : Event {
+		class Fin(
+			/** for pipelines, the exit code of the last stage */
+			val exitCode: Int?,
+			val limit: LimitKind? = null,
+			/** for pipelines, how each stage exited, in order, or empty for Exec */
+			val stages: List<Status.Exit?> = emptyList()
+		) : Event {
 			companion object {
 				const val ID: UInt = 2u
 			}
 		}
 
+		class ConsoleLine(
+			val kind: ConsoleKind,
+			/** in microseconds since the process started, from a monotonic clock */
+			val timestamp: ULong,
+			/** without the line ending */
+			val line: ByteArray,
+			/** the line continues in the next ConsoleLine of the same kind, or the stream ended before the line did */
+			val partial: Boolean,
+			/** part of the line was dropped, because the line was longer than the max line length */
+			val truncated: Boolean
+		) : Event {
+			companion object {
+				const val ID: UInt = 3u
+			}
+
+			/** the line as text, with a newline at the end, unless the line is partial */
+			val text: String get() =
+				line.toString(Charsets.UTF_8) + if (partial) "" else "\n"
+		}
+
+		/** console output was dropped here, because the client wasn't keeping up */
+		class Dropped(val kind: ConsoleKind, val bytes: ULong) : Event {
+			companion object {
+				const val ID: UInt = 4u
+			}
+		}
+
+		/** the host processor shut down while the process was still running, so there won't be a Fin */
+		object Detached : Event {
+			const val ID: UInt = 5u
+		}
+
+		enum class LimitKind(val id: UInt) {
+
+			CpuTime(1u),
+			Memory(2u),
+			WallTime(3u),
+			IdleTime(4u);
+
+			companion object {
+
+				operator fun get(id: UInt): LimitKind =
+					values().find { it.id == id }
+						?: throw NoSuchElementException("unrecognized limit kind: $id")
+			}
+		}
+
 		enum class ConsoleKind(val id: UInt) {
 
 			Stdout(1u),
@@ -76,10 +129,68 @@ sealed interface Response {
 		}
 	}
 
-	class Status(val isRunning: Boolean) : Response {
+	class Status(val status: ProcessStatus) : Response {
 		companion object {
 			const val ID: UInt = 5u
 		}
+
+		val isRunning: Boolean get() =
+			status is Running
+
+		sealed interface ProcessStatus
+
+		/** the process wasn't launched by Exec, or it finished longer ago than the retention window */
+		object Unknown : ProcessStatus {
+			const val ID: UInt = 1u
+		}
+
+		class Running(
+			/** in milliseconds since the epoch */
+			val startTime: ULong,
+			/** in milliseconds */
+			val wallTime: ULong
+		) : ProcessStatus {
+			companion object {
+				const val ID: UInt = 2u
+			}
+		}
+
+		class Exited(
+			/** in milliseconds since the epoch */
+			val startTime: ULong,
+			/** in milliseconds */
+			val wallTime: ULong,
+			val exit: Exit?,
+			val usage: Usage?,
+			val limit: ProcessEvent.LimitKind?
+		) : ProcessStatus {
+			companion object {
+				const val ID: UInt = 3u
+			}
+		}
+
+		sealed interface Exit
+
+		data class Code(val code: Int) : Exit {
+			companion object {
+				const val ID: UInt = 1u
+			}
+		}
+
+		data class Signal(val signal: Int) : Exit {
+			companion object {
+				const val ID: UInt = 2u
+			}
+		}
+
+		data class Usage(
+			/** in microseconds */
+			val userTime: ULong,
+			/** in microseconds */
+			val systemTime: ULong,
+			/** in KiB */
+			val maxRss: ULong
+		)
 	}
 
 	class Username(val username: String?): Response {
@@ -111,6 +222,192 @@ sealed interface Response {
 			const val ID: UInt = 10u
 		}
 	}
+
+	class Attach(val response: Response) : Response {
+		companion object {
+			const val ID: UInt = 11u
+		}
+
+		sealed interface Response
+
+		object Success : Response {
+			const val ID: UInt = 1u
+		}
+
+		class Failure(val reason: String) : Response {
+			companion object {
+				const val ID: UInt = 2u
+			}
+		}
+	}
+
+	class ListProcesses(val processes: List<ProcessInfo>) : Response {
+		companion object {
+			const val ID: UInt = 12u
+		}
+
+		data class ProcessInfo(
+			val pid: UInt,
+			val program: String,
+			val args: List<String>,
+			val dir: String,
+			/** in milliseconds since the epoch */
+			val startTime: ULong,
+			val running: Boolean,
+			/** null if unknown */
+			val stdio: Stdio?,
+			/** the id of the connection that launched the process, or null if unknown */
+			val connection: UInt?
+		)
+
+		data class Stdio(
+			val stdin: Request.Exec.Stdin,
+			val stdout: Request.Exec.Stdout,
+			val stderr: Request.Exec.Stderr
+		)
+	}
+
+	class Kill(val response: Response) : Response {
+		companion object {
+			const val ID: UInt = 13u
+		}
+
+		sealed interface Response
+
+		object Success : Response {
+			const val ID: UInt = 1u
+		}
+
+		class Failure(val failure: KillFailure) : Response {
+			companion object {
+				const val ID: UInt = 2u
+			}
+		}
+	}
+
+	class Stats(
+		/** in milliseconds */
+		val uptime: ULong,
+		val version: String,
+		/** connections that are open right now */
+		val connections: UInt,
+		/** processes launched by Exec that are still running */
+		val liveProcesses: UInt,
+		/** processes launched by Exec */
+		val totalProcesses: ULong,
+		/** console output read from processes to stream to clients, in bytes */
+		val bytesStreamed: ULong,
+		/** requests that couldn't be decoded */
+		val decodeErrors: ULong,
+		/** Exec requests that didn't launch a process */
+		val execFailures: ULong
+	) : Response {
+		companion object {
+			const val ID: UInt = 15u
+		}
+	}
+
+	class Wait(val response: Response) : Response {
+		companion object {
+			const val ID: UInt = 16u
+		}
+
+		sealed interface Response
+
+		/** the process exited, maybe before the request */
+		class Exited(val exit: Status.Exit?) : Response {
+			companion object {
+				const val ID: UInt = 1u
+			}
+		}
+
+		/** the process was still running after the timeout */
+		object Timeout : Response {
+			const val ID: UInt = 2u
+		}
+
+		/** the process wasn't launched by Exec, or it finished longer ago than the retention window */
+		object Unknown : Response {
+			const val ID: UInt = 3u
+		}
+	}
+
+	/** the answer to a WriteStdin or CloseStdin request that asked for an ack */
+	class Stdin(val response: Response) : Response {
+		companion object {
+			const val ID: UInt = 17u
+		}
+
+		sealed interface Response
+
+		/**
+		 * the whole chunk was written to stdin,
+		 * and the connection can send [credit] more bytes of acked writes before it has to wait for more acks
+		 */
+		class Written(val bytes: UInt, val credit: UInt) : Response {
+			companion object {
+				const val ID: UInt = 1u
+			}
+		}
+
+		object Closed : Response {
+			const val ID: UInt = 2u
+		}
+
+		class Failure(val reason: String) : Response {
+			companion object {
+				const val ID: UInt = 3u
+			}
+		}
+	}
+
+	class KillGracefully(val response: Response) : Response {
+		companion object {
+			const val ID: UInt = 14u
+		}
+
+		sealed interface Response
+
+		/** the process exited after SIGTERM */
+		object Terminated : Response {
+			const val ID: UInt = 1u
+		}
+
+		/** the process didn't exit after SIGTERM before the timeout, so its process group got SIGKILL */
+		object Killed : Response {
+			const val ID: UInt = 2u
+		}
+
+		class Failure(val failure: KillFailure) : Response {
+			companion object {
+				const val ID: UInt = 3u
+			}
+		}
+	}
+
+	data class KillFailure(
+		/** the error number from kill(2), if the failure came from there, eg ESRCH or EPERM */
+		val errno: Int?,
+		val reason: String
+	) {
+
+		fun encode(out: DataOutputStream) {
+			out.writeOption(errno) {
+				out.writeInt(it)
+			}
+			out.writeUtf8(reason)
+		}
+
+		companion object {
+
+			fun decode(input: DataInputStream) = KillFailure(
+				errno = input.readOption {
+					input.readInt()
+				},
+				reason = input.readUtf8()
+			)
+		}
+	}
 }
 
 
@@ -165,13 +462,58 @@ class ResponseEnvelope(
 						out.writeOption(event.exitCode) {
 							out.writeInt(it)
 						}
+						out.writeOption(event.limit) {
+							out.writeU32(it.id)
+						}
+						out.writeArray(event.stages) { stage ->
+							out.writeOption(stage) { out.writeExit(it) }
+						}
+					}
+					is Response.ProcessEvent.ConsoleLine -> {
+						out.writeU32(Response.ProcessEvent.ConsoleLine.ID)
+						out.writeU32(event.kind.id)
+						out.writeU64(event.timestamp)
+						out.writeBytes(event.line)
+						out.writeBoolean(event.partial)
+						out.writeBoolean(event.truncated)
+					}
+					is Response.ProcessEvent.Dropped -> {
+						out.writeU32(Response.ProcessEvent.Dropped.ID)
+						out.writeU32(event.kind.id)
+						out.writeU64(event.bytes)
+					}
+					is Response.ProcessEvent.Detached -> {
+						out.writeU32(Response.ProcessEvent.Detached.ID)
 					}
 				}
 			}
 
 			is Response.Status -> {
 				out.writeU32(Response.Status.ID)
-				out.writeBoolean(response.isRunning)
+				when (val status = response.status) {
+					Response.Status.Unknown -> {
+						out.writeU32(Response.Status.Unknown.ID)
+					}
+					is Response.Status.Running -> {
+						out.writeU32(Response.Status.Running.ID)
+						out.writeU64(status.startTime)
+						out.writeU64(status.wallTime)
+					}
+					is Response.Status.Exited -> {
+						out.writeU32(Response.Status.Exited.ID)
+						out.writeU64(status.startTime)
+						out.writeU64(status.wallTime)
+						out.writeOption(status.exit) { out.writeExit(it) }
+						out.writeOption(status.usage) { usage ->
+							out.writeU64(usage.userTime)
+							out.writeU64(usage.systemTime)
+							out.writeU64(usage.maxRss)
+						}
+						out.writeOption(status.limit) {
+							out.writeU32(it.id)
+						}
+					}
+				}
 			}
 
 			is Response.Username -> {
@@ -210,6 +552,116 @@ class ResponseEnvelope(
 					}
 				}
 			}
+
+			is Response.Attach -> {
+				out.writeU32(Response.Attach.ID)
+				when (val response = response.response) {
+					Response.Attach.Success -> {
+						out.writeU32(Response.Attach.Success.ID)
+					}
+					is Response.Attach.Failure -> {
+						out.writeU32(Response.Attach.Failure.ID)
+						out.writeUtf8(response.reason)
+					}
+				}
+			}
+
+			is Response.ListProcesses -> {
+				out.writeU32(Response.ListProcesses.ID)
+				out.writeArray(response.processes) { proc ->
+					out.writeU32(proc.pid)
+					out.writeUtf8(proc.program)
+					out.writeArray(proc.args) {
+						out.writeUtf8(it)
+					}
+					out.writeUtf8(proc.dir)
+					out.writeU64(proc.startTime)
+					out.writeBoolean(proc.running)
+					out.writeOption(proc.stdio) { stdio ->
+						out.writeStdin(stdio.stdin)
+						out.writeStdout(stdio.stdout)
+						out.writeStderr(stdio.stderr)
+					}
+					out.writeOption(proc.connection) {
+						out.writeU32(it)
+					}
+				}
+			}
+
+			is Response.Kill -> {
+				out.writeU32(Response.Kill.ID)
+				when (val response = response.response) {
+					Response.Kill.Success -> {
+						out.writeU32(Response.Kill.Success.ID)
+					}
+					is Response.Kill.Failure -> {
+						out.writeU32(Response.Kill.Failure.ID)
+						response.failure.encode(out)
+					}
+				}
+			}
+
+			is Response.KillGracefully -> {
+				out.writeU32(Response.KillGracefully.ID)
+				when (val response = response.response) {
+					Response.KillGracefully.Terminated -> {
+						out.writeU32(Response.KillGracefully.Terminated.ID)
+					}
+					Response.KillGracefully.Killed -> {
+						out.writeU32(Response.KillGracefully.Killed.ID)
+					}
+					is Response.KillGracefully.Failure -> {
+						out.writeU32(Response.KillGracefully.Failure.ID)
+						response.failure.encode(out)
+					}
+				}
+			}
+
+			is Response.Stats -> {
+				out.writeU32(Response.Stats.ID)
+				out.writeU64(response.uptime)
+				out.writeUtf8(response.version)
+				out.writeU32(response.connections)
+				out.writeU32(response.liveProcesses)
+				out.writeU64(response.totalProcesses)
+				out.writeU64(response.bytesStreamed)
+				out.writeU64(response.decodeErrors)
+				out.writeU64(response.execFailures)
+			}
+
+			is Response.Wait -> {
+				out.writeU32(Response.Wait.ID)
+				when (val response = response.response) {
+					is Response.Wait.Exited -> {
+						out.writeU32(Response.Wait.Exited.ID)
+						out.writeOption(response.exit) { out.writeExit(it) }
+					}
+					Response.Wait.Timeout -> {
+						out.writeU32(Response.Wait.Timeout.ID)
+					}
+					Response.Wait.Unknown -> {
+						out.writeU32(Response.Wait.Unknown.ID)
+					}
+				}
+			}
+
+			is Response.Stdin -> {
+				out.writeU32(Response.Stdin.ID)
+				when (val response = response.response) {
+					is Response.Stdin.Written -> {
+						out.writeU32(Response.Stdin.Written.ID)
+						out.writeU32(response.bytes)
+						out.writeU32(response.credit)
+					}
+					Response.Stdin.Closed -> {
+						out.writeU32(Response.Stdin.Closed.ID)
+					}
+					is Response.Stdin.Failure -> {
+						out.writeU32(Response.Stdin.Failure.ID)
+						out.writeUtf8(response.reason)
+					}
+				}
+			}
 		}
 
 		return bos.toByteArray()
@@ -250,14 +702,52 @@ class ResponseEnvelope(
 					Response.ProcessEvent.Fin.ID -> Response.ProcessEvent.Fin(
 						exitCode = input.readOption {
 							input.readInt()
+						},
+						limit = input.readOption {
+							Response.ProcessEvent.LimitKind[input.readU32()]
+						},
+						stages = input.readArray {
+							input.readOption { input.readExit() }
 						}
 					)
+					Response.ProcessEvent.ConsoleLine.ID -> Response.ProcessEvent.ConsoleLine(
+						kind = Response.ProcessEvent.ConsoleKind[input.readU32()],
+						timestamp = input.readU64(),
+						line = input.readBytes(),
+						partial = input.readBoolean(),
+						truncated = input.readBoolean()
+					)
+					Response.ProcessEvent.Dropped.ID -> Response.ProcessEvent.Dropped(
+						kind = Response.ProcessEvent.ConsoleKind[input.readU32()],
+						bytes = input.readU64()
+					)
+					Response.ProcessEvent.Detached.ID -> Response.ProcessEvent.Detached
 					else -> throw NoSuchElementException("unrecognized response process event type: $eventTypeId")
 				})
 
-				Response.Status.ID -> Response.Status(
-					isRunning = input.readBoolean()
-				)
+				Response.Status.ID -> Response.Status(when (val statusTypeId = input.readU32()) {
+					Response.Status.Unknown.ID -> Response.Status.Unknown
+					Response.Status.Running.ID -> Response.Status.Running(
+						startTime = input.readU64(),
+						wallTime = input.readU64()
+					)
+					Response.Status.Exited.ID -> Response.Status.Exited(
+						startTime = input.readU64(),
+						wallTime = input.readU64(),
+						exit = input.readOption { input.readExit() },
+						usage = input.readOption {
+							Response.Status.Usage(
+								userTime = input.readU64(),
+								systemTime = input.readU64(),
+								maxRss = input.readU64()
+							)
+						},
+						limit = input.readOption {
+							Response.ProcessEvent.LimitKind[input.readU32()]
+						}
+					)
+					else -> throw NoSuchElementException("unrecognized response status type: $statusTypeId")
+				})
 
 				Response.Username.ID -> Response.Username(
 					username = input.readOption {
@@ -290,6 +780,87 @@ class ResponseEnvelope(
 					}
 				)
 
+				Response.Attach.ID -> Response.Attach(when (val attachTypeId = input.readU32()) {
+					Response.Attach.Success.ID -> Response.Attach.Success
+					Response.Attach.Failure.ID -> Response.Attach.Failure(
+						reason = input.readUtf8()
+					)
+					else -> throw NoSuchElementException("unrecognized response attach type: $attachTypeId")
+				})
+
+				Response.ListProcesses.ID -> Response.ListProcesses(
+					processes = input.readArray {
+						Response.ListProcesses.ProcessInfo(
+							pid = input.readU32(),
+							program = input.readUtf8(),
+							args = input.readArray {
+								input.readUtf8()
+							},
+							dir = input.readUtf8(),
+							startTime = input.readU64(),
+							running = input.readBoolean(),
+							stdio = input.readOption {
+								Response.ListProcesses.Stdio(
+									stdin = input.readStdin(),
+									stdout = input.readStdout(),
+									stderr = input.readStderr()
+								)
+							},
+							connection = input.readOption {
+								input.readU32()
+							}
+						)
+					}
+				)
+
+				Response.Kill.ID -> Response.Kill(when (val killTypeId = input.readU32()) {
+					Response.Kill.Success.ID -> Response.Kill.Success
+					Response.Kill.Failure.ID -> Response.Kill.Failure(
+						failure = Response.KillFailure.decode(input)
+					)
+					else -> throw NoSuchElementException("unrecognized response kill type: $killTypeId")
+				})
+
+				Response.KillGracefully.ID -> Response.KillGracefully(when (val killTypeId = input.readU32()) {
+					Response.KillGracefully.Terminated.ID -> Response.KillGracefully.Terminated
+					Response.KillGracefully.Killed.ID -> Response.KillGracefully.Killed
+					Response.KillGracefully.Failure.ID -> Response.KillGracefully.Failure(
+						failure = Response.KillFailure.decode(input)
+					)
+					else -> throw NoSuchElementException("unrecognized response kill gracefully type: $killTypeId")
+				})
+
+				Response.Stats.ID -> Response.Stats(
+					uptime = input.readU64(),
+					version = input.readUtf8(),
+					connections = input.readU32(),
+					liveProcesses = input.readU32(),
+					totalProcesses = input.readU64(),
+					bytesStreamed = input.readU64(),
+					decodeErrors = input.readU64(),
+					execFailures = input.readU64()
+				)
+
+				Response.Wait.ID -> Response.Wait(when (val waitTypeId = input.readU32()) {
+					Response.Wait.Exited.ID -> Response.Wait.Exited(
+						exit = input.readOption { input.readExit() }
+					)
+					Response.Wait.Timeout.ID -> Response.Wait.Timeout
+					Response.Wait.Unknown.ID -> Response.Wait.Unknown
+					else -> throw NoSuchElementException("unrecognized response wait type: $waitTypeId")
+				})
+
+				Response.Stdin.ID -> Response.Stdin(when (val stdinTypeId = input.readU32()) {
+					Response.Stdin.Written.ID -> Response.Stdin.Written(
+						bytes = input.readU32(),
+						credit = input.readU32()
+					)
+					Response.Stdin.Closed.ID -> Response.Stdin.Closed
+					Response.Stdin.Failure.ID -> Response.Stdin.Failure(
+						reason = input.readUtf8()
+					)
+					else -> throw NoSuchElementException("unrecognized response stdin type: $stdinTypeId")
+				})
 
 				else -> throw NoSuchElementException("unrecognized response type: $responseTypeId")
 			}
@@ -298,3 +869,24 @@ class ResponseEnvelope(
 		}
 	}
 }
+
+
+private fun DataOutputStream.writeExit(exit: Response.Status.Exit) {
+	when (exit) {
+		is Response.Status.Code -> {
+			writeU32(Response.Status.Code.ID)
+			writeInt(exit.code)
+		}
+		is Response.Status.Signal -> {
+			writeU32(Response.Status.Signal.ID)
+			writeInt(exit.signal)
+		}
+	}
+}
+
+private fun DataInputStream.readExit(): Response.Status.Exit =
+	when (val exitTypeId = readU32()) {
+		Response.Status.Code.ID -> Response.Status.Code(readInt())
+		Response.Status.Signal.ID -> Response.Status.Signal(readInt())
+		else -> throw NoSuchElementException("unrecognized response status exit type: $exitTypeId")
+	}
@@ -185,7 +185,11 @@ class UserProcessor(
 								HostProcessorResponse.ProcessEvent.ConsoleKind.Stdout -> pipe(event.chunk, System.out)
 								HostProcessorResponse.ProcessEvent.ConsoleKind.Stderr -> pipe(event.chunk, System.err)
 							}
+							// NOTE: lines weren't requested
+							is HostProcessorResponse.ProcessEvent.ConsoleLine -> Unit
+							is HostProcessorResponse.ProcessEvent.Dropped -> Unit
 							is HostProcessorResponse.ProcessEvent.Fin -> break
+							is HostProcessorResponse.ProcessEvent.Detached -> break
 						}
 					}
 				}
@@ -0,0 +1,325 @@
+use std::cell::Cell;
+use std::io;
+use std::rc::Rc;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tokio::net::unix::UCred;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::logfile::LogFileWriter;
+use crate::proto::{AttachResponse, ExecResponse, KillGracefullyResponse, KillResponse, LimitKind, LogFile, Request, Response};
+
+
+/// An append-only log of every request, with one JSON object per line
+#[derive(Clone)]
+pub struct AuditLog {
+	writer: Rc<Mutex<LogFileWriter>>
+}
+
+impl AuditLog {
+
+	pub async fn open(log_file: &LogFile) -> io::Result<Self> {
+		let writer = LogFileWriter::open(log_file)
+			.await?;
+		Ok(Self {
+			writer: Rc::new(Mutex::new(writer))
+		})
+	}
+
+	async fn write(&self, entry: &Entry<'_>) {
+
+		let mut line = match serde_json::to_vec(entry) {
+			Ok(line) => line,
+			Err(e) => {
+				warn!("Failed to encode audit log entry: {}", e);
+				return;
+			}
+		};
+		line.push(b'\n');
+
+		let result = self.writer.lock()
+			.await
+			.write(&line)
+			.await;
+		if let Err(e) = result {
+			warn!("Failed to write audit log entry: {}", e);
+		}
+	}
+}
+
+
+/// The audit log entries for one request.
+/// Does nothing if there's no audit log.
+pub struct AuditRecord {
+	log: Option<AuditLog>,
+	connection: u32,
+	peer: Peer,
+	request_id: u32,
+	request: &'static str,
+	fields: Fields,
+	written: Cell<bool>
+}
+
+impl AuditRecord {
+
+	pub fn new(log: Option<AuditLog>, connection: u32, peer: &UCred, request_id: u32, request: &Request) -> Self {
+		Self {
+			log,
+			connection,
+			peer: Peer::from(peer),
+			request_id,
+			request: request.name(),
+			fields: Fields::from(request),
+			written: Cell::new(false)
+		}
+	}
+
+	/// For requests that couldn't be decoded
+	pub fn invalid(log: Option<AuditLog>, connection: u32, peer: &UCred, request_id: u32) -> Self {
+		Self {
+			log,
+			connection,
+			peer: Peer::from(peer),
+			request_id,
+			request: "Invalid",
+			fields: Fields::default(),
+			written: Cell::new(false)
+		}
+	}
+
+	/// Writes an entry with the outcome of the request.
+	/// Requests that run a process can have more than one outcome, eg when it starts and when it exits.
+	pub async fn outcome(&self, outcome: Outcome) {
+
+		self.written.set(true);
+
+		let Some(log) = &self.log
+			else { return; };
+
+		log.write(&Entry {
+			time: OffsetDateTime::now_utc()
+				.format(&Rfc3339)
+				.unwrap_or_default(),
+			connection: self.connection,
+			peer: self.peer,
+			request_id: self.request_id,
+			request: self.request,
+			fields: &self.fields,
+			outcome
+		}).await;
+	}
+
+	/// Writes the outcome of the response
+	pub async fn response(&self, response: &Response) {
+		self.outcome(Outcome::from(response))
+			.await;
+	}
+
+	/// Writes an entry for the request, if no outcome was written yet
+	pub async fn finish(self) {
+		if !self.written.get() {
+			self.outcome(Outcome::Done)
+				.await;
+		}
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, Serialize)]
+struct Peer {
+	uid: u32,
+	gid: u32,
+	pid: Option<i32>
+}
+
+impl From<&UCred> for Peer {
+	fn from(peer: &UCred) -> Self {
+		Self {
+			uid: peer.uid(),
+			gid: peer.gid(),
+			pid: peer.pid()
+		}
+	}
+}
+
+
+/// The interesting fields of a request.
+/// NOTE: environment variables and stdin chunks can have secrets in them, so don't log them
+#[derive(Debug, Default, Serialize)]
+struct Fields {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	program: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	args: Option<Vec<String>>,
+	/// for pipelines, instead of program and args
+	#[serde(skip_serializing_if = "Option::is_none")]
+	stages: Option<Vec<StageFields>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	dir: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	uid: Option<u32>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	gid: Option<u32>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pid: Option<u32>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	signal: Option<&'static str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	process_group: Option<bool>
+}
+
+#[derive(Debug, Serialize)]
+struct StageFields {
+	program: String,
+	args: Vec<String>
+}
+
+impl From<&Request> for Fields {
+	fn from(request: &Request) -> Self {
+		match request {
+
+			Request::Exec(exec) => Self {
+				program: Some(exec.program.clone()),
+				args: Some(exec.args.clone()),
+				dir: exec.dir.clone(),
+				uid: exec.uid,
+				gid: exec.gid,
+				.. Self::default()
+			},
+
+			Request::ExecPipeline(pipeline) => Self {
+				stages: Some(pipeline.stages.iter()
+					.map(|stage| StageFields {
+						program: stage.program.clone(),
+						args: stage.args.clone()
+					})
+					.collect()
+				),
+				dir: pipeline.dir.clone(),
+				uid: pipeline.uid,
+				gid: pipeline.gid,
+				.. Self::default()
+			},
+
+			Request::Kill { signal, pid, process_group } => Self {
+				pid: Some(*pid),
+				signal: Some(signal.name()),
+				process_group: Some(*process_group),
+				.. Self::default()
+			},
+
+			Request::KillGracefully { pid, .. } => Self {
+				pid: Some(*pid),
+				signal: Some("SIGTERM"),
+				process_group: Some(true),
+				.. Self::default()
+			},
+
+			Request::Status { pid }
+			| Request::Wait { pid, .. }
+			| Request::WriteStdin { pid, .. }
+			| Request::CloseStdin { pid, .. }
+			| Request::Attach { pid }
+			| Request::ResizePty { pid, .. } => Self {
+				pid: Some(*pid),
+				.. Self::default()
+			},
+
+			Request::Username { uid }
+			| Request::Gids { uid } => Self {
+				uid: Some(*uid),
+				.. Self::default()
+			},
+
+			Request::Groupname { gid } => Self {
+				gid: Some(*gid),
+				.. Self::default()
+			},
+
+			Request::Ping
+			| Request::Uid { .. }
+			| Request::Gid { .. }
+			| Request::ListProcesses
+			| Request::Stats => Self::default()
+		}
+	}
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Outcome {
+
+	/// the request was handled, and there's nothing more to say about it
+	Done,
+
+	/// the request started a process
+	Started {
+		pid: u32
+	},
+
+	/// the process started by the request exited
+	Exited {
+		pid: u32,
+		exit_code: Option<i32>,
+		limit: Option<&'static str>
+	},
+
+	/// the host processor shut down while the process started by the request was still running
+	Detached {
+		pid: u32
+	},
+
+	/// the process exited after SIGTERM
+	Terminated,
+
+	/// the process group got SIGKILL
+	Killed,
+
+	Error {
+		reason: String
+	}
+}
+
+impl Outcome {
+
+	pub fn exited(pid: u32, exit_code: Option<i32>, limit: Option<LimitKind>) -> Self {
+		Self::Exited {
+			pid,
+			exit_code,
+			limit: limit.map(|limit| limit.name())
+		}
+	}
+}
+
+impl From<&Response> for Outcome {
+	fn from(response: &Response) -> Self {
+		match response {
+			Response::Exec(ExecResponse::Success { pid }) => Self::Started { pid: *pid },
+			Response::Exec(ExecResponse::Failure { reason }) => Self::Error { reason: reason.clone() },
+			Response::Attach(AttachResponse::Failure { reason }) => Self::Error { reason: reason.clone() },
+			Response::Kill(KillResponse::Failure(failure)) => Self::Error { reason: failure.reason.clone() },
+			Response::KillGracefully(KillGracefullyResponse::Terminated) => Self::Terminated,
+			Response::KillGracefully(KillGracefullyResponse::Killed) => Self::Killed,
+			Response::KillGracefully(KillGracefullyResponse::Failure(failure)) => Self::Error { reason: failure.reason.clone() },
+			Response::Error { reason } => Self::Error { reason: reason.clone() },
+			_ => Self::Done
+		}
+	}
+}
+
+
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+	time: String,
+	connection: u32,
+	peer: Peer,
+	request_id: u32,
+	request: &'static str,
+	#[serde(flatten)]
+	fields: &'a Fields,
+	outcome: Outcome
+}
@@ -0,0 +1,355 @@
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use serde::de::Error;
+
+use crate::container::ContainerPolicy;
+use crate::outbox::OverflowPolicy;
+use crate::peers::PeerPolicy;
+use crate::policy::{ProgramPolicy, ProgramRule};
+use crate::proto::{ExecLimits, LogFile, LogRotation};
+use crate::shutdown::ShutdownPolicy;
+
+
+/// settings for the host processor daemon
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+
+	/// the unix socket to listen on
+	pub socket_path: PathBuf,
+
+	/// file permissions for the socket, or None to keep the permissions from the umask
+	pub socket_mode: Option<u32>,
+
+	/// a cgroup (v2) folder in which to create child cgroups for processes launched with Exec,
+	/// needed only to enforce cgroup limits
+	pub cgroup: Option<PathBuf>,
+
+	/// uids that Exec requests may run processes as, besides this process' own uid
+	pub allowed_uids: Vec<u32>,
+
+	/// gids that Exec requests may run processes as, besides this process' own gid
+	pub allowed_gids: Vec<u32>,
+
+	/// uids that may connect to the socket, or empty to allow anyone with access to the socket file
+	pub allowed_peer_uids: Vec<u32>,
+
+	/// gids that may connect to the socket, or empty to allow anyone with access to the socket file
+	pub allowed_peer_gids: Vec<u32>,
+
+	/// what to do with connections from peers that aren't allowed
+	pub peer_policy: PeerPolicy,
+
+	/// where to write the audit log of every request, if anywhere
+	pub audit_log: Option<LogFile>,
+
+	/// programs (and arguments) that Exec requests may run, or empty to allow any program
+	pub programs: ProgramPolicy,
+
+	/// the working directory for Exec requests that don't ask for one,
+	/// or None to use this process' working directory
+	pub default_dir: Option<PathBuf>,
+
+	/// which environment variables processes launched with Exec inherit from this process
+	pub env: EnvScrub,
+
+	/// limits for Exec requests that don't ask for them
+	pub default_limits: ExecLimits,
+
+	/// how to run Exec requests inside containers
+	pub container: ContainerPolicy,
+
+	/// file to keep track of persistent processes, so they can be recovered after a restart
+	pub state_file: Option<PathBuf>,
+	/// how long to keep finished processes around, so clients can still query their status
+	pub retention: Duration,
+
+	/// max bytes of console output to hold in memory for each process streaming to a connection
+	pub output_budget: usize,
+
+	/// what to do with console output over the budget
+	pub overflow: OverflowPolicy,
+
+	/// max bytes of acked stdin writes each connection can have waiting to be written
+	pub stdin_window: usize,
+
+	/// how long to wait for a process group to exit after a timeout, before killing it harder
+	pub timeout_grace: Duration,
+
+	/// what to do with running processes when shutting down
+	pub shutdown_policy: ShutdownPolicy,
+
+	/// when shutting down, how long to wait for process groups to exit after each signal,
+	/// and for connections to receive their last events
+	pub shutdown_deadline: Duration,
+
+	/// file to write the running processes to when detaching from them, in the same format as the state file
+	pub handoff_file: Option<PathBuf>,
+
+	/// file to write the stats to periodically, in the Prometheus text format, if anywhere
+	pub metrics_file: Option<PathBuf>,
+
+	/// how often to write the metrics file
+	pub metrics_interval: Duration
+}
+
+impl Config {
+
+	pub const DEFAULT_RETENTION: Duration = Duration::from_secs(60);
+	pub const DEFAULT_OUTPUT_BUDGET: usize = 1024*1024;
+	pub const DEFAULT_STDIN_WINDOW: usize = 1024*1024;
+	pub const DEFAULT_TIMEOUT_GRACE: Duration = Duration::from_secs(5);
+	pub const DEFAULT_AUDIT_MAX_SIZE: u64 = 64*1024*1024;
+	pub const DEFAULT_AUDIT_GENERATIONS: u32 = 10;
+	pub const DEFAULT_SHUTDOWN_DEADLINE: Duration = Duration::from_secs(10);
+	pub const DEFAULT_METRICS_INTERVAL: Duration = Duration::from_secs(15);
+	pub const DEFAULT_APPTAINER: &'static str = "apptainer";
+
+	/// Fills in the defaults for anything the config file doesn't have
+	pub fn from_file(file: ConfigFile) -> Self {
+		Self {
+			// NOTE: use a relative path instead of an absolute path, since limits on socket paths
+			//       in Linux are *FAR* less than limits on general paths (108 vs 255 bytes)
+			//       see: https://man7.org/linux/man-pages/man7/unix.7.html
+			socket_path: file.socket.path
+				.unwrap_or_else(|| PathBuf::from(format!("host-processor-{}", std::process::id()))),
+			socket_mode: file.socket.mode,
+			cgroup: file.exec.cgroup,
+			allowed_uids: file.exec.allowed_uids,
+			allowed_gids: file.exec.allowed_gids,
+			allowed_peer_uids: file.socket.allowed_uids,
+			allowed_peer_gids: file.socket.allowed_gids,
+			peer_policy: file.socket.peer_policy.unwrap_or_default(),
+			audit_log: file.audit.file.map(|path| LogFile {
+				path: path.to_string_lossy().to_string(),
+				append: true,
+				rotation: Some(LogRotation {
+					max_size: file.audit.max_size.unwrap_or(Self::DEFAULT_AUDIT_MAX_SIZE),
+					generations: file.audit.generations.unwrap_or(Self::DEFAULT_AUDIT_GENERATIONS),
+					gzip: file.audit.gzip.unwrap_or(true)
+				}),
+				timestamps: false
+			}),
+			programs: ProgramPolicy::new(
+				file.exec.allowed_programs.into_iter()
+					.map(ProgramRule::any_args)
+					.chain(file.exec.rules)
+					.collect()
+			),
+			default_dir: file.exec.default_dir,
+			env: EnvScrub {
+				clear: file.exec.env_clear,
+				keep: file.exec.env_keep,
+				remove: file.exec.env_remove
+			},
+			default_limits: ExecLimits {
+				address_space: file.limits.address_space,
+				cpu_time: file.limits.cpu_time,
+				open_files: file.limits.open_files,
+				processes: file.limits.processes,
+				cgroup: None,
+				wall_time: file.limits.wall_time,
+				idle_time: file.limits.idle_time
+			},
+			container: ContainerPolicy::new(
+				file.container.apptainer
+					.unwrap_or_else(|| Self::DEFAULT_APPTAINER.to_string()),
+				file.container.allowed_binds
+			),
+			state_file: file.exec.state_file,
+			retention: file.exec.retention
+				.map(Duration::from_secs)
+				.unwrap_or(Self::DEFAULT_RETENTION),
+			output_budget: file.exec.output_budget
+				.unwrap_or(Self::DEFAULT_OUTPUT_BUDGET),
+			overflow: file.exec.overflow.unwrap_or_default(),
+			stdin_window: file.exec.stdin_window
+				.unwrap_or(Self::DEFAULT_STDIN_WINDOW),
+			timeout_grace: file.exec.timeout_grace
+				.map(Duration::from_secs)
+				.unwrap_or(Self::DEFAULT_TIMEOUT_GRACE),
+			shutdown_policy: file.shutdown.policy.unwrap_or_default(),
+			shutdown_deadline: file.shutdown.deadline
+				.map(Duration::from_secs)
+				.unwrap_or(Self::DEFAULT_SHUTDOWN_DEADLINE),
+			handoff_file: file.shutdown.handoff_file,
+			metrics_file: file.metrics.file,
+			metrics_interval: file.metrics.interval
+				// NOTE: an interval of zero would never let anything else run
+				.map(|secs| Duration::from_secs(secs.max(1)))
+				.unwrap_or(Self::DEFAULT_METRICS_INTERVAL)
+		}
+	}
+}
+
+
+/// Which environment variables to pass from this process to the processes it launches
+#[derive(Debug, Clone, Default)]
+pub struct EnvScrub {
+
+	/// don't pass any variables, except the ones in `keep`
+	pub clear: bool,
+
+	/// variables to pass even when clearing the environment
+	pub keep: Vec<String>,
+
+	/// variables to never pass
+	pub remove: Vec<String>
+}
+
+
+/// The TOML config file, where every setting is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+	pub log: LogSection,
+	pub socket: SocketSection,
+	pub audit: AuditSection,
+	pub exec: ExecSection,
+	pub limits: LimitsSection,
+	pub container: ContainerSection,
+	pub shutdown: ShutdownSection,
+	pub metrics: MetricsSection
+}
+
+impl ConfigFile {
+
+	pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		let toml = fs::read_to_string(path)
+			.context(format!("Failed to read config file: {}", path.to_string_lossy()))?;
+		toml::from_str(&toml)
+			.context(format!("Failed to parse config file: {}", path.to_string_lossy()))
+	}
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSection {
+
+	/// settings for log output, eg "host_processor=info"
+	pub filter: Option<String>,
+
+	/// file to write the log to, instead of stdout
+	pub file: Option<PathBuf>
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SocketSection {
+	pub path: Option<PathBuf>,
+	/// eg 0o660
+	pub mode: Option<u32>,
+	pub allowed_uids: Vec<u32>,
+	pub allowed_gids: Vec<u32>,
+	#[serde(deserialize_with = "parse_option")]
+	pub peer_policy: Option<PeerPolicy>
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditSection {
+
+	/// file to write the audit log to, or None for no audit log
+	pub file: Option<PathBuf>,
+
+	/// rotate the file when it gets bigger than this, in bytes
+	pub max_size: Option<u64>,
+
+	/// how many rotated files to keep
+	pub generations: Option<u32>,
+
+	/// compress the rotated files
+	pub gzip: Option<bool>
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecSection {
+	/// programs to allow with any arguments
+	pub allowed_programs: Vec<String>,
+	/// programs to allow with some arguments
+	pub rules: Vec<ProgramRule>,
+	pub allowed_uids: Vec<u32>,
+	pub allowed_gids: Vec<u32>,
+	pub default_dir: Option<PathBuf>,
+	pub env_clear: bool,
+	pub env_keep: Vec<String>,
+	pub env_remove: Vec<String>,
+	pub cgroup: Option<PathBuf>,
+	pub state_file: Option<PathBuf>,
+	/// in seconds
+	pub retention: Option<u64>,
+	/// in bytes
+	pub output_budget: Option<usize>,
+	#[serde(deserialize_with = "parse_option")]
+	pub overflow: Option<OverflowPolicy>,
+	/// in bytes
+	pub stdin_window: Option<usize>,
+	/// in seconds
+	pub timeout_grace: Option<u64>
+}
+
+/// the same fields as ExecLimits, except for the cgroup limits
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsSection {
+	pub address_space: Option<u64>,
+	pub cpu_time: Option<u64>,
+	pub open_files: Option<u64>,
+	pub processes: Option<u64>,
+	pub wall_time: Option<u64>,
+	pub idle_time: Option<u64>
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContainerSection {
+	pub apptainer: Option<String>,
+	/// host folders that containers may mount, or empty to allow no binds
+	pub allowed_binds: Vec<PathBuf>
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownSection {
+	#[serde(deserialize_with = "parse_option")]
+	pub policy: Option<ShutdownPolicy>,
+	/// in seconds
+	pub deadline: Option<u64>,
+	pub handoff_file: Option<PathBuf>
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsSection {
+	/// file to write the stats to, in the Prometheus text format, or None for no metrics file
+	pub file: Option<PathBuf>,
+	/// in seconds
+	pub interval: Option<u64>
+}
+
+
+/// Deserializes a string into any type with a FromStr implementation
+fn parse_option<'de,D,T>(deserializer: D) -> Result<Option<T>,D::Error>
+	where
+		D: Deserializer<'de>,
+		T: FromStr,
+		T::Err: Display
+{
+	Option::<String>::deserialize(deserializer)?
+		.map(|s| s.parse::<T>().map_err(D::Error::custom))
+		.transpose()
+}
+
+
+/// Parses a file mode in octal, eg 660 or 0660
+pub fn parse_mode(s: &str) -> Result<u32,String> {
+	u32::from_str_radix(s.trim_start_matches("0o"), 8)
+		.map_err(|e| format!("Invalid file mode: {}: {}", s, e))
+}
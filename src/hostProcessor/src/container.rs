@@ -0,0 +1,141 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::proto::{ContainerBind, ContainerFlag, ExecContainer};
+
+
+/// Which host folders Exec requests may mount into containers, and how to run apptainer
+#[derive(Debug, Clone, Default)]
+pub struct ContainerPolicy {
+
+	/// the apptainer (or singularity) program, names without a / are looked up in PATH
+	pub apptainer: String,
+
+	/// canonical paths of the host folders that binds must be inside of
+	allowed_binds: Vec<PathBuf>
+}
+
+impl ContainerPolicy {
+
+	pub fn new(apptainer: String, allowed_binds: Vec<PathBuf>) -> Self {
+		let allowed_binds = allowed_binds.into_iter()
+			.map(|path| {
+				fs::canonicalize(&path)
+					.unwrap_or_else(|e| {
+						warn!("Failed to resolve allowed bind {}, it won't match anything: {}", path.to_string_lossy(), e);
+						path
+					})
+			})
+			.collect();
+		Self {
+			apptainer,
+			allowed_binds
+		}
+	}
+
+	/// Checks the container against the policy, and builds the apptainer command line that runs the program inside it
+	pub fn command(&self, container: &ExecContainer, program: &str, args: &[String], dir: &Path) -> Result<ContainerCommand,String> {
+
+		// NOTE: apptainer would read an image starting with - as an option
+		if container.image.is_empty() || container.image.starts_with('-') {
+			return Err(format!("Invalid container image: {}", container.image));
+		}
+
+		let mut apptainer_args = vec!["exec".to_string()];
+		for flag in &container.flags {
+			apptainer_args.push(flag_arg(*flag).to_string());
+		}
+		for bind in &container.binds {
+			apptainer_args.push("--bind".to_string());
+			apptainer_args.push(self.bind_arg(bind, dir)?);
+		}
+		if let Some(workdir) = &container.workdir {
+			if !workdir.starts_with('/') {
+				return Err(format!("Container workdir must be an absolute path: {}", workdir));
+			}
+			apptainer_args.push("--pwd".to_string());
+			apptainer_args.push(workdir.clone());
+		}
+		apptainer_args.push(container.image.clone());
+		apptainer_args.push(program.to_string());
+		apptainer_args.extend(args.iter().cloned());
+
+		// NOTE: pass variables with APPTAINERENV_ instead of --env, since --env splits values on commas
+		let envvars = container.envvars.iter()
+			.map(|(name, value)| {
+				if !is_env_name(name) {
+					return Err(format!("Invalid container environment variable name: {}", name));
+				}
+				Ok((format!("APPTAINERENV_{}", name), value.clone()))
+			})
+			.collect::<Result<Vec<_>,_>>()?;
+
+		Ok(ContainerCommand {
+			program: self.apptainer.clone(),
+			args: apptainer_args,
+			envvars
+		})
+	}
+
+	/// Checks the bind against the allow-list, and formats it for --bind
+	fn bind_arg(&self, bind: &ContainerBind, dir: &Path) -> Result<String,String> {
+
+		// NOTE: --bind uses : to separate the parts and , to separate binds, so paths can't have either
+		let is_bad_path = |path: &str| path.is_empty() || path.contains([':', ',']);
+		if is_bad_path(&bind.src) {
+			return Err(format!("Invalid bind source: {}", bind.src));
+		}
+
+		// resolve symlinks, so they can't point outside of the allowed folders
+		let src = fs::canonicalize(dir.join(&bind.src))
+			.map_err(|e| format!("Bind source not found: {}: {}", bind.src, e))?;
+		if !self.allowed_binds.iter().any(|allowed| src.starts_with(allowed)) {
+			return Err(format!("Bind not allowed: {}", src.to_string_lossy()));
+		}
+		let src = src.to_str()
+			.filter(|src| !is_bad_path(src))
+			.ok_or_else(|| format!("Invalid bind source: {}", src.to_string_lossy()))?;
+
+		let dst = match &bind.dst {
+			Some(dst) if is_bad_path(dst) || !dst.starts_with('/') => return Err(format!("Invalid bind destination: {}", dst)),
+			Some(dst) => dst.as_str(),
+			None => src
+		};
+
+		Ok(match bind.read_only {
+			true => format!("{}:{}:ro", src, dst),
+			false => format!("{}:{}", src, dst)
+		})
+	}
+}
+
+
+/// How to launch apptainer to run a program inside a container
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerCommand {
+	pub program: String,
+	pub args: Vec<String>,
+	/// variables for the apptainer process, to set inside the container
+	pub envvars: Vec<(String,String)>
+}
+
+
+fn flag_arg(flag: ContainerFlag) -> &'static str {
+	match flag {
+		ContainerFlag::Nv => "--nv",
+		ContainerFlag::Rocm => "--rocm",
+		ContainerFlag::CleanEnv => "--cleanenv",
+		ContainerFlag::Contain => "--contain",
+		ContainerFlag::NoHome => "--no-home"
+	}
+}
+
+
+fn is_env_name(name: &str) -> bool {
+	let mut chars = name.chars();
+	chars.next()
+		.is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+		&& chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -0,0 +1,109 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+
+use crate::logging::ResultExt;
+use crate::proto::{ConsoleKind, ExecStderr, ExecStdout};
+
+
+/// Copies of the outputs we read from a process, so something can keep reading them after the host processor detaches from it.
+/// Otherwise, the process would die from SIGPIPE the next time it wrote to an output no one reads anymore.
+pub struct Drains {
+	/// where each output should go, if it's a file
+	stdout_path: Option<String>,
+	stderr_path: Option<String>,
+	/// None when we won't detach from the process, so there's no need for copies
+	outputs: Option<Vec<(ConsoleKind,io::Result<OwnedFd>)>>
+}
+
+impl Drains {
+
+	pub fn new(detachable: bool, stdout: &ExecStdout, stderr: &ExecStderr) -> Self {
+		let stdout_path = match stdout {
+			ExecStdout::Write { path } => Some(path.clone()),
+			ExecStdout::LogFile(log_file) => Some(log_file.path.clone()),
+			_ => None
+		};
+		let stderr_path = match stderr {
+			ExecStderr::Write { path } => Some(path.clone()),
+			ExecStderr::LogFile(log_file) => Some(log_file.path.clone()),
+			ExecStderr::Merge => stdout_path.clone(),
+			_ => None
+		};
+		Self {
+			stdout_path,
+			stderr_path,
+			outputs: detachable.then(Vec::new)
+		}
+	}
+
+	pub fn keep(&mut self, kind: ConsoleKind, output: &impl AsFd) {
+		if let Some(outputs) = &mut self.outputs {
+			outputs.push((kind, output.as_fd().try_clone_to_owned()));
+		}
+	}
+
+	/// Starts a `cat` for each output, that outlives the host processor.
+	/// Outputs that went to files get appended to them as-is, without any rotation or timestamps.
+	/// Other outputs have no one to go to anymore, so they get discarded.
+	pub fn spawn(self) {
+		for (kind, output) in self.outputs.into_iter().flatten() {
+			let path = match kind {
+				ConsoleKind::Stdout => &self.stdout_path,
+				ConsoleKind::Stderr => &self.stderr_path
+			};
+			output.context("Failed to copy output")
+				.and_then(|output| spawn_cat(output, path.as_deref()))
+				.context(format!("Failed to drain {} of detached process", kind.name()))
+				.warn_err()
+				.ok();
+		}
+	}
+}
+
+
+fn spawn_cat(output: OwnedFd, path: Option<&str>) -> Result<()> {
+
+	// NOTE: the async runtime reads the output without blocking, but cat expects to block
+	set_blocking(&output)
+		.context("Failed to make output blocking")?;
+
+	let stdout = match path {
+		Some(path) => Stdio::from(
+			OpenOptions::new()
+				.create(true)
+				.append(true)
+				.open(path)
+				.context(format!("Failed to open file: {}", path))?
+		),
+		None => Stdio::null()
+	};
+
+	// NOTE: start a new process group, so signals meant for the host processor's group don't stop cat too
+	// NOTE: don't wait for cat, it keeps running after the host processor exits
+	Command::new("cat")
+		.stdin(output)
+		.stdout(stdout)
+		.stderr(Stdio::null())
+		.process_group(0)
+		.spawn()
+		.context("Failed to spawn cat")?;
+
+	Ok(())
+}
+
+
+fn set_blocking(fd: &OwnedFd) -> io::Result<()> {
+	let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFL) };
+	if flags == -1 {
+		return Err(io::Error::last_os_error());
+	}
+	match unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFL, flags & !libc::O_NONBLOCK) } {
+		-1 => Err(io::Error::last_os_error()),
+		_ => Ok(())
+	}
+}
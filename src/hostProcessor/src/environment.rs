@@ -0,0 +1,27 @@
+use std::env;
+use std::ffi::OsString;
+
+use crate::config::EnvScrub;
+use crate::proto::{EnvMode, ExecEnv};
+
+
+/// The environment variables a process launched with Exec gets from this process,
+/// after applying the config and then the request, but before the request's own variables
+pub fn inherited(scrub: &EnvScrub, request: Option<&ExecEnv>) -> Vec<(OsString,OsString)> {
+	env::vars_os()
+		.filter(|(name, _)| !scrub.clear || is_listed(name, &scrub.keep))
+		.filter(|(name, _)| !is_listed(name, &scrub.remove))
+		.filter(|(name, _)| match request.map(|env| &env.mode) {
+			None | Some(EnvMode::Inherit) => true,
+			Some(EnvMode::Clear) => false,
+			Some(EnvMode::Allow(names)) => is_listed(name, names)
+		})
+		.filter(|(name, _)| !request.is_some_and(|env| is_listed(name, &env.unset)))
+		.collect()
+}
+
+
+fn is_listed(name: &OsString, names: &[String]) -> bool {
+	names.iter()
+		.any(|n| name == n.as_str())
+}
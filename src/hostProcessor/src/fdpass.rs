@@ -0,0 +1,166 @@
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, Interest, ReadBuf};
+use tokio::net::UnixStream;
+use tokio::net::unix::OwnedReadHalf;
+use tracing::warn;
+
+use crate::proto::{ExecRequest, ExecStderr, ExecStdin, ExecStdout};
+
+
+/// room for the control messages of one read, in u64s so the buffer is aligned for cmsghdr,
+/// enough for a few more file descriptors than the three stdio streams
+const CMSG_BUF_LEN: usize = 8;
+
+
+/// Reads from the socket like any other stream, but also collects any file descriptors the client sent with SCM_RIGHTS.
+/// NOTE: Framed reads never read past the end of the current frame,
+///       so the file descriptors collected while reading a frame are exactly the ones the client sent with it.
+pub struct FdReader {
+	socket: OwnedReadHalf,
+	fds: Vec<OwnedFd>
+}
+
+impl FdReader {
+
+	pub fn new(socket: OwnedReadHalf) -> Self {
+		Self {
+			socket,
+			fds: Vec::new()
+		}
+	}
+
+	/// Takes the file descriptors received since the last call
+	pub fn take_fds(&mut self) -> Vec<OwnedFd> {
+		mem::take(&mut self.fds)
+	}
+}
+
+impl AsyncRead for FdReader {
+
+	fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+
+		let this = self.get_mut();
+		let socket: &UnixStream = this.socket.as_ref();
+		loop {
+			ready!(socket.poll_read_ready(cx))?;
+			let result = socket.try_io(Interest::READABLE, || {
+				recv_with_fds(socket.as_raw_fd(), buf.initialize_unfilled(), &mut this.fds)
+			});
+			match result {
+				Ok(size) => {
+					buf.advance(size);
+					return Poll::Ready(Ok(()));
+				}
+				// not actually readable yet, wait again
+				Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+				Err(e) => return Poll::Ready(Err(e))
+			}
+		}
+	}
+}
+
+
+fn recv_with_fds(socket: RawFd, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> io::Result<usize> {
+
+	let mut iov = libc::iovec {
+		iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+		iov_len: buf.len()
+	};
+	let mut cmsg_buf = [0u64; CMSG_BUF_LEN];
+	let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+	msg.msg_iov = &mut iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = cmsg_buf.as_mut_ptr() as *mut libc::c_void;
+	msg.msg_controllen = mem::size_of_val(&cmsg_buf) as _;
+
+	// NOTE: don't leak the file descriptors to processes launched for other requests
+	let size = unsafe { libc::recvmsg(socket, &mut msg, libc::MSG_CMSG_CLOEXEC) };
+	if size == -1 {
+		return Err(io::Error::last_os_error());
+	}
+
+	// take ownership of the file descriptors, so they get closed if nothing uses them
+	let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+	while !cmsg.is_null() {
+		let header = unsafe { &*cmsg };
+		if header.cmsg_level == libc::SOL_SOCKET && header.cmsg_type == libc::SCM_RIGHTS {
+			let data = unsafe { libc::CMSG_DATA(cmsg) } as *const RawFd;
+			let count = (header.cmsg_len as usize - unsafe { libc::CMSG_LEN(0) } as usize)/size_of::<RawFd>();
+			for i in 0 .. count {
+				let fd = unsafe { data.add(i).read_unaligned() };
+				fds.push(unsafe { OwnedFd::from_raw_fd(fd) });
+			}
+		}
+		cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+	}
+	if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+		warn!("Client sent too many file descriptors, some were dropped");
+	}
+
+	Ok(size as usize)
+}
+
+
+/// The file descriptors sent with an Exec request, for the stdio streams that use them
+pub struct StdioFds {
+	pub stdin: Option<OwnedFd>,
+	pub stdout: Option<OwnedFd>,
+	pub stderr: Option<OwnedFd>
+}
+
+impl StdioFds {
+
+	/// Matches up the file descriptors with the stdio streams, in stdin, stdout, stderr order.
+	/// Merged stderr goes to the stdout file descriptor too.
+	pub fn assign(request: &ExecRequest, fds: Vec<OwnedFd>) -> Result<Self,String> {
+
+		let sent = fds.len();
+		let mut fds = fds.into_iter();
+		let mut next = |name: &str| {
+			fds.next()
+				.ok_or_else(|| format!("No file descriptor was sent for {}: sent {}", name, sent))
+		};
+
+		let stdin = matches!(request.stdin, ExecStdin::Fd)
+			.then(|| next("stdin"))
+			.transpose()?;
+		let stdout = matches!(request.stdout, ExecStdout::Fd)
+			.then(|| next("stdout"))
+			.transpose()?;
+		let stderr = match (&request.stderr, &stdout) {
+			(ExecStderr::Fd, _) => Some(next("stderr")?),
+			(ExecStderr::Merge, Some(stdout)) => Some(
+				stdout.try_clone()
+					.map_err(|e| format!("Failed to copy stdout file descriptor for stderr: {}", e))?
+			),
+			_ => None
+		};
+
+		let unused = fds.count();
+		if unused > 0 {
+			return Err(format!("{} more file descriptor(s) were sent than the request uses", unused));
+		}
+
+		Ok(Self {
+			stdin,
+			stdout,
+			stderr
+		})
+	}
+
+	/// Takes the stderr file descriptor, if any, copied once for each of `count` processes
+	pub fn take_stderr(&mut self, count: usize) -> io::Result<Vec<OwnedFd>> {
+		let Some(stderr) = self.stderr.take()
+			else { return Ok(Vec::new()); };
+		let mut fds = (1 .. count)
+			.map(|_| stderr.try_clone())
+			.collect::<io::Result<Vec<_>>>()?;
+		fds.insert(0, stderr);
+		Ok(fds)
+	}
+}
@@ -0,0 +1,105 @@
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use tokio::net::unix::pipe::{self, Receiver};
+
+use crate::proto::ConsoleKind;
+
+
+/// A named pipe next to the state file, for a streamed output of a persistent process, so the output can outlive the host processor.
+/// The process opens the pipe for reading and writing, so there's always a reader and it never dies from SIGPIPE:
+/// while no host processor is reading, its writes just block once the pipe is full,
+/// until the host processor that recovers it opens the pipe again and starts reading.
+pub struct OutputFifo {
+	path: PathBuf,
+	/// our end, for reading
+	pub reader: Option<Receiver>,
+	/// the process' end
+	pub writer: Option<OwnedFd>,
+	/// true once the pipe is named after the process, so it stays behind for the next host processor
+	kept: bool
+}
+
+impl OutputFifo {
+
+	/// Makes a new pipe, with a temporary name until we know the pid of the process
+	pub fn create(state_path: &Path, kind: ConsoleKind) -> io::Result<Self> {
+
+		let path = path(state_path, &format!("new-{}", rand::random::<u32>()), kind);
+		let path_c = CString::new(path.as_os_str().as_bytes())?;
+		if unsafe { libc::mkfifo(path_c.as_ptr(), 0o600) } == -1 {
+			return Err(io::Error::last_os_error());
+		}
+
+		// NOTE: open the process' end first, since our end would see the end of the stream without any writers
+		let opened = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.open(&path)
+			.and_then(|writer| Ok((writer, pipe::OpenOptions::new().open_receiver(&path)?)));
+		let (writer, reader) = match opened {
+			Ok(o) => o,
+			Err(e) => {
+				fs::remove_file(&path)
+					.ok();
+				return Err(e);
+			}
+		};
+
+		Ok(Self {
+			path,
+			reader: Some(reader),
+			writer: Some(OwnedFd::from(writer)),
+			kept: false
+		})
+	}
+
+	/// Names the pipe after the process, so the next host processor can find it
+	pub fn keep(&mut self, state_path: &Path, pid: u32, kind: ConsoleKind) -> io::Result<()> {
+		let kept_path = path(state_path, &pid.to_string(), kind);
+		fs::rename(&self.path, &kept_path)?;
+		self.path = kept_path;
+		self.kept = true;
+		Ok(())
+	}
+
+	/// Opens the pipe of a process launched by a previous host processor, if it has one
+	pub fn open(state_path: &Path, pid: u32, kind: ConsoleKind) -> io::Result<Option<Receiver>> {
+		let path = path(state_path, &pid.to_string(), kind);
+		if !path.exists() {
+			return Ok(None);
+		}
+		pipe::OpenOptions::new()
+			.open_receiver(&path)
+			.map(Some)
+	}
+
+	/// Removes the pipes of a process, once it has finished
+	pub fn remove(state_path: &Path, pid: u32) {
+		for kind in [ConsoleKind::Stdout, ConsoleKind::Stderr] {
+			fs::remove_file(path(state_path, &pid.to_string(), kind))
+				.ok();
+		}
+	}
+}
+
+impl Drop for OutputFifo {
+	fn drop(&mut self) {
+		if !self.kept {
+			fs::remove_file(&self.path)
+				.ok();
+		}
+	}
+}
+
+
+/// eg, /var/lib/host-processor/state.1234.stdout
+fn path(state_path: &Path, name: &str, kind: ConsoleKind) -> PathBuf {
+	let mut path = state_path.as_os_str().to_owned();
+	path.push(format!(".{}.{}", name, kind.name()));
+	PathBuf::from(path)
+}
@@ -3,3 +3,24 @@ pub mod logging;
 pub mod proto;
 pub mod framing;
 pub mod processes;
+pub mod config;
+pub mod limits;
+pub mod privileges;
+pub mod wait;
+pub mod signals;
+pub mod pty;
+pub mod logfile;
+pub mod lines;
+pub mod outbox;
+pub mod peers;
+pub mod policy;
+pub mod audit;
+pub mod shutdown;
+pub mod systemd;
+pub mod stats;
+pub mod fdpass;
+pub mod environment;
+pub mod container;
+pub mod stdin;
+pub mod drain;
+pub mod fifo;
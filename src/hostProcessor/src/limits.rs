@@ -0,0 +1,276 @@
+use std::cell::Cell;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use tokio::time::{self, Instant};
+
+use crate::proto::{ExecCgroup, ExecLimits, LimitKind};
+
+
+/// Checks the limits against the hard limits of this process,
+/// since an unprivileged process can only lower its hard limits, never raise them.
+/// Call this before spawning a process, so the caller gets a useful error message.
+pub fn check_rlimits(limits: &ExecLimits) -> Result<()> {
+	for (name, resource, requested) in rlimits(limits) {
+		let Some(requested) = requested
+			else { continue; };
+		let hard = get_rlimit(resource)
+			.context(format!("Failed to read {} limit", name))?
+			.rlim_max;
+		if hard != libc::RLIM_INFINITY && requested > hard as u64 {
+			bail!("Requested {} limit of {} exceeds the hard limit of {}", name, requested, hard);
+		}
+	}
+	Ok(())
+}
+
+
+/// Applies the limits to the current process.
+/// WARNING: This gets called in the child process after fork() but before exec(),
+///          so only async-signal-safe functions are allowed here. ie, no allocations!
+pub fn apply_rlimits(limits: &ExecLimits) -> io::Result<()> {
+	for (_, resource, requested) in rlimits(limits) {
+		let Some(requested) = requested
+			else { continue; };
+		let hard = if resource == libc::RLIMIT_CPU {
+			// put the hard limit one second past the soft limit,
+			// so the process gets a SIGXCPU (rather than a SIGKILL) and we can tell why it died
+			requested.saturating_add(1)
+		} else {
+			requested
+		};
+		set_rlimit(resource, requested, hard)?;
+	}
+	Ok(())
+}
+
+
+/// Fills in any limits the request didn't ask for from the defaults
+pub fn with_defaults(limits: Option<ExecLimits>, defaults: &ExecLimits) -> ExecLimits {
+	let limits = limits.unwrap_or_default();
+	ExecLimits {
+		address_space: limits.address_space.or(defaults.address_space),
+		cpu_time: limits.cpu_time.or(defaults.cpu_time),
+		open_files: limits.open_files.or(defaults.open_files),
+		processes: limits.processes.or(defaults.processes),
+		cgroup: limits.cgroup.or(defaults.cgroup.clone()),
+		wall_time: limits.wall_time.or(defaults.wall_time),
+		idle_time: limits.idle_time.or(defaults.idle_time)
+	}
+}
+
+
+/// Figures out which limit, if any, ended the process
+pub fn exceeded(limits: &ExecLimits, exit: &ExitStatus, cgroup: Option<&Cgroup>) -> Option<LimitKind> {
+
+	if limits.cpu_time.is_some() && exit.signal() == Some(libc::SIGXCPU) {
+		return Some(LimitKind::CpuTime);
+	}
+
+	// NOTE: processes that exceed RLIMIT_AS just see allocations fail, and can die in any number of ways,
+	//       so we can only reliably detect memory limits enforced by the cgroup OOM killer
+	if let Some(cgroup) = cgroup {
+		if cgroup.oom_killed() {
+			return Some(LimitKind::Memory);
+		}
+	}
+
+	None
+}
+
+
+type Resource = libc::__rlimit_resource_t;
+
+fn rlimits(limits: &ExecLimits) -> [(&'static str, Resource, Option<u64>); 4] {
+	[
+		("address space", libc::RLIMIT_AS, limits.address_space),
+		("cpu time", libc::RLIMIT_CPU, limits.cpu_time),
+		("open files", libc::RLIMIT_NOFILE, limits.open_files),
+		("processes", libc::RLIMIT_NPROC, limits.processes)
+	]
+}
+
+fn get_rlimit(resource: Resource) -> io::Result<libc::rlimit> {
+	let mut rlim = libc::rlimit {
+		rlim_cur: 0,
+		rlim_max: 0
+	};
+	match unsafe { libc::getrlimit(resource, &mut rlim) } {
+		0 => Ok(rlim),
+		_ => Err(io::Error::last_os_error())
+	}
+}
+
+fn set_rlimit(resource: Resource, soft: u64, hard: u64) -> io::Result<()> {
+	let rlim = libc::rlimit {
+		rlim_cur: soft as libc::rlim_t,
+		rlim_max: hard as libc::rlim_t
+	};
+	match unsafe { libc::setrlimit(resource, &rlim) } {
+		0 => Ok(()),
+		_ => Err(io::Error::last_os_error())
+	}
+}
+
+
+/// A cgroup (v2) for a single process (and all its subprocesses)
+pub struct Cgroup {
+	path: PathBuf,
+	procs: File
+}
+
+impl Cgroup {
+
+	/// Makes a new cgroup inside of the parent cgroup folder.
+	/// The parent cgroup must have the needed controllers (eg, memory, cpu) enabled in its cgroup.subtree_control.
+	pub fn create(parent: &Path, name: impl AsRef<str>, limits: &ExecCgroup) -> Result<Self> {
+
+		let path = parent.join(name.as_ref());
+		fs::create_dir(&path)
+			.context(format!("Failed to create cgroup: {}", path.to_string_lossy()))?;
+
+		// NOTE: cgroup folder is created, don't return without cleaning it up
+		let result = Self::init(&path, limits);
+		match result {
+			Ok(procs) => Ok(Self {
+				path,
+				procs
+			}),
+			Err(e) => {
+				fs::remove_dir(&path)
+					.ok();
+				Err(e)
+			}
+		}
+	}
+
+	fn init(path: &Path, limits: &ExecCgroup) -> Result<File> {
+
+		if let Some(bytes) = limits.memory_max {
+			write_file(&path.join("memory.max"), format!("{}", bytes))?;
+		}
+
+		if let Some(cpu_max) = &limits.cpu_max {
+			write_file(&path.join("cpu.max"), format!("{} {}", cpu_max.quota, cpu_max.period))?;
+		}
+
+		// open the procs file now, so the child process can join the cgroup without allocating anything
+		let procs_path = path.join("cgroup.procs");
+		OpenOptions::new()
+			.write(true)
+			.open(&procs_path)
+			.context(format!("Failed to open: {}", procs_path.to_string_lossy()))
+	}
+
+	pub fn procs_fd(&self) -> RawFd {
+		self.procs.as_raw_fd()
+	}
+
+	/// Moves the current process into the cgroup, via a file descriptor from `procs_fd()`.
+	/// WARNING: This gets called in the child process after fork() but before exec(),
+	///          so only async-signal-safe functions are allowed here. ie, no allocations!
+	pub fn join(procs_fd: RawFd) -> io::Result<()> {
+		// writing pid 0 means the calling process
+		let buf = b"0";
+		match unsafe { libc::write(procs_fd, buf.as_ptr() as *const libc::c_void, buf.len()) } {
+			-1 => Err(io::Error::last_os_error()),
+			_ => Ok(())
+		}
+	}
+
+	/// returns true if the OOM killer has killed anything in this cgroup
+	pub fn oom_killed(&self) -> bool {
+		let Ok(events) = fs::read_to_string(self.path.join("memory.events"))
+			else { return false; };
+		events.lines()
+			.filter_map(|line| line.strip_prefix("oom_kill "))
+			.any(|count| count.trim().parse::<u64>().unwrap_or(0) > 0)
+	}
+
+	/// Removes the cgroup folder.
+	/// Will fail if any processes are still in the cgroup.
+	pub fn remove(self) -> Result<()> {
+		let Self { path, procs } = self;
+		drop(procs);
+		fs::remove_dir(&path)
+			.context(format!("Failed to remove cgroup: {}", path.to_string_lossy()))
+	}
+}
+
+
+
+/// The wall-clock and idle-output timeouts for a process
+pub struct Timeouts {
+	started: Instant,
+	wall_time: Option<Duration>,
+	idle_time: Option<Duration>,
+	last_output: Cell<Instant>,
+	exceeded: Cell<Option<LimitKind>>
+}
+
+impl Timeouts {
+
+	/// Returns None if the limits don't have any timeouts
+	pub fn new(limits: &ExecLimits, started: std::time::Instant) -> Option<Self> {
+
+		if limits.wall_time.is_none() && limits.idle_time.is_none() {
+			return None;
+		}
+
+		let started = Instant::from_std(started);
+		Some(Self {
+			started,
+			wall_time: limits.wall_time.map(Duration::from_millis),
+			idle_time: limits.idle_time.map(Duration::from_millis),
+			last_output: Cell::new(started),
+			exceeded: Cell::new(None)
+		})
+	}
+
+	/// Restarts the idle timer, call this whenever the process writes any output
+	pub fn output(&self) {
+		self.last_output.set(Instant::now());
+	}
+
+	/// Waits until one of the timeouts passes, and returns which one
+	pub async fn wait(&self) -> LimitKind {
+		loop {
+
+			let next = [
+				self.wall_time.map(|t| (self.started + t, LimitKind::WallTime)),
+				self.idle_time.map(|t| (self.last_output.get() + t, LimitKind::IdleTime))
+			]
+				.into_iter()
+				.flatten()
+				.min_by_key(|(deadline, _)| *deadline);
+			let Some((deadline, kind)) = next
+				else { return std::future::pending().await; };
+
+			if deadline <= Instant::now() {
+				self.exceeded.set(Some(kind));
+				return kind;
+			}
+
+			// NOTE: the process may write more output while we sleep, so check the deadlines again after
+			time::sleep_until(deadline)
+				.await;
+		}
+	}
+
+	/// Returns the timeout that passed, if any
+	pub fn exceeded(&self) -> Option<LimitKind> {
+		self.exceeded.get()
+	}
+}
+
+
+fn write_file(path: &Path, content: String) -> Result<()> {
+	fs::write(path, content)
+		.context(format!("Failed to write: {}", path.to_string_lossy()))
+}
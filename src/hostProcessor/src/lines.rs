@@ -0,0 +1,88 @@
+use std::mem;
+use std::time::Instant;
+
+use crate::proto::{ConsoleKind, ConsoleLines, ProcessEvent};
+
+
+/// Splits the console output of a process into lines, for ProcessEvent::ConsoleLine
+pub struct LineFramer {
+	kind: ConsoleKind,
+	max_line_length: usize,
+	max_partial: usize,
+	started: Instant,
+	buf: Vec<u8>,
+	/// how many bytes of the current line were already sent as partial lines
+	sent: usize,
+	truncated: bool
+}
+
+impl LineFramer {
+
+	pub fn new(kind: ConsoleKind, config: &ConsoleLines, started: Instant) -> Self {
+		Self {
+			kind,
+			max_line_length: config.max_line_length as usize,
+			max_partial: config.max_partial as usize,
+			started,
+			buf: Vec::new(),
+			sent: 0,
+			truncated: false
+		}
+	}
+
+	/// Adds a chunk of console output, and returns the events for any lines that are ready
+	pub fn push(&mut self, chunk: &[u8]) -> Vec<ProcessEvent> {
+
+		let mut events = Vec::new();
+
+		for segment in chunk.split_inclusive(|b| *b == b'\n') {
+
+			let (content, eol) = match segment.strip_suffix(b"\n") {
+				Some(content) => (content, true),
+				None => (segment, false)
+			};
+
+			// keep only as much of the line as will fit
+			let room = self.max_line_length.saturating_sub(self.sent + self.buf.len());
+			if content.len() > room {
+				self.truncated = true;
+			}
+			self.buf.extend_from_slice(&content[..content.len().min(room)]);
+
+			if eol {
+				// NOTE: terminals end lines with \r\n
+				if self.buf.ends_with(b"\r") {
+					self.buf.pop();
+				}
+				events.push(self.event(false));
+				self.sent = 0;
+			} else if !self.buf.is_empty() && self.buf.len() >= self.max_partial {
+				self.sent += self.buf.len();
+				events.push(self.event(true));
+			}
+		}
+
+		events
+	}
+
+	/// Returns the event for the rest of the last line, if any, after the output has ended
+	pub fn finish(&mut self) -> Option<ProcessEvent> {
+		if self.buf.is_empty() && !self.truncated {
+			None
+		} else {
+			Some(self.event(true))
+		}
+	}
+
+	fn event(&mut self, partial: bool) -> ProcessEvent {
+		let event = ProcessEvent::ConsoleLine {
+			kind: self.kind,
+			timestamp: self.started.elapsed().as_micros() as u64,
+			line: mem::take(&mut self.buf),
+			partial,
+			truncated: self.truncated
+		};
+		self.truncated = false;
+		event
+	}
+}
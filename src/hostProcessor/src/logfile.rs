@@ -0,0 +1,208 @@
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use time::{OffsetDateTime, UtcOffset};
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+use crate::proto::{LogFile, LogRotation};
+
+
+const TIMESTAMP_FORMAT: &[FormatItem<'static>] = format_description!(
+	version = 2,
+	"[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:3] [offset_hour sign:mandatory]:[offset_minute] "
+);
+
+
+/// Writes the output of a process to a file, rotating the file when it gets too big
+pub struct LogFileWriter {
+	path: PathBuf,
+	rotation: Option<LogRotation>,
+	/// the local time offset for timestamps, if timestamps are needed
+	timestamps: Option<UtcOffset>,
+	file: File,
+	size: u64,
+	at_line_start: bool
+}
+
+impl LogFileWriter {
+
+	/// Creates (or truncates) the file, like File::create(), without any rotation or timestamps
+	pub async fn create(path: impl Into<String>) -> io::Result<Self> {
+		Self::open(&LogFile {
+			path: path.into(),
+			append: false,
+			rotation: None,
+			timestamps: false
+		}).await
+	}
+
+	pub async fn open(log_file: &LogFile) -> io::Result<Self> {
+
+		let path = PathBuf::from(&log_file.path);
+		let file = open_file(&path, log_file.append)
+			.await?;
+		let size = file.metadata()
+			.await?
+			.len();
+
+		Ok(Self {
+			path,
+			rotation: log_file.rotation.clone(),
+			timestamps: match log_file.timestamps {
+				true => Some(UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC)),
+				false => None
+			},
+			file,
+			size,
+			at_line_start: true
+		})
+	}
+
+	pub async fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+
+		if self.rotation.is_none() && self.timestamps.is_none() {
+			// nothing to do for each line, just write the whole chunk
+			self.write_raw(chunk).await?;
+		} else {
+			for line in chunk.split_inclusive(|b| *b == b'\n') {
+
+				if self.needs_rotation(line.len()) {
+					self.rotate().await?;
+				}
+
+				if self.at_line_start {
+					if let Some(offset) = self.timestamps {
+						let timestamp = OffsetDateTime::now_utc()
+							.to_offset(offset)
+							.format(TIMESTAMP_FORMAT)
+							.map_err(io::Error::other)?;
+						self.write_raw(timestamp.as_bytes()).await?;
+					}
+				}
+
+				self.write_raw(line).await?;
+				self.at_line_start = line.ends_with(b"\n");
+			}
+		}
+
+		// NOTE: tokio files write in the background, so wait for the write to actually finish
+		self.file.flush()
+			.await
+	}
+
+	async fn write_raw(&mut self, buf: &[u8]) -> io::Result<()> {
+		self.file.write_all(buf)
+			.await?;
+		self.size += buf.len() as u64;
+		Ok(())
+	}
+
+	fn needs_rotation(&self, line_len: usize) -> bool {
+
+		let Some(rotation) = &self.rotation
+			else { return false; };
+
+		if self.size == 0 {
+			// nothing to rotate, the line is just too big for one file
+			false
+		} else if self.at_line_start {
+			self.size + line_len as u64 > rotation.max_size
+		} else {
+			// only split lines that are already too big
+			self.size >= rotation.max_size
+		}
+	}
+
+	async fn rotate(&mut self) -> io::Result<()> {
+
+		let Some(rotation) = self.rotation.clone()
+			else { return Ok(()); };
+
+		self.file.flush()
+			.await?;
+
+		if rotation.generations > 0 {
+
+			let ext = match rotation.gzip {
+				true => ".gz",
+				false => ""
+			};
+
+			// shift the old generations down, dropping the oldest one
+			remove_if_exists(&generation_path(&self.path, rotation.generations, ext))
+				.await?;
+			for i in (1 .. rotation.generations).rev() {
+				rename_if_exists(&generation_path(&self.path, i, ext), &generation_path(&self.path, i + 1, ext))
+					.await?;
+			}
+
+			// move the current file into the newest generation
+			let newest = generation_path(&self.path, 1, "");
+			fs::rename(&self.path, &newest)
+				.await?;
+			if rotation.gzip {
+				let dst = generation_path(&self.path, 1, ext);
+				tokio::task::spawn_blocking(move || gzip(&newest, &dst))
+					.await
+					.map_err(io::Error::other)??;
+			}
+		}
+
+		// start the next file
+		self.file = open_file(&self.path, false)
+			.await?;
+		self.size = 0;
+
+		Ok(())
+	}
+}
+
+
+async fn open_file(path: &Path, append: bool) -> io::Result<File> {
+	OpenOptions::new()
+		.write(true)
+		.create(true)
+		.append(append)
+		.truncate(!append)
+		.open(path)
+		.await
+}
+
+
+fn generation_path(path: &Path, generation: u32, ext: &str) -> PathBuf {
+	let mut out = OsString::from(path.as_os_str());
+	out.push(format!(".{}{}", generation, ext));
+	PathBuf::from(out)
+}
+
+
+async fn remove_if_exists(path: &Path) -> io::Result<()> {
+	match fs::remove_file(path).await {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+		r => r
+	}
+}
+
+
+async fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+	match fs::rename(from, to).await {
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+		r => r
+	}
+}
+
+
+/// Compresses the file into a new file, then removes the uncompressed file
+fn gzip(src: &Path, dst: &Path) -> io::Result<()> {
+	let mut reader = std::fs::File::open(src)?;
+	let mut encoder = GzEncoder::new(std::fs::File::create(dst)?, Compression::default());
+	io::copy(&mut reader, &mut encoder)?;
+	encoder.finish()?;
+	std::fs::remove_file(src)
+}
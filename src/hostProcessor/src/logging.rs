@@ -1,5 +1,8 @@
 
+use std::fs::OpenOptions;
 use std::ops::Deref;
+use std::path::Path;
+use std::sync::Mutex;
 
 use anyhow::{Context, Result};
 use display_error_chain::ErrorChainExt;
@@ -11,13 +14,29 @@ use tracing::dispatcher::DefaultGuard;
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 use tracing_subscriber::fmt::format::{Format, Full};
 use tracing_subscriber::fmt::time::OffsetTime;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 
 
-pub fn init(log: impl AsRef<str>) -> Result<()> {
+/// Starts logging to stdout, or to the file, if any
+pub fn init(log: impl AsRef<str>, file: Option<&Path>) -> Result<()> {
+
+	let writer = match file {
+		Some(path) => {
+			let file = OpenOptions::new()
+				.create(true)
+				.append(true)
+				.open(path)
+				.context(format!("Failed to open log file: {}", path.to_string_lossy()))?;
+			BoxMakeWriter::new(Mutex::new(file))
+		}
+		None => BoxMakeWriter::new(std::io::stdout)
+	};
 
 	let log_subscriber = FmtSubscriber::builder()
 		.with_env_filter(log_filter(log)?)
 		.event_format(log_format())
+		.with_writer(writer)
+		.with_ansi(file.is_none())
 		.finish();
 
 	tracing::subscriber::set_global_default(log_subscriber)
@@ -63,6 +82,7 @@ fn log_format() -> Format<Full,OffsetTime<&'static [FormatItem<'static>]>> {
 }
 
 
+#[allow(clippy::result_unit_err)]
 pub trait ResultExt<T> {
 	fn log_err(self) -> Result<T,()>;
 	fn warn_err(self) -> Result<T,()>;
@@ -73,14 +93,12 @@ impl<T> ResultExt<T> for Result<T,anyhow::Error> {
 	fn log_err(self) -> Result<T,()> {
 		self.map_err(|e| {
 			error!("{}", e.deref().chain());
-			()
 		})
 	}
 
 	fn warn_err(self) -> Result<T,()> {
 		self.map_err(|e| {
 			warn!("{}", e.deref().chain());
-			()
 		})
 	}
 }
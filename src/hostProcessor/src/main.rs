@@ -1,32 +1,60 @@
 
 use std::{env, fs};
+use std::ffi::OsString;
+use std::fs::Permissions;
+use std::mem;
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::os::fd::{OwnedFd, RawFd};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::process::{ExitCode, Stdio};
+use std::process::{Command, ExitCode, Stdio};
 use std::rc::Rc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Result};
 use display_error_chain::ErrorChainExt;
+use futures_util::{future, stream};
 use gumdrop::Options;
-use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::net::{UnixListener, UnixStream};
 use tokio::net::unix::OwnedWriteHalf;
-use tokio::process::Command;
+use tokio::process::{ChildStderr, ChildStdin, ChildStdout};
 use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::Mutex;
-use tokio::task::LocalSet;
+use tokio::task::{JoinHandle, LocalSet};
 use tokio_stream::{Stream, StreamExt, StreamMap};
 use tokio_util::bytes::Bytes;
 use tokio_util::io::ReaderStream;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
 use tracing::{debug, error_span, info, Instrument, trace, warn};
 
+use host_processor::audit::{AuditLog, AuditRecord, Outcome};
+use host_processor::config::{self, Config, ConfigFile};
+use host_processor::drain::Drains;
+use host_processor::environment;
+use host_processor::fdpass::{FdReader, StdioFds};
+use host_processor::fifo::OutputFifo;
 use host_processor::framing::{AsyncReadFramed, AsyncWriteFramed};
+use host_processor::limits::{self, Cgroup, Timeouts};
+use host_processor::lines::LineFramer;
+use host_processor::logfile::LogFileWriter;
 use host_processor::logging::{self, ResultExt};
-use host_processor::processes::Processes;
-use host_processor::proto::{ConsoleKind, ExecRequest, ExecResponse, ExecStderr, ExecStdin, ExecStdout, KillSignal, ProcessEvent, Request, RequestEnvelope, Response, ResponseEnvelope};
+use host_processor::outbox::{OverflowPolicy, Outbox, OutputQueue};
+use host_processor::peers::{self, PeerPolicy};
+use host_processor::policy::AllowedProgram;
+use host_processor::privileges::Privileges;
+use host_processor::shutdown::ShutdownPolicy;
+use host_processor::signals;
+use host_processor::stats::{self, Stats};
+use host_processor::stdin::StdinWindow;
+use host_processor::systemd::{self, Notifier};
+use host_processor::wait;
+use host_processor::processes::{self, Attachment, Launch, ProcStdin, Processes};
+use host_processor::pty::{self, PtyStdio};
+use host_processor::proto::{AttachResponse, ConsoleKind, ExecLimits, ExecPipelineRequest, ExecRequest, ExecResponse, ExecStderr, ExecStdin, ExecStdout, KillFailure, KillGracefullyResponse, KillResponse, KillSignal, PipelineStage, ProcessEvent, ProcessStatus, ProcessStdio, Request, RequestEnvelope, ResourceUsage, Response, ResponseEnvelope, StdinResponse, WaitResponse};
 
 
 #[derive(Options)]
@@ -35,9 +63,177 @@ struct Args {
 	#[options(help_flag)]
 	help: bool,
 
-	/// settings for log output
-	#[options(default = "host_processor=info")]
-	log: String
+	/// a TOML file with settings, which the other options override
+	#[options(no_short, meta = "FILE")]
+	config: Option<String>,
+
+	/// settings for log output (default: host_processor=info)
+	log: Option<String>,
+
+	/// write the log to this file, instead of stdout
+	#[options(no_short, meta = "FILE")]
+	log_file: Option<String>,
+
+	/// the unix socket to listen on (default: host-processor-<pid>, in the current folder)
+	#[options(no_short, meta = "PATH")]
+	socket: Option<String>,
+
+	/// file permissions for the socket, in octal, eg 660
+	#[options(no_short, meta = "MODE", parse(try_from_str = "config::parse_mode"))]
+	socket_mode: Option<u32>,
+
+	/// a cgroup (v2) folder in which to create cgroups for processes, needed for cgroup limits
+	#[options(meta = "DIR")]
+	cgroup: Option<String>,
+
+	/// allow processes to run as this uid (can be given more than once)
+	#[options(no_short, meta = "UID")]
+	allow_uid: Vec<u32>,
+
+	/// allow processes to run in this gid (can be given more than once)
+	#[options(no_short, meta = "GID")]
+	allow_gid: Vec<u32>,
+
+	/// allow clients with this uid to connect to the socket (can be given more than once)
+	#[options(no_short, meta = "UID")]
+	allow_peer_uid: Vec<u32>,
+
+	/// allow clients with this primary gid to connect to the socket (can be given more than once)
+	#[options(no_short, meta = "GID")]
+	allow_peer_gid: Vec<u32>,
+
+	/// what to do with clients that aren't allowed to connect: refuse, or log (and serve them anyway) (default: refuse)
+	#[options(no_short, meta = "POLICY")]
+	peer_policy: Option<PeerPolicy>,
+
+	/// write an audit log of every request to this file, as JSON lines
+	#[options(no_short, meta = "FILE")]
+	audit_log: Option<String>,
+
+	/// rotate the audit log when it gets bigger than this, in bytes (default: 67108864)
+	#[options(no_short, meta = "BYTES")]
+	audit_max_size: Option<u64>,
+
+	/// how many rotated audit logs to keep (default: 10)
+	#[options(no_short, meta = "NUM")]
+	audit_generations: Option<u32>,
+
+	/// allow processes to run this program, instead of any program (can be given more than once)
+	#[options(no_short, meta = "PROGRAM")]
+	allow_program: Vec<String>,
+
+	/// the working directory for processes that don't ask for one (default: the current folder)
+	#[options(no_short, meta = "DIR")]
+	default_dir: Option<String>,
+
+	/// don't pass this process' environment variables to processes, except the ones from --env-keep
+	#[options(no_short)]
+	env_clear: bool,
+
+	/// pass this environment variable to processes, even with --env-clear (can be given more than once)
+	#[options(no_short, meta = "NAME")]
+	env_keep: Vec<String>,
+
+	/// don't pass this environment variable to processes (can be given more than once)
+	#[options(no_short, meta = "NAME")]
+	env_remove: Vec<String>,
+
+	/// file to keep track of persistent processes, so they can be recovered after a restart
+	#[options(no_short, meta = "FILE")]
+	state: Option<String>,
+
+	/// how long to keep the status of finished processes, in seconds (default: 60)
+	#[options(no_short, meta = "SECS")]
+	retention: Option<u64>,
+
+	/// max bytes of console output to hold in memory for each process, while the client catches up (default: 1048576)
+	#[options(no_short, meta = "BYTES")]
+	output_budget: Option<usize>,
+
+	/// what to do with console output over the budget: block, drop, or spill (to a temp file) (default: block)
+	#[options(no_short, meta = "POLICY")]
+	overflow: Option<OverflowPolicy>,
+
+	/// max bytes of acknowledged stdin writes each connection can have waiting to be written (default: 1048576)
+	#[options(no_short, meta = "BYTES")]
+	stdin_window: Option<usize>,
+
+	/// after a process times out, how long to wait for SIGTERM to work before sending SIGKILL, in seconds (default: 5)
+	#[options(no_short, meta = "SECS")]
+	timeout_grace: Option<u64>,
+
+	/// the program to run containers with, eg singularity (default: apptainer)
+	#[options(no_short, meta = "PROGRAM")]
+	apptainer: Option<String>,
+
+	/// allow containers to mount this host folder, or anything inside it (can be given more than once)
+	#[options(no_short, meta = "DIR")]
+	allow_bind: Vec<String>,
+
+	/// what to do with running processes when shutting down: detach (and leave them running), or terminate (default: detach)
+	#[options(no_short, meta = "POLICY")]
+	shutdown: Option<ShutdownPolicy>,
+
+	/// when shutting down, how long to wait for processes to exit after each signal, in seconds (default: 10)
+	#[options(no_short, meta = "SECS")]
+	shutdown_deadline: Option<u64>,
+
+	/// when shutting down, write the processes we detach from to this file, in the same format as the --state file
+	#[options(no_short, meta = "FILE")]
+	handoff: Option<String>,
+
+	/// write stats to this file periodically, in the Prometheus text format
+	#[options(no_short, meta = "FILE")]
+	metrics_file: Option<String>,
+
+	/// how often to write the metrics file, in seconds (default: 15)
+	#[options(no_short, meta = "SECS")]
+	metrics_interval: Option<u64>
+}
+
+impl Args {
+
+	/// Overrides the settings in the config file with any settings from the command line
+	fn apply(self, file: &mut ConfigFile) {
+
+		fn set<T>(dst: &mut Option<T>, src: Option<T>) {
+			if src.is_some() {
+				*dst = src;
+			}
+		}
+
+		set(&mut file.log.filter, self.log);
+		set(&mut file.log.file, self.log_file.map(PathBuf::from));
+		set(&mut file.socket.path, self.socket.map(PathBuf::from));
+		set(&mut file.socket.mode, self.socket_mode);
+		file.socket.allowed_uids.extend(self.allow_peer_uid);
+		file.socket.allowed_gids.extend(self.allow_peer_gid);
+		set(&mut file.socket.peer_policy, self.peer_policy);
+		set(&mut file.audit.file, self.audit_log.map(PathBuf::from));
+		set(&mut file.audit.max_size, self.audit_max_size);
+		set(&mut file.audit.generations, self.audit_generations);
+		set(&mut file.exec.cgroup, self.cgroup.map(PathBuf::from));
+		file.exec.allowed_uids.extend(self.allow_uid);
+		file.exec.allowed_gids.extend(self.allow_gid);
+		file.exec.allowed_programs.extend(self.allow_program);
+		set(&mut file.exec.default_dir, self.default_dir.map(PathBuf::from));
+		file.exec.env_clear |= self.env_clear;
+		file.exec.env_keep.extend(self.env_keep);
+		file.exec.env_remove.extend(self.env_remove);
+		set(&mut file.exec.state_file, self.state.map(PathBuf::from));
+		set(&mut file.exec.retention, self.retention);
+		set(&mut file.exec.output_budget, self.output_budget);
+		set(&mut file.exec.overflow, self.overflow);
+		set(&mut file.exec.stdin_window, self.stdin_window);
+		set(&mut file.exec.timeout_grace, self.timeout_grace);
+		set(&mut file.container.apptainer, self.apptainer);
+		file.container.allowed_binds.extend(self.allow_bind.into_iter().map(PathBuf::from));
+		set(&mut file.shutdown.policy, self.shutdown);
+		set(&mut file.shutdown.deadline, self.shutdown_deadline);
+		set(&mut file.shutdown.handoff_file, self.handoff.map(PathBuf::from));
+		set(&mut file.metrics.file, self.metrics_file.map(PathBuf::from));
+		set(&mut file.metrics.interval, self.metrics_interval);
+	}
 }
 
 
@@ -46,12 +242,37 @@ fn main() -> ExitCode {
 	// parse arguments
 	let args = Args::parse_args_default_or_exit();
 
+	// read the config file, if any
+	let mut file = match &args.config {
+		Some(path) => match ConfigFile::load(path) {
+			Ok(file) => file,
+			Err(e) => {
+				// NOTE: logging isn't ready yet
+				eprintln!("{}", e.deref().chain());
+				return ExitCode::FAILURE;
+			}
+		},
+		None => ConfigFile::default()
+	};
+	args.apply(&mut file);
+
 	// init logging
-	let Ok(_) = logging::init(&args.log)
+	let log = file.log.filter.clone()
+		.unwrap_or("host_processor=info".to_string());
+	let Ok(_) = logging::init(log, file.log.file.as_deref())
+		.log_err()
+		else { return ExitCode::FAILURE; };
+
+	let config = Config::from_file(file);
+
+	// look for systemd, before starting any threads
+	let notifier = Notifier::from_env();
+	let Ok(listener) = systemd::listen_fds()
+		.context("Failed to use socket from systemd")
 		.log_err()
 		else { return ExitCode::FAILURE; };
 
-	let Ok(_) = run()
+	let Ok(_) = run(config, listener, notifier)
 		.log_err()
 		else { return ExitCode::FAILURE; };
 
@@ -61,13 +282,13 @@ fn main() -> ExitCode {
 
 
 #[tracing::instrument(skip_all, level = 5, name = "HostProcessor")]
-fn run() -> Result<()> {
+fn run(config: Config, listener: Option<std::os::unix::net::UnixListener>, notifier: Notifier) -> Result<()> {
 
-	// build the socket path (in the current folder)
-	// NOTE: use a relative path instead of an absolute path, since limits on socket paths
-	//       in Linux are *FAR* less than limits on general paths (108 vs 255 bytes)
-	//       see: https://man7.org/linux/man-pages/man7/unix.7.html
-	let socket_path = PathBuf::from(format!("host-processor-{}", std::process::id()));
+	// NOTE: if systemd gave us the socket, then systemd owns the socket file too
+	let socket_path = match &listener {
+		Some(_) => None,
+		None => Some(config.socket_path.clone())
+	};
 
 	// WARNING: Now that we're listening on the socket, don't exit this function without cleaning it up.
 	//          That means no ? operator or any other kind of early returns.
@@ -81,8 +302,6 @@ fn run() -> Result<()> {
 		.log_err();
 	if let Ok(runtime) = result {
 
-		let socket_path = socket_path.clone();
-
 		debug!("Async runtime started");
 
 		// run the event loop on the async runtime
@@ -90,7 +309,7 @@ fn run() -> Result<()> {
 			.block_on(async move {
 				LocalSet::new().run_until(async move {
 
-					event_loop(socket_path).await
+					event_loop(config, listener, notifier).await
 
 				}).await
 			}.in_current_span())
@@ -104,25 +323,70 @@ fn run() -> Result<()> {
 	}
 
 	// try to cleanup the socket file
-	info!("Removing socket file: {}", socket_path.to_string_lossy());
-	fs::remove_file(&socket_path)
-		.context(format!("Failed to remove socket file: {}", socket_path.to_string_lossy()))
-		.warn_err()
-		.ok();
+	if let Some(socket_path) = socket_path {
+		info!("Removing socket file: {}", socket_path.to_string_lossy());
+		fs::remove_file(&socket_path)
+			.context(format!("Failed to remove socket file: {}", socket_path.to_string_lossy()))
+			.warn_err()
+			.ok();
+	}
 
 	Ok(())
 }
 
 
-async fn event_loop(socket_path: PathBuf) -> Result<()> {
+async fn event_loop(config: Config, listener: Option<std::os::unix::net::UnixListener>, notifier: Notifier) -> Result<()> {
 
-	// start listening on the socket
-	let socket = UnixListener::bind(&socket_path)
-		.context(format!("Failed to open unix socket at: {}", socket_path.to_string_lossy()))?;
-	info!("Opened socket: {}", socket_path.to_string_lossy());
+	// start listening on the socket, unless systemd is already listening for us
+	let socket = match listener {
+		Some(listener) => {
+			let socket = UnixListener::from_std(listener)
+				.context("Failed to use socket from systemd")?;
+			let path = socket.local_addr()
+				.ok()
+				.and_then(|addr| addr.as_pathname().map(|path| path.to_string_lossy().to_string()));
+			info!("Using socket from systemd: {}", path.as_deref().unwrap_or("(unnamed)"));
+			socket
+		}
+		None => {
+			let socket_path = &config.socket_path;
+			let socket = UnixListener::bind(socket_path)
+				.context(format!("Failed to open unix socket at: {}", socket_path.to_string_lossy()))?;
+			info!("Opened socket: {}", socket_path.to_string_lossy());
+			if let Some(mode) = config.socket_mode {
+				fs::set_permissions(socket_path, Permissions::from_mode(mode))
+					.context(format!("Failed to set socket permissions to {:o}", mode))?;
+			}
+			socket
+		}
+	};
 
 	// init state
-	let processes = Rc::new(Mutex::new(Processes::new()));
+	let config = Rc::new(config);
+	let processes = Rc::new(Mutex::new(Processes::new(config.retention, config.state_file.clone())));
+	let stats = Rc::new(Stats::new());
+
+	// open the audit log, if any
+	let audit_log = match &config.audit_log {
+		Some(log_file) => Some(
+			AuditLog::open(log_file)
+				.await
+				.context(format!("Failed to open audit log: {}", &log_file.path))?
+		),
+		None => None
+	};
+
+	// keep track of the tasks we'll need to wait for when shutting down
+	let shutdown = CancellationToken::new();
+	let tasks = TaskTracker::new();
+
+	// recover any persistent processes from a previous run
+	if let Some(path) = &config.state_file {
+		recover_processes(path, &processes, &shutdown, &tasks)
+			.await
+			.warn_err()
+			.ok();
+	}
 
 	// install signal handlers
 	let mut sigint = signal(SignalKind::interrupt())
@@ -130,7 +394,30 @@ async fn event_loop(socket_path: PathBuf) -> Result<()> {
 	let mut sigterm = signal(SignalKind::terminate())
 		.context("Failed to install SIGTERM handler")?;
 
+	// write the metrics file, if needed
+	if let Some(path) = config.metrics_file.clone() {
+		let interval = config.metrics_interval;
+		let processes = processes.clone();
+		let stats = stats.clone();
+		tokio::task::spawn_local(async move {
+			let mut interval = tokio::time::interval(interval);
+			loop {
+				interval.tick()
+					.await;
+				let live = processes.lock()
+					.await
+					.running()
+					.len();
+				stats::write_prometheus(&path, &stats.snapshot(live))
+					.context(format!("Failed to write metrics file: {}", path.to_string_lossy()))
+					.warn_err()
+					.ok();
+			}
+		}.in_current_span());
+	}
+
 	// listen for requests
+	notifier.ready();
 	loop {
 
 		tokio::select! {
@@ -142,11 +429,16 @@ async fn event_loop(socket_path: PathBuf) -> Result<()> {
 					.warn_err()
 					else { continue; };
 
+				let config = config.clone();
 				let processes = processes.clone();
 
+				let audit_log = audit_log.clone();
+				let shutdown = shutdown.clone();
+				let stats = stats.clone();
+
 				// drive the connection in a new task
-				tokio::task::spawn_local(async move {
-					drive_connection(conn, processes)
+				tasks.spawn_local(async move {
+					drive_connection(conn, config, processes, stats, audit_log, shutdown)
 						.await
 				}.in_current_span());
 			}
@@ -163,44 +455,240 @@ async fn event_loop(socket_path: PathBuf) -> Result<()> {
 		}
 	}
 
+	// stop accepting connections
+	notifier.stopping();
+	drop(socket);
+
+	shutdown_processes(&config, &processes, &shutdown, &tasks)
+		.await;
+
+	Ok(())
+}
+
+
+/// Terminates or detaches from the running processes, according to the shutdown policy,
+/// then waits for the connections to send their last events
+async fn shutdown_processes(config: &Config, processes: &Rc<Mutex<Processes>>, shutdown: &CancellationToken, tasks: &TaskTracker) {
+
+	match config.shutdown_policy {
+
+		ShutdownPolicy::Terminate => {
+
+			let pids = processes.lock()
+				.await
+				.running();
+			info!("Terminating {} running process(es) ...", pids.len());
+
+			// NOTE: the processes exit normally from the point of view of their Exec requests, so clients get their Fins
+			let responses = future::join_all(pids.iter()
+				.map(|pid| kill_gracefully(processes, *pid, config.shutdown_deadline))
+			).await;
+			for (pid, response) in pids.into_iter().zip(responses) {
+				match response {
+					KillGracefullyResponse::Failure(failure) => warn!(pid, "Failed to stop process: {}", failure.reason),
+					response => debug!(pid, ?response, "Stopped process")
+				}
+			}
+		}
+
+		ShutdownPolicy::Detach => {
+			if let Some(path) = &config.handoff_file {
+				let processes = processes.lock()
+					.await;
+				info!("Handing off {} running process(es) to: {}", processes.running().len(), path.to_string_lossy());
+				processes.write_handoff(path)
+					.warn_err()
+					.ok();
+			}
+		}
+	}
+
+	// detach from any processes that are still running, and stop reading requests
+	shutdown.cancel();
+
+	// wait for the connections to send their last events
+	tasks.close();
+	if tokio::time::timeout(config.shutdown_deadline, tasks.wait()).await.is_err() {
+		warn!("Timed out waiting for connections to finish");
+	}
+}
+
+
+async fn recover_processes(state_path: &Path, processes: &Rc<Mutex<Processes>>, shutdown: &CancellationToken, tasks: &TaskTracker) -> Result<()> {
+
+	let entries = Processes::load_state(state_path)?;
+
+	let mut procs = processes.lock()
+		.await;
+	for (pid, start_time) in entries {
+
+		// skip processes that have since exited (or whose pid was reused by some other process)
+		if processes::read_start_time(pid) != Some(start_time) {
+			info!(pid, "Persistent process from previous run has exited");
+			OutputFifo::remove(state_path, pid);
+			continue;
+		}
+
+		info!(pid, "Recovered persistent process from previous run");
+		let attachment = Rc::new(Mutex::new(Attachment::new(None)));
+		procs.add_recovered(pid, start_time, attachment.clone());
+
+		// resume streaming the outputs that went through named pipes, if any
+		let mut outputs = StreamMap::new();
+		for kind in [ConsoleKind::Stdout, ConsoleKind::Stderr] {
+			match OutputFifo::open(state_path, pid, kind) {
+				Ok(Some(reader)) => {
+					outputs.insert(kind, ReaderStream::new(reader));
+				}
+				Ok(None) => (),
+				Err(e) => warn!(pid, "Failed to open named pipe for {}: {}", kind.name(), e)
+			}
+		}
+
+		// the process isn't our child anymore, so we can't wait() for it: poll instead
+		tasks.spawn_local({
+			let processes = processes.clone();
+			let shutdown = shutdown.clone();
+			let state_path = state_path.to_path_buf();
+			async move {
+
+				let sink = EventSink::Persistent(attachment.clone());
+				loop {
+					tokio::select! {
+						next = outputs.next(), if !outputs.is_empty() => match next {
+							Some((kind, Ok(chunk))) => {
+								send_console(&sink, kind, &chunk, None)
+									.await
+									.ok();
+							}
+							Some((kind, Err(e))) => warn!(pid, "Failed to read from {}: {}", kind.name(), e.into_chain()),
+							// both outputs closed
+							None => ()
+						},
+						// NOTE: read the rest of the outputs before finishing
+						_ = tokio::time::sleep(Duration::from_secs(1)), if outputs.is_empty() => {
+							if processes::read_start_time(pid) != Some(start_time) {
+								break;
+							}
+						}
+						_ = shutdown.cancelled() => {
+							trace!(pid, "Detaching from recovered process");
+							send_persistent(&mut *attachment.lock().await, ProcessEvent::Detached)
+								.await;
+							return;
+						}
+					}
+				}
+
+				// and we have no idea how it exited
+				trace!(pid, "Recovered process exited");
+				processes.lock()
+					.await
+					.finish(pid, None, None, None);
+				OutputFifo::remove(&state_path, pid);
+				finish_persistent(&attachment, Some(ProcessEvent::Fin {
+					exit_code: None,
+					limit: None,
+					stages: Vec::new()
+				}))
+					.await;
+			}.in_current_span()
+		});
+	}
+
 	Ok(())
 }
 
 
-#[tracing::instrument(skip_all, level = 5, name = "Connection", fields(id))]
-async fn drive_connection(socket: UnixStream, processes: Rc<Mutex<Processes>>) {
+#[tracing::instrument(skip_all, level = 5, name = "Connection", fields(id, uid, gid, pid))]
+async fn drive_connection(socket: UnixStream, config: Rc<Config>, processes: Rc<Mutex<Processes>>, stats: Rc<Stats>, audit_log: Option<AuditLog>, shutdown: CancellationToken) {
 
 	// assign an id to the connection so we can make sense of the log entries
 	let id = rand::random::<u32>();
 	tracing::Span::current().record("id", id);
+
+	// find out who's on the other end
+	let Ok(peer) = socket.peer_cred()
+		.context("Failed to read peer credentials")
+		.warn_err()
+		else { return; };
+	tracing::Span::current()
+		.record("uid", peer.uid())
+		.record("gid", peer.gid());
+	if let Some(pid) = peer.pid() {
+		tracing::Span::current().record("pid", pid);
+	}
 	debug!("open");
 
+	// check the peer against the allow-lists
+	if !peers::is_allowed(&config, &peer) {
+		match config.peer_policy {
+			PeerPolicy::Refuse => {
+				warn!("Refused connection from peer that isn't allowed");
+				return;
+			}
+			PeerPolicy::Log => warn!("Accepted connection from peer that isn't allowed")
+		}
+	}
+
+	let _connection = stats.connection();
+
 	// split the socket into read and write halves so we can operate them concurrently
-	let (mut socket_read, socket_write) = socket.into_split();
+	let (socket_read, socket_write) = socket.into_split();
 	let socket_write = Rc::new(Mutex::new(socket_write));
 
+	// accept file descriptors sent with requests too
+	let mut socket_read = FdReader::new(socket_read);
+
+	// send process events in a separate task, so processes can take turns
+	let outbox = Outbox::new();
+	let outbox_task = tokio::task::spawn_local({
+		let outbox = outbox.clone();
+		let socket_write = socket_write.clone();
+		async move {
+			while let Some((request_id, event)) = outbox.next().await {
+				let Ok(_) = write_response(&socket_write, request_id, Response::ProcessEvent(event))
+					.await
+					else { break; };
+			}
+			outbox.close();
+		}.in_current_span()
+	});
+
+	// acked stdin writes share one window per connection
+	let stdin_window = StdinWindow::new(config.stdin_window);
+
 	let mut next_request_id: u64 = 1;
+	let requests = TaskTracker::new();
 
 	loop {
 
 		// wait for a request
-		let msg = match socket_read.read_framed().await {
+		let (msg, fds) = tokio::select! {
 
-			// got a message!
-			Ok(Some(msg)) => msg,
+			result = socket_read.read_framed() => match result {
 
-			// client closed the connection)
-			Ok(None) => {
-				debug!("socket closed by remote");
-				return;
-			}
+				// got a message!
+				Ok(Some(msg)) => (msg, socket_read.take_fds()),
 
-			// some other error
-			r => {
-				r.context("Failed to read request")
-					.warn_err()
-					.ok();
-				return;
+				// client closed the connection)
+				Ok(None) => {
+					debug!("socket closed by remote");
+					break;
+				}
+
+				// some other error
+				r => {
+					r.context("Failed to read request")
+						.warn_err()
+						.ok();
+					break;
+				}
+			},
+
+			_ = shutdown.cancelled() => {
+				debug!("shutting down");
+				break;
 			}
 		};
 
@@ -210,9 +698,15 @@ async fn drive_connection(socket: UnixStream, processes: Rc<Mutex<Processes>>) {
 		let _span = error_span!("Request", id = request_id).entered();
 
 		// process the request in a task, so other requests on this connection can happen concurrently
-		tokio::task::spawn_local({
+		requests.spawn_local({
+			let config = config.clone();
 			let processes = processes.clone();
 			let socket_write = socket_write.clone();
+			let outbox = outbox.clone();
+			let audit_log = audit_log.clone();
+			let shutdown = shutdown.clone();
+			let stats = stats.clone();
+			let stdin_window = stdin_window.clone();
 			async move {
 
 				trace!("started");
@@ -221,6 +715,8 @@ async fn drive_connection(socket: UnixStream, processes: Rc<Mutex<Processes>>) {
 					Ok(r) => r,
 					Err((e, request_id)) => {
 
+						stats.decode_error();
+
 						// log the error internally
 						Err::<(),_>(e)
 							.context("Failed to decode request")
@@ -232,7 +728,8 @@ async fn drive_connection(socket: UnixStream, processes: Rc<Mutex<Processes>>) {
 						let request_id = request_id.unwrap_or(0);
 
 						// send an error response back to the client
-						write_response(&socket_write, request_id, Response::Error {
+						let audit = AuditRecord::invalid(audit_log, id, &peer, request_id);
+						write_audited_response(&socket_write, request_id, &audit, Response::Error {
 							reason: "Failed to decode request".to_string()
 						})
 							.await
@@ -240,6 +737,7 @@ async fn drive_connection(socket: UnixStream, processes: Rc<Mutex<Processes>>) {
 						return;
 					}
 				};
+				let audit = AuditRecord::new(audit_log, id, &peer, request.id, &request.request);
 
 				// dispatch the request
 				match request.request {
@@ -249,23 +747,51 @@ async fn drive_connection(socket: UnixStream, processes: Rc<Mutex<Processes>>) {
 							.await,
 
 					Request::Exec(exec) =>
-						dispatch_exec(socket_write, request.id, processes, exec)
+						dispatch_exec(socket_write, outbox, request.id, id, &audit, config, processes, stats, shutdown, exec, fds)
+							.await,
+
+					Request::ExecPipeline(pipeline) =>
+						dispatch_exec_pipeline(socket_write, outbox, request.id, id, &audit, config, processes, stats, shutdown, pipeline, fds)
 							.await,
 
 					Request::Status { pid } =>
 						dispatch_status(socket_write, request.id, processes, pid)
 							.await,
 
-					Request::WriteStdin { pid, chunk } =>
-						dispatch_write_stdin(processes, pid, chunk)
+					Request::Wait { pid, timeout } =>
+						dispatch_wait(socket_write, request.id, processes, shutdown, pid, timeout)
+							.await,
+
+					Request::Attach { pid } =>
+						dispatch_attach(socket_write, request.id, &audit, processes, pid)
+							.await,
+
+					Request::ListProcesses =>
+						dispatch_list_processes(socket_write, request.id, processes)
+							.await,
+
+					Request::WriteStdin { pid, chunk, ack } =>
+						dispatch_write_stdin(socket_write, request.id, processes, stdin_window, pid, chunk, ack)
+							.await,
+
+					Request::CloseStdin { pid, ack } =>
+						dispatch_close_stdin(socket_write, request.id, processes, pid, ack)
+							.await,
+
+					Request::ResizePty { pid, rows, cols } =>
+						dispatch_resize_pty(processes, pid, rows, cols)
 							.await,
 
-					Request::CloseStdin { pid } =>
-						dispatch_close_stdin(processes, pid)
+					Request::Stats =>
+						dispatch_stats(socket_write, request.id, processes, stats)
 							.await,
 
 					Request::Kill { signal, pid, process_group } =>
-						dispatch_kill(processes, signal, pid, process_group)
+						dispatch_kill(socket_write, request.id, &audit, processes, signal, pid, process_group)
+							.await,
+
+					Request::KillGracefully { pid, timeout } =>
+						dispatch_kill_gracefully(socket_write, request.id, &audit, processes, pid, timeout)
 							.await,
 
 					Request::Username { uid } =>
@@ -289,10 +815,46 @@ async fn drive_connection(socket: UnixStream, processes: Rc<Mutex<Processes>>) {
 							.await
 				}
 
+				audit.finish()
+					.await;
+
 				trace!("complete");
 			}.in_current_span()
 		});
 	}
+
+	if shutdown.is_cancelled() {
+		// let the requests finish, and send their last events, before hanging up
+		requests.close();
+		requests.wait()
+			.await;
+		outbox.finish();
+		outbox_task.await
+			.ok();
+	}
+
+	// stop sending process events to this connection
+	outbox.close();
+}
+
+
+/// Sends back the reason an Exec request didn't launch a process
+async fn write_exec_failure(socket: &Mutex<OwnedWriteHalf>, request_id: u32, audit: &AuditRecord, stats: &Stats, reason: String) {
+	stats.exec_failure();
+	write_audited_response(socket, request_id, audit, Response::Exec(ExecResponse::Failure {
+		reason
+	}))
+		.await
+		.ok();
+}
+
+
+/// Writes the response, after recording its outcome in the audit log
+async fn write_audited_response(socket: &Mutex<OwnedWriteHalf>, request_id: u32, audit: &AuditRecord, response: Response) -> Result<(),()> {
+	audit.response(&response)
+		.await;
+	write_response(socket, request_id, response)
+		.await
 }
 
 
@@ -319,6 +881,85 @@ async fn write_response(socket: &Mutex<OwnedWriteHalf>, request_id: u32, respons
 }
 
 
+/// Where to send the events for an exec'd process
+enum EventSink {
+
+	/// to the connection that launched the process, until that connection goes away
+	Connection(OutputQueue),
+
+	/// to whichever connection is attached, buffering events when none are
+	Persistent(Rc<Mutex<Attachment>>)
+}
+
+impl EventSink {
+
+	/// returns Err if no one will ever receive the event
+	async fn send(&self, event: ProcessEvent) -> Result<(),()> {
+		match self {
+
+			Self::Connection(queue) =>
+				queue.send(event)
+					.await,
+
+			Self::Persistent(attachment) => {
+				send_persistent(&mut *attachment.lock().await, event)
+					.await;
+				Ok(())
+			}
+		}
+	}
+}
+
+
+async fn send_persistent(attachment: &mut Attachment, event: ProcessEvent) {
+	if let Some((socket, request_id)) = &attachment.socket {
+		if write_response(socket, *request_id, Response::ProcessEvent(event.clone())).await.is_ok() {
+			return;
+		}
+		// the connection went away, so stop sending to it
+		debug!("Persistent process detached");
+		attachment.socket = None;
+	}
+	attachment.buffer(event);
+}
+
+
+/// Sends console output as a raw chunk, or as lines, if needed
+async fn send_console(sink: &EventSink, kind: ConsoleKind, chunk: &[u8], lines: Option<&mut LineFramer>) -> Result<(),()> {
+	match lines {
+		None => sink.send(ProcessEvent::Console {
+			kind,
+			chunk: chunk.to_vec()
+		}).await,
+		Some(lines) => {
+			for event in lines.push(chunk) {
+				sink.send(event)
+					.await?;
+			}
+			Ok(())
+		}
+	}
+}
+
+
+/// Sends the final event, if any, for a persistent process.
+/// The process stays tracked after its retention window until an attached connection receives everything.
+async fn finish_persistent(attachment: &Mutex<Attachment>, fin: Option<ProcessEvent>) {
+
+	let mut attachment = attachment.lock()
+		.await;
+	if let Some(fin) = fin {
+		send_persistent(&mut attachment, fin)
+			.await;
+	}
+	attachment.closed = true;
+
+	if !attachment.is_done() {
+		trace!("Keeping persistent process events for a later Attach");
+	}
+}
+
+
 #[tracing::instrument(skip_all, level = 5, name = "Ping")]
 async fn dispatch_ping(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32) {
 
@@ -331,147 +972,546 @@ async fn dispatch_ping(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32) {
 }
 
 
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(skip_all, level = 5, name = "Exec", fields(pid))]
-async fn dispatch_exec(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, processes: Rc<Mutex<Processes>>, request: ExecRequest) {
+async fn dispatch_exec(socket: Rc<Mutex<OwnedWriteHalf>>, outbox: Outbox, request_id: u32, connection_id: u32, audit: &AuditRecord, config: Rc<Config>, processes: Rc<Mutex<Processes>>, stats: Rc<Stats>, shutdown: CancellationToken, request: ExecRequest, fds: Vec<OwnedFd>) {
 
 	trace!("Request: {:?}", &request);
 
-	// figure out the start folder
-	let dir = match request.dir {
-		Some(dir) => PathBuf::from(dir),
-		None => env::current_dir()
-			.unwrap_or(PathBuf::from("."))
-	};
+	exec(socket, outbox, request_id, connection_id, audit, config, processes, stats, shutdown, request, None, fds)
+		.await;
+}
 
-	// open files for stdout,stderr if needed
-	let mut stdout_file = match &request.stdout {
-		ExecStdout::Write { path } => {
-			match File::create(path).await {
-				Ok(f) => Some(f),
-				Err(e) => {
-					// send back the error
-					write_response(&socket, request_id, Response::Exec(ExecResponse::Failure {
-						reason: format!("Failed to open file for stdout: {}", e.chain())
-					}))
-						.await
-						.ok();
-					return;
-				}
-			}
-		}
-		_ => None
-	};
-	let mut stderr_file = match &request.stderr {
-		ExecStderr::Write { path } => {
-			match File::create(path).await {
-				Ok(f) => Some(f),
-				Err(e) => {
-					// send back the error
-					write_response(&socket, request_id, Response::Exec(ExecResponse::Failure {
-						reason: format!("Failed to open file for stderr: {}", e.chain())
-					}))
-						.await
-						.ok();
-					return;
-				}
-			}
-		}
-		_ => None
-	};
 
-	// spawn the process
-	let result = Command::new(&request.program)
-		.args(request.args)
-		.current_dir(&dir)
-		.envs(request.envvars)
-		.stdin(match &request.stdin {
-			ExecStdin::Stream => Stdio::piped(),
-			ExecStdin::Ignore => Stdio::null()
-		})
-		.stdout(match &request.stdout {
-			ExecStdout::Stream => Stdio::piped(),
-			ExecStdout::Write { .. } => Stdio::piped(),
-			ExecStdout::Log => Stdio::piped(),
-			ExecStdout::Ignore => Stdio::null()
-		})
-		.stderr(match &request.stderr {
-			ExecStderr::Stream => Stdio::piped(),
-			ExecStderr::Write { .. } => Stdio::piped(),
-			ExecStderr::Merge => Stdio::piped(),
-			ExecStderr::Log => Stdio::piped(),
-			ExecStderr::Ignore => Stdio::null()
-		})
-		.process_group(0) // start a new process group for this process and all its subprocesses
-		.spawn()
-		.context(format!("Failed to spawn process: program=\"{}\", dir={:?}", &request.program, &dir))
-		.and_then(|proc| {
-			// lookup the pid, if any
-			// NOTE: the PID here is also the PGID, since we created a new process group
-			match proc.id() {
-				Some(pid) => Ok((proc, pid)),
-				None => Err(anyhow!("Spawned process had no pid"))
-			}
-		});
-	let (mut proc, pid) = match result {
-		Ok(p) => p,
-		Err(e) => {
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip_all, level = 5, name = "ExecPipeline", fields(pid))]
+async fn dispatch_exec_pipeline(socket: Rc<Mutex<OwnedWriteHalf>>, outbox: Outbox, request_id: u32, connection_id: u32, audit: &AuditRecord, config: Rc<Config>, processes: Rc<Mutex<Processes>>, stats: Rc<Stats>, shutdown: CancellationToken, request: ExecPipelineRequest, fds: Vec<OwnedFd>) {
 
-			// send back the error
-			write_response(&socket, request_id, Response::Exec(ExecResponse::Failure {
-				reason: format!("Failed to start process: {}", e.deref().chain())
-			}))
-				.await
-				.ok();
+	trace!("Request: {:?}", &request);
 
+	let mut stages = request.stages.into_iter();
+	let Some(first) = stages.next()
+		else {
+			write_exec_failure(&socket, request_id, audit, &stats, "Pipeline has no stages".to_string())
+				.await;
 			return;
-		}
+		};
+	if matches!(request.stdout, ExecStdout::Pty) {
+		write_exec_failure(&socket, request_id, audit, &stats, "Pipelines can't use a pseudo-terminal".to_string())
+			.await;
+		return;
+	}
+
+	// the first stage launches like any other Exec, with the settings for the whole pipeline,
+	// and the rest of the stages follow it
+	let exec_request = ExecRequest {
+		program: first.program,
+		args: first.args,
+		dir: request.dir,
+		envvars: first.envvars,
+		stdin: request.stdin,
+		stdout: request.stdout,
+		stderr: request.stderr,
+		stream_fin: request.stream_fin,
+		limits: request.limits,
+		uid: request.uid,
+		gid: request.gid,
+		gids: request.gids,
+		persist: false,
+		lines: request.lines,
+		env: request.env,
+		container: None
 	};
+	exec(socket, outbox, request_id, connection_id, audit, config, processes, stats, shutdown, exec_request, Some(stages.collect()), fds)
+		.await;
+}
 
-	tracing::Span::current().record("pid", pid);
-	trace!("Spawned process");
 
-	// track the process for later
-	processes.lock()
-		.await
-		.add(pid, &mut proc);
+/// Launches the process for an Exec request, followed by the rest of the stages for an ExecPipeline request,
+/// then streams its events until it exits
+#[allow(clippy::too_many_arguments)]
+async fn exec(socket: Rc<Mutex<OwnedWriteHalf>>, outbox: Outbox, request_id: u32, connection_id: u32, audit: &AuditRecord, config: Rc<Config>, processes: Rc<Mutex<Processes>>, stats: Rc<Stats>, shutdown: CancellationToken, mut request: ExecRequest, pipeline: Option<Vec<PipelineStage>>, fds: Vec<OwnedFd>) {
+
+	let rest = pipeline.as_deref()
+		.unwrap_or_default();
+
+	// match up the file descriptors sent with the request, if any
+	let mut stdio_fds = match StdioFds::assign(&request, fds) {
+		Ok(f) => f,
+		Err(reason) => {
+			// send back the error
+			write_exec_failure(&socket, request_id, audit, &stats, reason)
+				.await;
+			return;
+		}
+	};
+	// route the streamed outputs of persistent processes through named pipes, if we can recover them after a restart,
+	// so the process can keep writing them after we exit
+	let state_path = config.state_file.as_deref()
+		.filter(|_| request.persist);
+	let stdout_fifo = state_path
+		.filter(|_| matches!(request.stdout, ExecStdout::Stream))
+		.map(|state_path| OutputFifo::create(state_path, ConsoleKind::Stdout))
+		.transpose();
+	let stderr_fifo = state_path
+		.filter(|_| matches!(request.stderr, ExecStderr::Stream))
+		.map(|state_path| OutputFifo::create(state_path, ConsoleKind::Stderr))
+		.transpose();
+	let (mut stdout_fifo, mut stderr_fifo) = match (stdout_fifo, stderr_fifo) {
+		(Ok(stdout), Ok(stderr)) => (stdout, stderr),
+		(Err(e), _) | (_, Err(e)) => {
+			// send back the error
+			write_exec_failure(&socket, request_id, audit, &stats, format!("Failed to create named pipe for output: {}", e))
+				.await;
+			return;
+		}
+	};
+	// NOTE: the process writes to the named pipes like to any other file descriptor
+	if let Some(fifo) = &mut stdout_fifo {
+		stdio_fds.stdout = fifo.writer.take();
+	}
+	if let Some(fifo) = &mut stderr_fifo {
+		stdio_fds.stderr = fifo.writer.take();
+	}
+
+	// NOTE: every stage of a pipeline writes to the same stderr
+	let mut stderr_fds = match stdio_fds.take_stderr(1 + rest.len()) {
+		Ok(f) => f.into_iter(),
+		Err(e) => {
+			// send back the error
+			write_exec_failure(&socket, request_id, audit, &stats, format!("Failed to copy stderr file descriptor: {}", e))
+				.await;
+			return;
+		}
+	};
+
+	// check the user and groups, if any
+	let privileges = match Privileges::from_request(&config, &request) {
+		Ok(p) => p,
+		Err(e) => {
+			// send back the error
+			write_exec_failure(&socket, request_id, audit, &stats, format!("Failed to change user: {}", e.deref().chain()))
+				.await;
+			return;
+		}
+	};
+
+	// figure out the start folder
+	let dir = match (request.dir, &config.default_dir) {
+		(Some(dir), _) => PathBuf::from(dir),
+		(None, Some(dir)) => dir.clone(),
+		(None, None) => env::current_dir()
+			.unwrap_or(PathBuf::from("."))
+	};
+
+	// figure out which of our environment variables the process gets
+	let inherited_env = environment::inherited(&config.env, request.env.as_ref());
+
+	// wrap the program in an apptainer command line, if it runs in a container
+	let container = match &request.container {
+		Some(container) => match config.container.command(container, &request.program, &request.args, &dir) {
+			Ok(c) => Some(c),
+			Err(reason) => {
+				warn!(target: "host_processor::audit", program = request.program, image = container.image, reason, "Exec denied");
+				write_exec_failure(&socket, request_id, audit, &stats, reason)
+					.await;
+				return;
+			}
+		},
+		None => None
+	};
+	let (program_name, args) = match &container {
+		Some(container) => (&container.program, &container.args),
+		None => (&request.program, &request.args)
+	};
+
+	// check the programs against the policy, if any
+	// NOTE: for containers, that's apptainer itself
+	let program = check_program(&config, program_name, args, &request.envvars, &inherited_env, &dir);
+	let rest_programs = rest.iter()
+		.map(|stage| check_program(&config, &stage.program, &stage.args, &stage.envvars, &inherited_env, &dir))
+		.collect::<Result<Vec<_>,_>>();
+	let (program, rest_programs) = match (program, rest_programs) {
+		(Ok(program), Ok(rest_programs)) => (program, rest_programs),
+		(Err(reason), _) | (_, Err(reason)) => {
+			write_exec_failure(&socket, request_id, audit, &stats, reason)
+				.await;
+			return;
+		}
+	};
+
+	// open files for stdin,stdout,stderr if needed
+	let stdin_file = match &request.stdin {
+		ExecStdin::File { path } => match tokio::fs::File::open(path).await {
+			Ok(f) => Some(OwnedFd::from(f.into_std().await)),
+			Err(e) => {
+				// send back the error
+				write_exec_failure(&socket, request_id, audit, &stats, format!("Failed to open file for stdin: {}: {}", path, e))
+					.await;
+				return;
+			}
+		},
+		_ => None
+	};
+	let stdout_file = match &request.stdout {
+		ExecStdout::Write { path } => Some(LogFileWriter::create(path).await),
+		ExecStdout::LogFile(log_file) => Some(LogFileWriter::open(log_file).await),
+		_ => None
+	};
+	let mut stdout_file = match stdout_file.transpose() {
+		Ok(f) => f,
+		Err(e) => {
+			// send back the error
+			write_exec_failure(&socket, request_id, audit, &stats, format!("Failed to open file for stdout: {}", e.chain()))
+				.await;
+			return;
+		}
+	};
+	let stderr_file = match &request.stderr {
+		ExecStderr::Write { path } => Some(LogFileWriter::create(path).await),
+		ExecStderr::LogFile(log_file) => Some(LogFileWriter::open(log_file).await),
+		_ => None
+	};
+	let mut stderr_file = match stderr_file.transpose() {
+		Ok(f) => f,
+		Err(e) => {
+			// send back the error
+			write_exec_failure(&socket, request_id, audit, &stats, format!("Failed to open file for stderr: {}", e.chain()))
+				.await;
+			return;
+		}
+	};
+
+	// check the limits, if any
+	let limits = limits::with_defaults(request.limits.clone(), &config.default_limits);
+	if let Err(e) = limits::check_rlimits(&limits) {
+		// send back the error
+		write_exec_failure(&socket, request_id, audit, &stats, format!("Invalid limits: {}", e.deref().chain()))
+			.await;
+		return;
+	}
+
+	// make a cgroup for the process, if needed
+	let cgroup = match (&limits.cgroup, &config.cgroup) {
+		(Some(cgroup_limits), Some(cgroup_dir)) =>
+			Cgroup::create(cgroup_dir, format!("exec-{}", rand::random::<u32>()), cgroup_limits)
+				.map(Some),
+		(Some(_), None) => Err(anyhow!("cgroup limits were requested, but no cgroup folder was configured")),
+		(None, _) => Ok(None)
+	};
+	let cgroup = match cgroup {
+		Ok(c) => c,
+		Err(e) => {
+			// send back the error
+			write_exec_failure(&socket, request_id, audit, &stats, format!("Failed to apply cgroup limits: {}", e.deref().chain()))
+				.await;
+			return;
+		}
+	};
+
+	// NOTE: cgroup is created now, don't exit this fn without cleaning it up
+
+	// open a pseudo-terminal, if needed
+	let pty = match &request.stdout {
+		ExecStdout::Pty => PtyStdio::open(&request.stdin, &request.stderr)
+			.map(Some),
+		_ => Ok(None)
+	};
+	let mut pty = match pty {
+		Ok(p) => p,
+		Err(e) => {
+
+			// send back the error
+			write_exec_failure(&socket, request_id, audit, &stats, format!("Failed to open pseudo-terminal: {}", e.chain()))
+				.await;
+
+			if let Some(cgroup) = cgroup {
+				cgroup.remove()
+					.warn_err()
+					.ok();
+			}
+
+			return;
+		}
+	};
+
+	// spawn the process
+	// NOTE: run the program we checked, with the name the policy allowed it by in argv[0] (or the name the request asked for, without a policy)
+	let mut cmd = Command::new(&program.path);
+	cmd.arg0(&program.arg0);
+	cmd.args(args)
+		.current_dir(&dir)
+		.env_clear()
+		.envs(inherited_env.iter().map(|(name, value)| (name, value)))
+		.envs(request.envvars)
+		.envs(container.iter().flat_map(|container| container.envvars.iter().cloned()))
+		.stdin(match (&request.stdin, pty.as_mut().and_then(|pty| pty.stdin.take()).or(stdio_fds.stdin.take()).or(stdin_file)) {
+			(_, Some(fd)) => Stdio::from(fd),
+			(ExecStdin::Stream | ExecStdin::Bytes(..), None) => Stdio::piped(),
+			(ExecStdin::Ignore | ExecStdin::Fd | ExecStdin::File { .. }, None) => Stdio::null()
+		})
+		.stdout(match rest.is_empty() {
+			true => stdout_stdio(&request.stdout, pty.as_mut().and_then(|pty| pty.stdout.take()).or(stdio_fds.stdout.take())),
+			// the next stage in the pipeline reads it
+			false => Stdio::piped()
+		})
+		.stderr(stderr_stdio(&request.stderr, pty.as_mut().and_then(|pty| pty.stderr.take()).or(stderr_fds.next())));
+	if pty.is_none() {
+		// start a new process group for this process and all its subprocesses
+		// NOTE: processes in a pseudo-terminal get a new session (and process group) in pre_exec instead
+		cmd.process_group(0);
+	}
+	let procs_fd = cgroup.as_ref()
+		.map(|cgroup| cgroup.procs_fd());
+	prepare_process(&mut cmd, procs_fd, &limits, privileges.clone(), pty.is_some());
+	// NOTE: spawn with std's Command, rather than tokio's, since we reap the process ourselves with wait::wait(),
+	//       and tokio would try to reap its Child again when it's dropped, possibly reaping a different process that reused the pid
+	let result = cmd.spawn()
+		.context(format!("Failed to spawn process: program=\"{}\", dir={:?}", &request.program, &dir));
+
+	let started = Instant::now();
+
+	// close our copies of the terminal, if any, so we can tell when the process closes it
+	drop(cmd);
+	let pty = pty.map(|pty| pty.master);
+
+	let proc = match result {
+		Ok(p) => p,
+		Err(e) => {
+
+			// send back the error
+			write_exec_failure(&socket, request_id, audit, &stats, format!("Failed to start process: {}", e.deref().chain()))
+				.await;
+
+			if let Some(cgroup) = cgroup {
+				cgroup.remove()
+					.warn_err()
+					.ok();
+			}
+
+			return;
+		}
+	};
+
+	// NOTE: the PID here is also the PGID, since we created a new process group
+	let pid = proc.id();
+	tracing::Span::current().record("pid", pid);
+	trace!("Spawned process");
+
+	// spawn the rest of the pipeline, if any, each stage reading the output of the one before
+	// NOTE: the PID of the first stage is also the PGID of the whole pipeline
+	let mut procs = vec![proc];
+	let mut result = Ok(());
+	for (stage, program) in rest.iter().zip(rest_programs) {
+		let stage_stdin = procs.last_mut()
+			.and_then(|proc| proc.stdout.take());
+		let is_last = procs.len() == rest.len();
+		let stage_stdout = match is_last {
+			true => stdio_fds.stdout.take(),
+			false => None
+		};
+		let mut cmd = Command::new(&program.path);
+		cmd.arg0(&program.arg0);
+		cmd.args(&stage.args)
+			.current_dir(&dir)
+			.env_clear()
+			.envs(inherited_env.iter().map(|(name, value)| (name, value)))
+			.envs(stage.envvars.iter().cloned())
+			.stdin(match stage_stdin {
+				Some(stdout) => Stdio::from(stdout),
+				None => Stdio::null()
+			})
+			.stdout(match is_last {
+				true => stdout_stdio(&request.stdout, stage_stdout),
+				false => Stdio::piped()
+			})
+			.stderr(stderr_stdio(&request.stderr, stderr_fds.next()))
+			.process_group(pid as i32);
+		prepare_process(&mut cmd, procs_fd, &limits, privileges.clone(), false);
+		match cmd.spawn() {
+			Ok(proc) => procs.push(proc),
+			Err(e) => {
+				result = Err(anyhow!(e)
+					.context(format!("Failed to spawn pipeline process: program=\"{}\", dir={:?}", &stage.program, &dir)));
+				break;
+			}
+		}
+	}
+	let pids = procs.iter()
+		.map(|proc| proc.id())
+		.collect::<Vec<_>>();
+
+	// hand the pipes we talk to over to the async runtime
+	let pipes = match result.and_then(|()| ProcPipes::take(&mut procs)) {
+		Ok(p) => p,
+		Err(e) => {
+
+			// send back the error
+			write_exec_failure(&socket, request_id, audit, &stats, format!("Failed to start process: {}", e.deref().chain()))
+				.await;
+
+			// stop the processes that already started
+			signals::send(pid, KillSignal::Kill, true)
+				.context("Failed to stop process group")
+				.warn_err()
+				.ok();
+			for pid in pids {
+				wait::wait(pid)
+					.await
+					.warn_err()
+					.ok();
+			}
+
+			if let Some(cgroup) = cgroup {
+				cgroup.remove()
+					.warn_err()
+					.ok();
+			}
+
+			return;
+		}
+	};
+	let ProcPipes { stdin: mut proc_stdin, stdout: proc_stdout, stderrs: proc_stderrs } = pipes;
+	drop(procs);
+	stats.launched();
+
+	// write the inline stdin, if any, then close it
+	// NOTE: take the bytes out of the request, so ListProcesses doesn't send them back to everyone
+	if let ExecStdin::Bytes(bytes) = &mut request.stdin {
+		let bytes = mem::take(bytes);
+		if let Some(mut proc_stdin) = proc_stdin.take() {
+			tokio::task::spawn_local(async move {
+				// NOTE: the process doesn't have to read all of it, so this isn't worth a warning
+				if let Err(e) = proc_stdin.write_all(&bytes).await {
+					debug!("Failed to write inline stdin: {}", e);
+				}
+			}.in_current_span());
+		}
+	}
+
+	// track the process for later
+	let launch = Launch {
+		program: request.program.clone(),
+		args: request.args.clone(),
+		dir,
+		stdio: ProcessStdio {
+			stdin: request.stdin.clone(),
+			stdout: request.stdout.clone(),
+			stderr: request.stderr.clone()
+		},
+		connection: connection_id
+	};
+	let stdin = match &pty {
+		Some(master) if matches!(request.stdin, ExecStdin::Stream) => Some(ProcStdin::Pty(master.clone())),
+		_ => proc_stdin.map(ProcStdin::Pipe)
+	};
+	let sink = if request.persist {
+
+		// name the output pipes after the process, so the next host processor can find them
+		for (fifo, kind) in [(&mut stdout_fifo, ConsoleKind::Stdout), (&mut stderr_fifo, ConsoleKind::Stderr)] {
+			if let (Some(fifo), Some(state_path)) = (fifo, state_path) {
+				fifo.keep(state_path, pid, kind)
+					.context(format!("Failed to rename named pipe for {}", kind.name()))
+					.warn_err()
+					.ok();
+			}
+		}
+
+		let attachment = Rc::new(Mutex::new(Attachment::new(Some((socket.clone(), request_id)))));
+		processes.lock()
+			.await
+			.add_persistent(pid, stdin, pty.clone(), launch, attachment.clone());
+		EventSink::Persistent(attachment)
+	} else {
+		processes.lock()
+			.await
+			.add(pid, stdin, pty.clone(), launch);
+		EventSink::Connection(outbox.open(request_id, config.output_budget, config.overflow))
+	};
 
 	// NOTE: process is tracked now, don't exit this fn without cleaning it up
 
 	// send back the pid
-	write_response(&socket, request_id, Response::Exec(ExecResponse::Success {
+	write_audited_response(&socket, request_id, audit, Response::Exec(ExecResponse::Success {
 			pid
 		}))
 		.await
 		.ok();
 
+	// enforce the timeouts, if any
+	let timeouts = Timeouts::new(&limits, started)
+		.map(Rc::new);
+	let timeouts_task = timeouts.clone().map(|timeouts| {
+		let processes = processes.clone();
+		let grace = config.timeout_grace;
+		tokio::task::spawn_local(async move {
+			let kind = timeouts.wait()
+				.await;
+			info!("Process exceeded {} limit, stopping process group", kind.name());
+			let response = kill_gracefully(&processes, pid, grace)
+				.await;
+			trace!(?response);
+		}.in_current_span())
+	});
+
 	// stream stdout and/or stderr, if needed
+	// NOTE: keep copies of the outputs too, so they can still be read if we detach from the process
 	let mut proc_outputs = StreamMap::<ConsoleKind,Pin<Box<dyn Stream<Item=std::io::Result<Bytes>>>>>::new();
-	if let Some(proc_stdout) = proc.stdout.take() {
+	let mut drains = Drains::new(config.shutdown_policy == ShutdownPolicy::Detach, &request.stdout, &request.stderr);
+	if let Some(master) = pty {
+		drains.keep(ConsoleKind::Stdout, &master);
+		proc_outputs.insert(ConsoleKind::Stdout, Box::pin(ReaderStream::new(master)));
+	} else if let Some(proc_stdout) = proc_stdout {
+		drains.keep(ConsoleKind::Stdout, &proc_stdout);
 		proc_outputs.insert(ConsoleKind::Stdout, Box::pin(ReaderStream::new(proc_stdout)));
+	} else if let Some(reader) = stdout_fifo.as_mut().and_then(|fifo| fifo.reader.take()) {
+		// NOTE: don't drain named pipes, the next host processor reads them
+		proc_outputs.insert(ConsoleKind::Stdout, Box::pin(ReaderStream::new(reader)));
 	}
-	if let Some(proc_stderr) = proc.stderr.take() {
-		proc_outputs.insert(ConsoleKind::Stderr, Box::pin(ReaderStream::new(proc_stderr)));
+	// NOTE: every stage of a pipeline can write to stderr
+	if !proc_stderrs.is_empty() {
+		for proc_stderr in &proc_stderrs {
+			drains.keep(ConsoleKind::Stderr, proc_stderr);
+		}
+		let proc_stderrs = proc_stderrs.into_iter()
+			.map(ReaderStream::new);
+		proc_outputs.insert(ConsoleKind::Stderr, Box::pin(stream::select_all(proc_stderrs)));
+	} else if let Some(reader) = stderr_fifo.as_mut().and_then(|fifo| fifo.reader.take()) {
+		proc_outputs.insert(ConsoleKind::Stderr, Box::pin(ReaderStream::new(reader)));
 	}
+	let mut stdout_lines = request.lines.as_ref()
+		.map(|lines| LineFramer::new(ConsoleKind::Stdout, lines, started));
+	let mut stderr_lines = request.lines.as_ref()
+		.map(|lines| LineFramer::new(ConsoleKind::Stderr, lines, started));
 	if proc_outputs.is_empty() {
 		trace!("not streaming process outputs");
 	} else {
 		loop {
-			match proc_outputs.next().await {
+			let next = tokio::select! {
+				next = proc_outputs.next() => next,
+				_ = shutdown.cancelled() => {
+					detach_exec(pid, sink, drains, request.stream_fin, audit, timeouts_task)
+						.await;
+					return;
+				}
+			};
+			if let (Some(timeouts), Some((_, Ok(_)))) = (&timeouts, &next) {
+				timeouts.output();
+			}
+			match next {
 
 				Some((ConsoleKind::Stdout, Ok(chunk))) => {
 					match &request.stdout {
 
-						ExecStdout::Stream => {
+						ExecStdout::Stream | ExecStdout::Pty => {
 							// send back the console chunk
-							let Ok(_) = write_response(&socket, request_id, Response::ProcessEvent(ProcessEvent::Console {
-								kind: ConsoleKind::Stdout,
-								chunk: chunk.to_vec()
-							}))
+							let Ok(_) = send_console(&sink, ConsoleKind::Stdout, &chunk, stdout_lines.as_mut())
 								.await
 								else { break; };
+							stats.streamed(chunk.len());
 						}
 
-						ExecStdout::Write { .. } => {
+						ExecStdout::Write { .. } | ExecStdout::LogFile(..) => {
 							// write to the stdout file, if any
 							if let Some(file) = &mut stdout_file {
 								let Ok(_) = file.write(&chunk)
@@ -487,7 +1527,8 @@ async fn dispatch_exec(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, proce
 							}
 						}
 
-						ExecStdout::Ignore => ()
+						// NOTE: the process writes to the file descriptor directly, so we never see this output
+						ExecStdout::Ignore | ExecStdout::Fd => ()
 					}
 				}
 
@@ -496,15 +1537,13 @@ async fn dispatch_exec(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, proce
 
 						ExecStderr::Stream => {
 							// send back the console chunk
-							let Ok(_) = write_response(&socket, request_id, Response::ProcessEvent(ProcessEvent::Console {
-								kind: ConsoleKind::Stderr,
-								chunk: chunk.to_vec()
-							}))
+							let Ok(_) = send_console(&sink, ConsoleKind::Stderr, &chunk, stderr_lines.as_mut())
 								.await
 								else { break; };
+							stats.streamed(chunk.len());
 						}
 
-						ExecStderr::Write { .. } => {
+						ExecStderr::Write { .. } | ExecStderr::LogFile(..) => {
 							// write to the stderr file, if any
 							if let Some(file) = &mut stderr_file {
 								let Ok(_) = file.write(&chunk)
@@ -513,7 +1552,7 @@ async fn dispatch_exec(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, proce
 							}
 						}
 
-						ExecStderr::Merge { .. } => {
+						ExecStderr::Merge => {
 							// write to the stdout file, if any
 							if let Some(file) = &mut stdout_file {
 								let Ok(_) = file.write(&chunk)
@@ -529,7 +1568,7 @@ async fn dispatch_exec(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, proce
 							}
 						}
 
-						ExecStderr::Ignore => ()
+						ExecStderr::Ignore | ExecStderr::Fd => ()
 					}
 				}
 
@@ -544,35 +1583,267 @@ async fn dispatch_exec(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, proce
 				}
 			}
 		}
+
+		// send the rest of any unfinished lines
+		for lines in [&mut stdout_lines, &mut stderr_lines].into_iter().flatten() {
+			if let Some(event) = lines.finish() {
+				sink.send(event)
+					.await
+					.ok();
+			}
+		}
 	}
 
-	// wait for the process to finish
+	// wait for the process to finish, or all the processes in the pipeline
+	// NOTE: use wait4() instead of proc.wait(), so we can get the resource usage too
 	trace!("Waiting for process to exit");
-	let exit = proc.wait()
-		.await
-		.context("Failed to wait for exec process")
-		.warn_err();
-	if let Ok(exit) = exit {
+	let exits = tokio::select! {
+		exits = future::join_all(pids.iter().map(|pid| wait::wait(*pid))) => exits,
+		_ = shutdown.cancelled() => {
+			detach_exec(pid, sink, drains, request.stream_fin, audit, timeouts_task)
+				.await;
+			return;
+		}
+	};
+	let mut exits = exits.into_iter()
+		.map(|exit| exit
+			.context("Failed to wait for exec process")
+			.warn_err()
+		)
+		.collect::<Vec<_>>();
+
+	// the pipeline exits how its last stage exits, but uses the resources of all of them
+	let stages = match pipeline {
+		Some(_) => exits.iter()
+			.map(|exit| exit.as_ref().ok().and_then(|exit| exit.exit()))
+			.collect(),
+		None => Vec::new()
+	};
+	let usage = exits.iter()
+		.flatten()
+		.map(|exit| exit.usage.clone())
+		.reduce(|a, b| ResourceUsage {
+			user_time: a.user_time + b.user_time,
+			system_time: a.system_time + b.system_time,
+			max_rss: a.max_rss.max(b.max_rss)
+		});
+	let exceeded = exits.iter()
+		.flatten()
+		.find_map(|exit| limits::exceeded(&limits, &exit.status, cgroup.as_ref()));
+	let exit = exits.pop()
+		.unwrap_or(Err(()));
+
+	// NOTE: the pid could be reused now, so stop enforcing timeouts right away
+	if let Some(task) = timeouts_task {
+		task.abort();
+	}
 
-		trace!(code = ?exit.code(), "Process exited");
+	// did the process die from a limit?
+	let limit = timeouts.and_then(|timeouts| timeouts.exceeded())
+		.or(exceeded);
+	if let Some(limit) = &limit {
+		info!("Process exceeded {} limit", limit.name());
+	}
 
-		// send the final proc event, if needed
-		if request.stream_fin {
-			write_response(&socket, request_id, Response::ProcessEvent(ProcessEvent::Fin {
-				exit_code: exit.code()
-			}))
-				.await
-				.ok();
-		}
+	// cleanup the cgroup, if any
+	if let Some(cgroup) = cgroup {
+		cgroup.remove()
+			.warn_err()
+			.ok();
 	}
 
-	// cleanup the process collection
+	// update the process collection
 	processes.lock()
 		.await
-		.remove(pid)
-		.context(format!("Process {} finished, but had no Proc entry to remove", pid))
+		.finish(
+			pid,
+			exit.as_ref().ok().and_then(|exit| exit.exit()),
+			usage,
+			limit
+		)
+		.context(format!("Process {} finished, but had no Proc entry", pid))
 		.warn_err()
 		.ok();
+	if let Some(state_path) = state_path {
+		OutputFifo::remove(state_path, pid);
+	}
+
+	// record the exit in the audit log too
+	audit.outcome(Outcome::exited(pid, exit.as_ref().ok().and_then(|exit| exit.status.code()), limit))
+		.await;
+
+	// make the final proc event, if needed
+	let fin = match exit {
+		Ok(exit) if request.stream_fin => {
+			trace!(code = ?exit.status.code(), "Process exited");
+			Some(ProcessEvent::Fin {
+				exit_code: exit.status.code(),
+				limit,
+				stages
+			})
+		}
+		_ => None
+	};
+
+	match sink {
+
+		EventSink::Connection(..) => {
+			if let Some(fin) = fin {
+				sink.send(fin)
+					.await
+					.ok();
+			}
+		}
+
+		EventSink::Persistent(attachment) =>
+			finish_persistent(&attachment, fin)
+				.await
+	}
+}
+
+
+/// Checks the program against the policy, if any, and finds the path to run
+fn check_program(config: &Config, program: &str, args: &[String], envvars: &[(String,String)], inherited_env: &[(OsString,OsString)], dir: &Path) -> Result<AllowedProgram,String> {
+
+	if config.programs.is_empty() {
+		return Ok(AllowedProgram {
+			path: PathBuf::from(program),
+			arg0: program.to_string()
+		});
+	}
+
+	let path_var = envvars.iter()
+		.rev()
+		.find(|(name, _)| name == "PATH")
+		.map(|(_, value)| OsString::from(value))
+		.or_else(|| inherited_env.iter()
+			.find(|(name, _)| name == "PATH")
+			.map(|(_, value)| value.clone())
+		);
+	match config.programs.check(program, args, dir, path_var.as_deref()) {
+		Ok(allowed) => {
+			info!(target: "host_processor::audit", program, path = %allowed.path.to_string_lossy(), args = ?args, "Exec allowed");
+			Ok(allowed)
+		}
+		Err(reason) => {
+			warn!(target: "host_processor::audit", program, args = ?args, reason, "Exec denied");
+			Err(reason)
+		}
+	}
+}
+
+
+fn stdout_stdio(stdout: &ExecStdout, fd: Option<OwnedFd>) -> Stdio {
+	match (stdout, fd) {
+		(_, Some(fd)) => Stdio::from(fd),
+		(ExecStdout::Stream, _) => Stdio::piped(),
+		(ExecStdout::Write { .. } | ExecStdout::LogFile(..), _) => Stdio::piped(),
+		(ExecStdout::Log, _) => Stdio::piped(),
+		(ExecStdout::Ignore, _) => Stdio::null(),
+		(ExecStdout::Pty | ExecStdout::Fd, None) => Stdio::null()
+	}
+}
+
+
+fn stderr_stdio(stderr: &ExecStderr, fd: Option<OwnedFd>) -> Stdio {
+	match (stderr, fd) {
+		(_, Some(fd)) => Stdio::from(fd),
+		(ExecStderr::Stream, _) => Stdio::piped(),
+		(ExecStderr::Write { .. } | ExecStderr::LogFile(..), _) => Stdio::piped(),
+		(ExecStderr::Merge, _) => Stdio::piped(),
+		(ExecStderr::Log, _) => Stdio::piped(),
+		(ExecStderr::Ignore, _) => Stdio::null(),
+		(ExecStderr::Fd, None) => Stdio::null()
+	}
+}
+
+
+/// The pipes of the spawned processes that we read and write, registered with the async runtime
+struct ProcPipes {
+	stdin: Option<ChildStdin>,
+	stdout: Option<ChildStdout>,
+	stderrs: Vec<ChildStderr>
+}
+
+impl ProcPipes {
+
+	/// Takes stdin from the first process, stdout from the last process, and stderr from all of them
+	fn take(procs: &mut [std::process::Child]) -> Result<Self> {
+		let stdin = procs.first_mut()
+			.and_then(|proc| proc.stdin.take())
+			.map(ChildStdin::from_std)
+			.transpose()
+			.context("Failed to register stdin")?;
+		let stdout = procs.last_mut()
+			.and_then(|proc| proc.stdout.take())
+			.map(ChildStdout::from_std)
+			.transpose()
+			.context("Failed to register stdout")?;
+		let stderrs = procs.iter_mut()
+			.filter_map(|proc| proc.stderr.take())
+			.map(ChildStderr::from_std)
+			.collect::<std::io::Result<Vec<_>>>()
+			.context("Failed to register stderr")?;
+		Ok(Self {
+			stdin,
+			stdout,
+			stderrs
+		})
+	}
+}
+
+
+/// Sets up the terminal, cgroup, limits, and user for the process, after it forks but before it runs the program
+fn prepare_process(cmd: &mut Command, procs_fd: Option<RawFd>, limits: &ExecLimits, privileges: Option<Privileges>, is_pty: bool) {
+
+	if *limits == ExecLimits::default() && privileges.is_none() && !is_pty {
+		return;
+	}
+
+	let limits = limits.clone();
+	unsafe {
+		cmd.pre_exec(move || {
+			if is_pty {
+				// NOTE: stdout is the terminal by now
+				pty::make_controlling_terminal(libc::STDOUT_FILENO)?;
+			}
+			if let Some(procs_fd) = procs_fd {
+				Cgroup::join(procs_fd)?;
+			}
+			limits::apply_rlimits(&limits)?;
+			// NOTE: drop privileges last, since the other steps might need them
+			if let Some(privileges) = &privileges {
+				privileges.apply()?;
+			}
+			Ok(())
+		});
+	}
+}
+
+
+/// Stops streaming events for a process that's still running, because the host processor is shutting down.
+/// The process stays tracked in the state file, if it's persistent, and any cgroup stays behind, since the process is still in it.
+/// Its outputs get drained, so it can keep writing to them after we exit.
+async fn detach_exec(pid: u32, sink: EventSink, drains: Drains, stream_fin: bool, audit: &AuditRecord, timeouts_task: Option<JoinHandle<()>>) {
+
+	info!("Detaching from running process");
+
+	drains.spawn();
+
+	// NOTE: no one will be around to enforce the timeouts anymore
+	if let Some(task) = timeouts_task {
+		task.abort();
+	}
+
+	audit.outcome(Outcome::Detached { pid })
+		.await;
+
+	// let the client know there won't be a Fin
+	if stream_fin {
+		sink.send(ProcessEvent::Detached)
+			.await
+			.ok();
+	}
 }
 
 
@@ -582,101 +1853,402 @@ async fn dispatch_status(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, pro
 	tracing::Span::current().record("pid", pid);
 	trace!("Request");
 
-	let is_running = processes.lock()
+	let status = processes.lock()
 		.await
-		.contains(pid);
+		.status(pid);
 
-	trace!(is_running);
+	trace!(?status);
 
 	// send back the response
-	write_response(&socket, request_id, Response::Status(is_running))
+	write_response(&socket, request_id, Response::Status(status))
 		.await
 		.ok();
 }
 
 
-#[tracing::instrument(skip_all, level = 5, name = "WriteStdin", fields(pid))]
-async fn dispatch_write_stdin(processes: Rc<Mutex<Processes>>, pid: u32, chunk: Vec<u8>) {
+#[tracing::instrument(skip_all, level = 5, name = "Wait", fields(pid))]
+async fn dispatch_wait(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, processes: Rc<Mutex<Processes>>, shutdown: CancellationToken, pid: u32, timeout: Option<u32>) {
+
+	tracing::Span::current().record("pid", pid);
+	trace!("Request");
+
+	// wait for the process to exit, if it's still running
+	let exited = processes.lock()
+		.await
+		.watch_exit(pid);
+	if let Some(mut exited) = exited {
+		let exit = async {
+			// NOTE: an error here means the process isn't tracked anymore, which is done waiting too
+			exited.wait_for(|exited| *exited)
+				.await
+				.ok();
+		};
+		let timeout = async {
+			match timeout {
+				Some(ms) => tokio::time::sleep(Duration::from_millis(ms as u64)).await,
+				None => future::pending().await
+			}
+		};
+		tokio::select! {
+			_ = exit => trace!("Process exited"),
+			_ = timeout => trace!("Timed out"),
+			// when shutting down, stop waiting and answer with what we know
+			_ = shutdown.cancelled() => trace!("shutting down")
+		}
+	}
+
+	let response = match processes.lock().await.status(pid) {
+		ProcessStatus::Exited { exit, .. } => WaitResponse::Exited {
+			exit
+		},
+		ProcessStatus::Running { .. } => WaitResponse::Timeout,
+		ProcessStatus::Unknown => WaitResponse::Unknown
+	};
+
+	trace!(?response);
+
+	// send back the response
+	write_response(&socket, request_id, Response::Wait(response))
+		.await
+		.ok();
+}
+
+
+#[tracing::instrument(skip_all, level = 5, name = "Attach", fields(pid))]
+async fn dispatch_attach(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, audit: &AuditRecord, processes: Rc<Mutex<Processes>>, pid: u32) {
 
 	tracing::Span::current().record("pid", pid);
-	trace!("Request: write {} bytes", chunk.len());
+	trace!("Request");
 
-	let mut processes = processes.lock()
+	let proc = processes.lock()
+		.await
+		.get(pid)
+		.map(|proc| (proc.attachment.clone(), proc.is_finished()));
+	let attachment = match proc {
+		Some((Some(a), is_finished)) => {
+			if is_finished && a.lock().await.is_done() {
+				write_audited_response(&socket, request_id, audit, Response::Attach(AttachResponse::Failure {
+					reason: "Process already finished".to_string()
+				}))
+					.await
+					.ok();
+				return;
+			}
+			a
+		}
+		Some((None, _)) => {
+			write_audited_response(&socket, request_id, audit, Response::Attach(AttachResponse::Failure {
+				reason: "Process is not persistent".to_string()
+			}))
+				.await
+				.ok();
+			return;
+		}
+		None => {
+			write_audited_response(&socket, request_id, audit, Response::Attach(AttachResponse::Failure {
+				reason: "Process not found".to_string()
+			}))
+				.await
+				.ok();
+			return;
+		}
+	};
+
+	let mut attachment = attachment.lock()
 		.await;
-	let Ok(proc) = processes.get_mut(pid)
-		.context("Process not found")
-		.warn_err()
-		else { return; };
 
-	let Ok(stdin) = proc.stdin.as_mut()
-		.context("Process stdin already closed")
-		.warn_err()
+	let Ok(_) = write_audited_response(&socket, request_id, audit, Response::Attach(AttachResponse::Success))
+		.await
 		else { return; };
 
-	stdin.write_all(chunk.as_ref())
+	// send any events we missed
+	while let Some(event) = attachment.unbuffer() {
+		if write_response(&socket, request_id, Response::ProcessEvent(event.clone())).await.is_err() {
+			attachment.rebuffer(event);
+			return;
+		}
+	}
+
+	// send any future events to this connection
+	// NOTE: if another connection was attached, it won't get events anymore
+	attachment.socket = Some((socket, request_id));
+	debug!("Attached");
+}
+
+
+#[tracing::instrument(skip_all, level = 5, name = "ListProcesses")]
+async fn dispatch_list_processes(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, processes: Rc<Mutex<Processes>>) {
+
+	trace!("Request");
+
+	let procs = processes.lock()
+		.await
+		.list();
+
+	trace!(num = procs.len());
+
+	// send back the response
+	write_response(&socket, request_id, Response::ListProcesses(procs))
 		.await
-		.context("Failed to write to stdin")
-		.warn_err()
 		.ok();
 }
 
 
+#[tracing::instrument(skip_all, level = 5, name = "Stats")]
+async fn dispatch_stats(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, processes: Rc<Mutex<Processes>>, stats: Rc<Stats>) {
+
+	trace!("Request");
+
+	let live = processes.lock()
+		.await
+		.running()
+		.len();
+
+	// send back the response
+	write_response(&socket, request_id, Response::Stats(stats.snapshot(live)))
+		.await
+		.ok();
+}
+
+
+#[tracing::instrument(skip_all, level = 5, name = "WriteStdin", fields(pid))]
+async fn dispatch_write_stdin(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, processes: Rc<Mutex<Processes>>, window: Rc<StdinWindow>, pid: u32, chunk: Vec<u8>, ack: bool) {
+
+	tracing::Span::current().record("pid", pid);
+	trace!("Request: write {} bytes, ack={}", chunk.len(), ack);
+
+	if !ack {
+		write_stdin(&processes, pid, &chunk)
+			.await
+			.warn_err()
+			.ok();
+		return;
+	}
+
+	// acked writes have to fit in what's left of the connection's window,
+	// so the client can't make us hold more than that in memory while the process catches up
+	let response = match window.reserve(chunk.len()) {
+		Ok(reservation) => {
+			let result = write_stdin(&processes, pid, &chunk)
+				.await;
+			drop(reservation);
+			match result {
+				Ok(()) => StdinResponse::Written {
+					bytes: chunk.len() as u32,
+					credit: u32::try_from(window.credit()).unwrap_or(u32::MAX)
+				},
+				Err(e) => StdinResponse::Failure {
+					reason: e.deref().chain().to_string()
+				}
+			}
+		}
+		Err(reason) => StdinResponse::Failure {
+			reason
+		}
+	};
+
+	trace!(?response);
+
+	// send back the response
+	write_response(&socket, request_id, Response::Stdin(response))
+		.await
+		.ok();
+}
+
+
+async fn write_stdin(processes: &Mutex<Processes>, pid: u32, chunk: &[u8]) -> Result<()> {
+
+	// NOTE: don't hold the lock on the process collection while writing,
+	//       since the write waits for as long as the process doesn't read its stdin
+	let stdin = processes.lock()
+		.await
+		.get(pid)
+		.context("Process not found")?
+		.stdin
+		.clone()
+		.context("Process stdin already closed")?;
+
+	let mut stdin = stdin.lock()
+		.await;
+	stdin.write_all(chunk)
+		.await
+		.context("Failed to write to stdin")
+}
+
+
 #[tracing::instrument(skip_all, level = 5, name = "CloseStdin", fields(pid))]
-async fn dispatch_close_stdin(processes: Rc<Mutex<Processes>>, pid: u32) {
+async fn dispatch_close_stdin(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, processes: Rc<Mutex<Processes>>, pid: u32, ack: bool) {
 
 	tracing::Span::current().record("pid", pid);
-	trace!("Request");
+	trace!("Request: ack={}", ack);
+
+	let result = close_stdin(&processes, pid)
+		.await;
+
+	if !ack {
+		result.warn_err()
+			.ok();
+		return;
+	}
+
+	let response = match result {
+		Ok(()) => StdinResponse::Closed,
+		Err(e) => StdinResponse::Failure {
+			reason: e.deref().chain().to_string()
+		}
+	};
+
+	trace!(?response);
+
+	// send back the response
+	write_response(&socket, request_id, Response::Stdin(response))
+		.await
+		.ok();
+}
+
+
+async fn close_stdin(processes: &Mutex<Processes>, pid: u32) -> Result<()> {
+
+	let stdin = processes.lock()
+		.await
+		.get_mut(pid)
+		.context("Process not found")?
+		.stdin
+		.take()
+		.context("Process stdin already closed")?;
+
+	// NOTE: wait for any writes in progress to finish first,
+	//       then the pipe closes when the last of them lets go of it
+	let mut stdin = stdin.lock()
+		.await;
+	stdin.close()
+		.await
+		.context("Failed to close stdin")
+}
+
+
+#[tracing::instrument(skip_all, level = 5, name = "ResizePty", fields(pid))]
+async fn dispatch_resize_pty(processes: Rc<Mutex<Processes>>, pid: u32, rows: u16, cols: u16) {
 
-	let mut processes = processes.lock()
+	tracing::Span::current().record("pid", pid);
+	trace!("Request: {}x{}", cols, rows);
+
+	let processes = processes.lock()
 		.await;
-	let Ok(proc) = processes.get_mut(pid)
+	let Ok(proc) = processes.get(pid)
 		.context("Process not found")
 		.warn_err()
 		else { return; };
 
-	// drop stdin to close it
-	let _ = proc.stdin.take()
-		.context("Process stdin already closed")
+	let Ok(pty) = proc.pty.as_ref()
+		.context("Process has no pseudo-terminal")
+		.warn_err()
+		else { return; };
+
+	pty.resize(rows, cols)
+		.context("Failed to resize pseudo-terminal")
 		.warn_err()
 		.ok();
 }
 
 
 #[tracing::instrument(skip_all, level = 5, name = "Kill", fields(pid))]
-async fn dispatch_kill(processes: Rc<Mutex<Processes>>, signal: KillSignal, pid: u32, process_group: bool) {
+async fn dispatch_kill(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, audit: &AuditRecord, processes: Rc<Mutex<Processes>>, signal: KillSignal, pid: u32, process_group: bool) {
 
 	tracing::Span::current().record("pid", pid);
 	trace!("Request: signal={}, process_group={}", &signal.name(), process_group);
 
-	let Ok(_) = processes.lock()
+	// NOTE: don't signal finished processes, since their pids could have been reused by now
+	let is_running = processes.lock()
 		.await
-		.get_mut(pid)
-		.context("Process not found")
-		.warn_err()
-		else { return; };
-
-	// send a kill signal (SIGTERM)
-	let Ok(output) = Command::new("kill")
-		.args([
-			"-s".to_string(), signal.name().to_string(),
-			"--".to_string(), // NODE: need this token to keep the negative pgid from being interpreted as an argument
-			if process_group {
-				format!("-{pid}")
-			} else {
-				format!("{pid}")
+		.is_running(pid);
+	let response = if !is_running {
+		KillResponse::Failure(KillFailure {
+			errno: None,
+			reason: "Process not found".to_string()
+		})
+	} else {
+		match signals::send(pid, signal, process_group) {
+			Ok(()) => KillResponse::Success,
+			Err(e) => {
+				warn!("Failed to send {}: {}", signal.name(), e);
+				KillResponse::Failure(e.into())
 			}
-		])
-		.output()
+		}
+	};
+
+	// send back the response
+	write_audited_response(&socket, request_id, audit, Response::Kill(response))
 		.await
-		.context("Failed to spawn `kill` and wait for its output")
-		.warn_err()
-		else { return; };
+		.ok();
+}
+
+
+#[tracing::instrument(skip_all, level = 5, name = "KillGracefully", fields(pid))]
+async fn dispatch_kill_gracefully(socket: Rc<Mutex<OwnedWriteHalf>>, request_id: u32, audit: &AuditRecord, processes: Rc<Mutex<Processes>>, pid: u32, timeout: u32) {
+
+	tracing::Span::current().record("pid", pid);
+	trace!("Request: timeout={}ms", timeout);
 
-	// examine the kill output
-	if !output.status.success() {
-		let stdout = String::from_utf8_lossy(&output.stdout);
-		let stderr = String::from_utf8_lossy(&output.stderr);
-		warn!("Kill failed with code {:?}\nSTDOUT: {}\nSTDERR: {}", output.status.code(), stdout, stderr);
+	let response = kill_gracefully(&processes, pid, Duration::from_millis(timeout as u64))
+		.await;
+
+	trace!(?response);
+
+	// send back the response
+	write_audited_response(&socket, request_id, audit, Response::KillGracefully(response))
+		.await
+		.ok();
+}
+
+
+async fn kill_gracefully(processes: &Mutex<Processes>, pid: u32, timeout: Duration) -> KillGracefullyResponse {
+
+	// NOTE: don't signal finished processes, since their pids could have been reused by now
+	if !processes.lock().await.is_running(pid) {
+		return KillGracefullyResponse::Failure(KillFailure {
+			errno: None,
+			reason: "Process not found".to_string()
+		});
+	}
+
+	// ask nicely first
+	if let Err(e) = signals::send(pid, KillSignal::Terminate, true) {
+		return KillGracefullyResponse::Failure(e.into());
+	}
+	if wait_for_exit(processes, pid, timeout).await {
+		return KillGracefullyResponse::Terminated;
+	}
+
+	// then not so nicely
+	debug!("Process didn't exit after SIGTERM, sending SIGKILL");
+	if let Err(e) = signals::send(pid, KillSignal::Kill, true) {
+		return KillGracefullyResponse::Failure(e.into());
+	}
+	if wait_for_exit(processes, pid, timeout).await {
+		return KillGracefullyResponse::Killed;
+	}
+
+	KillGracefullyResponse::Failure(KillFailure {
+		errno: None,
+		reason: "Process didn't exit after SIGKILL".to_string()
+	})
+}
+
+
+/// Waits until the process exits, or the timeout passes.
+/// Returns true if the process exited.
+async fn wait_for_exit(processes: &Mutex<Processes>, pid: u32, timeout: Duration) -> bool {
+	let deadline = tokio::time::Instant::now() + timeout;
+	loop {
+		if !processes.lock().await.is_running(pid) {
+			return true;
+		}
+		if tokio::time::Instant::now() >= deadline {
+			return false;
+		}
+		tokio::time::sleep(Duration::from_millis(50))
+			.await;
 	}
 }
 
@@ -0,0 +1,457 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::proto::{ConsoleKind, ProcessEvent, Response, ResponseEnvelope};
+
+
+/// What to do with the console output of a process when its queue is over budget
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+
+	/// stop reading the output until there's room in the queue, which eventually blocks the process
+	#[default]
+	Block,
+
+	/// drop the output, and send a ProcessEvent::Dropped in its place once the queue catches up,
+	/// or before the next event, whichever comes first
+	Drop,
+
+	/// write the output to a temporary file, and send it from there once the queue catches up
+	Spill
+}
+
+impl FromStr for OverflowPolicy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"block" => Ok(Self::Block),
+			"drop" => Ok(Self::Drop),
+			"spill" => Ok(Self::Spill),
+			_ => Err(format!("Unrecognized overflow policy: {}, expected one of: block, drop, spill", s))
+		}
+	}
+}
+
+
+/// The process events waiting to be sent over one connection.
+/// Each process gets its own queue with a budget for console output,
+/// and the connection takes turns sending from each queue, so one chatty process can't starve the others.
+#[derive(Clone)]
+pub struct Outbox {
+	shared: Rc<Shared>
+}
+
+struct Shared {
+	queues: RefCell<VecDeque<Rc<RefCell<Queue>>>>,
+	ready: Notify,
+	closed: Cell<bool>,
+	/// true when no more queues will be opened
+	finishing: Cell<bool>
+}
+
+impl Default for Outbox {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Outbox {
+
+	pub fn new() -> Self {
+		Self {
+			shared: Rc::new(Shared {
+				queues: RefCell::new(VecDeque::new()),
+				ready: Notify::new(),
+				closed: Cell::new(false),
+				finishing: Cell::new(false)
+			})
+		}
+	}
+
+	/// Adds a queue for the events of a process
+	/// `budget` is the max number of console bytes to hold in memory
+	pub fn open(&self, request_id: u32, budget: usize, policy: OverflowPolicy) -> OutputQueue {
+
+		let queue = Rc::new(RefCell::new(Queue {
+			request_id,
+			events: VecDeque::new(),
+			bytes: 0,
+			budget,
+			policy,
+			dropped: [0, 0],
+			spill: None,
+			space: Rc::new(Notify::new()),
+			done: false
+		}));
+		self.shared.queues.borrow_mut()
+			.push_back(queue.clone());
+
+		OutputQueue {
+			queue,
+			shared: self.shared.clone()
+		}
+	}
+
+	/// Waits for the next event to send, and its request id.
+	/// Returns None after the outbox is closed, or after it's finished and every queue is empty.
+	pub async fn next(&self) -> Option<(u32, ProcessEvent)> {
+		loop {
+			if self.shared.closed.get() {
+				return None;
+			}
+			if let Some(next) = self.shared.pop() {
+				return Some(next);
+			}
+			if self.shared.finishing.get() && self.shared.queues.borrow().is_empty() {
+				return None;
+			}
+			self.shared.ready.notified()
+				.await;
+		}
+	}
+
+	/// Keeps sending events until every open queue is done and empty, eg because the host processor is shutting down.
+	/// Don't open any more queues after this.
+	pub fn finish(&self) {
+		self.shared.finishing.set(true);
+		self.shared.ready.notify_one();
+	}
+
+	/// Stops sending events, eg because the connection went away
+	pub fn close(&self) {
+		self.shared.closed.set(true);
+		self.shared.ready.notify_one();
+		for queue in self.shared.queues.borrow().iter() {
+			queue.borrow().space.notify_one();
+		}
+	}
+}
+
+impl Shared {
+
+	/// Takes the next event from the next queue in line, if any
+	fn pop(&self) -> Option<(u32, ProcessEvent)> {
+
+		let mut queues = self.queues.borrow_mut();
+
+		for _ in 0 .. queues.len() {
+
+			let queue_rc = queues.pop_front()?;
+			let mut queue = queue_rc.borrow_mut();
+			let event = queue.pop();
+			let request_id = queue.request_id;
+			let finished = queue.done && queue.is_empty();
+			if event.is_some() {
+				queue.space.notify_one();
+			}
+			drop(queue);
+
+			// move the queue to the back of the line, if it still has work to do
+			if !finished {
+				queues.push_back(queue_rc);
+			}
+
+			if let Some(event) = event {
+				return Some((request_id, event));
+			}
+		}
+
+		None
+	}
+}
+
+
+/// The queue of events for one process in an Outbox
+pub struct OutputQueue {
+	queue: Rc<RefCell<Queue>>,
+	shared: Rc<Shared>
+}
+
+impl OutputQueue {
+
+	/// Queues an event to send.
+	/// Console output over the budget is handled by the overflow policy, but other events are always queued.
+	/// Returns Err if the outbox was closed.
+	pub async fn send(&self, event: ProcessEvent) -> Result<(),()> {
+
+		let console = console_bytes(&event);
+
+		loop {
+
+			if self.shared.closed.get() {
+				return Err(());
+			}
+
+			let space = {
+				let mut queue = self.queue.borrow_mut();
+				match console {
+
+					Some((kind, bytes)) if !queue.fits(bytes) => match queue.policy {
+
+						OverflowPolicy::Block => queue.space.clone(),
+
+						OverflowPolicy::Drop => {
+							queue.drop_bytes(kind, bytes);
+							return Ok(());
+						}
+
+						OverflowPolicy::Spill => {
+							queue.spill(event);
+							break;
+						}
+					}
+
+					_ => {
+						queue.push(event);
+						break;
+					}
+				}
+			};
+
+			// wait for the connection to catch up
+			space.notified()
+				.await;
+		}
+
+		self.shared.ready.notify_one();
+		Ok(())
+	}
+}
+
+impl Drop for OutputQueue {
+
+	fn drop(&mut self) {
+		// let the outbox clean up the queue once it's empty
+		self.queue.borrow_mut().done = true;
+		self.shared.ready.notify_one();
+	}
+}
+
+
+struct Queue {
+	request_id: u32,
+	events: VecDeque<ProcessEvent>,
+	/// console bytes in events
+	bytes: usize,
+	budget: usize,
+	policy: OverflowPolicy,
+	/// console bytes dropped since the last Dropped event, for stdout and stderr
+	dropped: [u64; 2],
+	spill: Option<Spill>,
+	/// notified when events leave the queue
+	space: Rc<Notify>,
+	/// true when the process won't send any more events
+	done: bool
+}
+
+impl Queue {
+
+	fn is_spilling(&self) -> bool {
+		self.spill.as_ref()
+			.is_some_and(|spill| !spill.is_empty())
+	}
+
+	fn is_empty(&self) -> bool {
+		self.events.is_empty() && !self.is_spilling() && self.dropped == [0, 0]
+	}
+
+	fn fits(&self, bytes: usize) -> bool {
+		if self.is_spilling() {
+			// wait for the spill to catch up, so events stay in order
+			false
+		} else {
+			// NOTE: always allow one event, even if it's bigger than the whole budget
+			self.events.is_empty() || self.bytes + bytes <= self.budget
+		}
+	}
+
+	fn push(&mut self, event: ProcessEvent) {
+
+		// mark where any output was dropped
+		while let Some(dropped) = self.take_dropped() {
+			self.push_raw(dropped);
+		}
+
+		self.push_raw(event);
+	}
+
+	/// Makes a Dropped event for output dropped since the last one, if any
+	fn take_dropped(&mut self) -> Option<ProcessEvent> {
+		[ConsoleKind::Stdout, ConsoleKind::Stderr].into_iter()
+			.find_map(|kind| {
+				let bytes = std::mem::take(&mut self.dropped[kind_index(kind)]);
+				(bytes > 0).then_some(ProcessEvent::Dropped {
+					kind,
+					bytes
+				})
+			})
+	}
+
+	fn push_raw(&mut self, event: ProcessEvent) {
+		if self.is_spilling() {
+			self.spill(event);
+		} else {
+			if let Some((_, bytes)) = console_bytes(&event) {
+				self.bytes += bytes;
+			}
+			self.events.push_back(event);
+		}
+	}
+
+	fn drop_bytes(&mut self, kind: ConsoleKind, bytes: usize) {
+		let dropped = &mut self.dropped[kind_index(kind)];
+		if *dropped == 0 {
+			warn!("Output queue full, dropping {}", kind.name());
+		}
+		*dropped += bytes as u64;
+	}
+
+	fn spill(&mut self, event: ProcessEvent) {
+
+		if self.spill.is_none() {
+			match Spill::open() {
+				Ok(spill) => self.spill = Some(spill),
+				Err(e) => warn!("Failed to open spill file: {}", e)
+			}
+		}
+
+		let result = match &mut self.spill {
+			Some(spill) => spill.write(&event),
+			None => Err(io::Error::other("no spill file"))
+		};
+		if let Err(e) = result {
+			warn!("Failed to spill event: {}", e);
+			// fall back to dropping the output
+			match console_bytes(&event) {
+				Some((kind, bytes)) => self.drop_bytes(kind, bytes),
+				None => self.events.push_back(event)
+			}
+		}
+	}
+
+	fn pop(&mut self) -> Option<ProcessEvent> {
+
+		if let Some(event) = self.events.pop_front() {
+			if let Some((_, bytes)) = console_bytes(&event) {
+				self.bytes -= bytes;
+			}
+			return Some(event);
+		}
+
+		let spilled = match self.spill.as_mut().map(|spill| spill.read()) {
+			Some(Ok(event)) => event,
+			Some(Err(e)) => {
+				warn!("Failed to read spilled event, dropping the rest: {}", e);
+				self.spill = None;
+				None
+			}
+			None => None
+		};
+
+		// once everything else is sent, mark where any output was dropped,
+		// rather than waiting for the process to send something else, which it might never do
+		spilled.or_else(|| self.take_dropped())
+	}
+}
+
+
+/// A temporary file for events that don't fit in memory
+struct Spill {
+	file: File,
+	read_pos: u64,
+	write_pos: u64
+}
+
+impl Spill {
+
+	fn open() -> io::Result<Self> {
+
+		let path = std::env::temp_dir()
+			.join(format!("host-processor-spill-{}-{}", std::process::id(), rand::random::<u32>()));
+		let file = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.create_new(true)
+			.open(&path)?;
+
+		// NOTE: the file only needs to live as long as we have it open
+		std::fs::remove_file(&path)?;
+
+		Ok(Self {
+			file,
+			read_pos: 0,
+			write_pos: 0
+		})
+	}
+
+	fn is_empty(&self) -> bool {
+		self.read_pos == self.write_pos
+	}
+
+	fn write(&mut self, event: &ProcessEvent) -> io::Result<()> {
+
+		let msg = ResponseEnvelope {
+			id: 0,
+			response: Response::ProcessEvent(event.clone())
+		}
+			.encode()
+			.map_err(io::Error::other)?;
+
+		self.file.write_all_at(&(msg.len() as u32).to_be_bytes(), self.write_pos)?;
+		self.file.write_all_at(&msg, self.write_pos + 4)?;
+		self.write_pos += 4 + msg.len() as u64;
+
+		Ok(())
+	}
+
+	fn read(&mut self) -> io::Result<Option<ProcessEvent>> {
+
+		if self.is_empty() {
+			return Ok(None);
+		}
+
+		let mut size = [0u8; 4];
+		self.file.read_exact_at(&mut size, self.read_pos)?;
+		let mut msg = vec![0u8; u32::from_be_bytes(size) as usize];
+		self.file.read_exact_at(&mut msg, self.read_pos + 4)?;
+		self.read_pos += 4 + msg.len() as u64;
+
+		// reclaim the disk space once everything has been read
+		if self.is_empty() {
+			self.file.set_len(0)?;
+			self.read_pos = 0;
+			self.write_pos = 0;
+		}
+
+		match ResponseEnvelope::decode(msg).map_err(io::Error::other)?.response {
+			Response::ProcessEvent(event) => Ok(Some(event)),
+			response => Err(io::Error::other(format!("unexpected spilled response: {:?}", response)))
+		}
+	}
+}
+
+
+fn console_bytes(event: &ProcessEvent) -> Option<(ConsoleKind, usize)> {
+	match event {
+		ProcessEvent::Console { kind, chunk } => Some((*kind, chunk.len())),
+		ProcessEvent::ConsoleLine { kind, line, .. } => Some((*kind, line.len())),
+		_ => None
+	}
+}
+
+
+fn kind_index(kind: ConsoleKind) -> usize {
+	match kind {
+		ConsoleKind::Stdout => 0,
+		ConsoleKind::Stderr => 1
+	}
+}
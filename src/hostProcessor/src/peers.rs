@@ -0,0 +1,45 @@
+use std::str::FromStr;
+
+use tokio::net::unix::UCred;
+
+use crate::config::Config;
+
+
+/// What to do with connections from peers that aren't in the allow-lists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PeerPolicy {
+
+	/// close the connection right away
+	#[default]
+	Refuse,
+
+	/// log a warning, but serve the connection anyway
+	Log
+}
+
+impl FromStr for PeerPolicy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"refuse" => Ok(Self::Refuse),
+			"log" => Ok(Self::Log),
+			_ => Err(format!("Unrecognized peer policy: {}, expected one of: refuse, log", s))
+		}
+	}
+}
+
+
+/// Checks the peer of a socket connection against the allow-lists in the config.
+/// When both allow-lists are empty, every peer is allowed,
+/// and the socket is protected only by its filesystem permissions.
+/// NOTE: SO_PEERCRED only reports the primary gid of the peer, so supplementary groups don't count
+pub fn is_allowed(config: &Config, peer: &UCred) -> bool {
+
+	if config.allowed_peer_uids.is_empty() && config.allowed_peer_gids.is_empty() {
+		return true;
+	}
+
+	config.allowed_peer_uids.contains(&peer.uid())
+		|| config.allowed_peer_gids.contains(&peer.gid())
+}
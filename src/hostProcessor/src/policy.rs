@@ -0,0 +1,200 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use tracing::warn;
+
+
+/// A program that Exec requests may run
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProgramRule {
+
+	/// the program, symlinks are resolved before matching, and names without a / are looked up in PATH.
+	/// Allowed processes run with this name in argv[0], no matter what name the request used
+	pub program: String,
+
+	/// glob patterns (where * matches anything and ? matches any one character) for the arguments,
+	/// every argument must match at least one of the patterns,
+	/// or None to allow any arguments
+	#[serde(default)]
+	pub args: Option<Vec<String>>
+}
+
+impl ProgramRule {
+
+	/// A rule that allows any arguments
+	pub fn any_args(program: impl Into<String>) -> Self {
+		Self {
+			program: program.into(),
+			args: None
+		}
+	}
+}
+
+
+/// Which programs Exec requests may run, and with which arguments
+#[derive(Debug, Clone, Default)]
+pub struct ProgramPolicy {
+	/// the rules, with their program paths before and after resolving symlinks
+	rules: Vec<(ProgramPaths, ProgramRule)>
+}
+
+#[derive(Debug, Clone)]
+struct ProgramPaths {
+	path: PathBuf,
+	canonical: PathBuf
+}
+
+
+/// A program the policy allows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedProgram {
+
+	/// the canonical path of the program
+	pub path: PathBuf,
+
+	/// the name to run the program with, in argv[0], from the rule that allowed it.
+	/// NOTE: multi-call binaries (like busybox) decide what to do from argv[0],
+	///       so running a symlink to an allowed program with its own name could run a different tool
+	pub arg0: String
+}
+
+impl ProgramPolicy {
+
+	pub fn new(rules: Vec<ProgramRule>) -> Self {
+		let rules = rules.into_iter()
+			.map(|rule| {
+				let paths = ProgramPaths::find(&rule.program, Path::new("."), env::var_os("PATH").as_deref())
+					.unwrap_or_else(|e| {
+						warn!("Failed to resolve program {} in program policy, it won't match anything: {}", &rule.program, e);
+						ProgramPaths {
+							path: PathBuf::from(&rule.program),
+							canonical: PathBuf::from(&rule.program)
+						}
+					});
+				(paths, rule)
+			})
+			.collect();
+		Self {
+			rules
+		}
+	}
+
+	/// Returns true if there are no rules, and any program may run
+	pub fn is_empty(&self) -> bool {
+		self.rules.is_empty()
+	}
+
+	/// Checks the program and arguments against the rules.
+	/// Returns the program to run, or the reason it isn't allowed.
+	pub fn check(&self, program: &str, args: &[String], dir: &Path, path_var: Option<&OsStr>) -> Result<AllowedProgram,String> {
+
+		let paths = ProgramPaths::find(program, dir, path_var)
+			.map_err(|e| format!("Program not found: {}: {}", program, e))?;
+		let path = paths.canonical;
+
+		// NOTE: try the rules for the program by the name it was asked for first,
+		//       so allowing several names for the same multi-call binary keeps them apart
+		let mut rules = self.rules.iter()
+			.filter(|(rule_paths, _)| rule_paths.canonical == path)
+			.collect::<Vec<_>>();
+		if rules.is_empty() {
+			return Err(format!("Program not allowed: {}", path.to_string_lossy()));
+		}
+		rules.sort_by_key(|(rule_paths, _)| rule_paths.path != paths.path);
+
+		// NOTE: any of the matching rules can allow the arguments
+		let mut bad_arg = None;
+		for (_, rule) in rules {
+			let allowed = || AllowedProgram {
+				path: path.clone(),
+				arg0: rule.program.clone()
+			};
+			let Some(patterns) = &rule.args
+				else { return Ok(allowed()); };
+			match args.iter().find(|arg| !patterns.iter().any(|pattern| glob_matches(pattern, arg))) {
+				None => return Ok(allowed()),
+				Some(arg) => bad_arg = Some(arg)
+			}
+		}
+
+		Err(format!("Argument not allowed for program {}: {}", path.to_string_lossy(), bad_arg.map(|a| a.as_str()).unwrap_or("")))
+	}
+}
+
+
+impl ProgramPaths {
+
+	fn find(program: &str, dir: &Path, path_var: Option<&OsStr>) -> io::Result<Self> {
+		let path = find(program, dir, path_var)?;
+		let canonical = fs::canonicalize(&path)?;
+		Ok(Self {
+			path,
+			canonical
+		})
+	}
+}
+
+
+/// Finds the path to the program the same way exec would find it, but without resolving symlinks:
+/// names with a / are relative to the working directory, other names are searched in PATH
+fn find(program: &str, dir: &Path, path_var: Option<&OsStr>) -> io::Result<PathBuf> {
+
+	if program.contains('/') {
+		return Ok(dir.join(program));
+	}
+
+	for folder in env::split_paths(path_var.unwrap_or_default()) {
+		let path = dir.join(folder).join(program);
+		let is_executable = fs::metadata(&path)
+			.is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0);
+		if is_executable {
+			return Ok(path);
+		}
+	}
+
+	Err(io::Error::new(io::ErrorKind::NotFound, "not found in PATH"))
+}
+
+
+/// Matches text against a glob pattern, where * matches anything and ? matches any one character
+fn glob_matches(pattern: &str, text: &str) -> bool {
+
+	let pattern = pattern.chars().collect::<Vec<_>>();
+	let text = text.chars().collect::<Vec<_>>();
+
+	let mut p = 0;
+	let mut t = 0;
+	// where to backtrack to, if a match after the last * fails
+	let mut star: Option<(usize,usize)> = None;
+
+	while t < text.len() {
+		match pattern.get(p) {
+			Some('*') => {
+				star = Some((p, t));
+				p += 1;
+			}
+			Some(c) if *c == '?' || *c == text[t] => {
+				p += 1;
+				t += 1;
+			}
+			_ => match star {
+				Some((star_p, star_t)) => {
+					// let the * match one more character
+					p = star_p + 1;
+					t = star_t + 1;
+					star = Some((star_p, star_t + 1));
+				}
+				None => return false
+			}
+		}
+	}
+
+	// any leftover *s can match nothing
+	pattern[p..].iter().all(|c| *c == '*')
+}
@@ -0,0 +1,141 @@
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::Config;
+use crate::proto::ExecRequest;
+
+
+/// The user and groups to run a process as, after checking with the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Privileges {
+	pub uid: Option<u32>,
+	pub gid: Option<u32>,
+	pub gids: Option<Vec<u32>>
+}
+
+impl Privileges {
+
+	/// Checks the requested user and groups against the allow-lists in the config.
+	/// When a uid other than this process' is requested without a gid or supplementary groups,
+	/// those are filled in from the user's account, and don't need to be in the allow-lists.
+	/// Returns None if the process should just run as this process' user.
+	pub fn from_request(config: &Config, request: &ExecRequest) -> Result<Option<Self>> {
+
+		if request.uid.is_none() && request.gid.is_none() && request.gids.is_none() {
+			return Ok(None);
+		}
+
+		let uid_current = users::get_effective_uid();
+		let gid_current = users::get_effective_gid();
+
+		// check the explicitly requested ids against the allow-lists
+		if let Some(uid) = request.uid {
+			if uid != uid_current && !config.allowed_uids.contains(&uid) {
+				bail!("uid {} is not allowed", uid);
+			}
+		}
+		if let Some(gid) = request.gid {
+			if gid != gid_current && !config.allowed_gids.contains(&gid) {
+				bail!("gid {} is not allowed", gid);
+			}
+		}
+		if let Some(gids) = &request.gids {
+			for gid in gids {
+				if !config.allowed_gids.contains(gid) {
+					bail!("supplementary gid {} is not allowed", gid);
+				}
+			}
+		}
+
+		// fill in any missing groups from the user account, if we're switching to another user
+		// NOTE: a process run as this process' own user just keeps this process' groups
+		let mut privileges = Self {
+			uid: request.uid,
+			gid: request.gid,
+			gids: request.gids.clone()
+		};
+		if let Some(uid) = request.uid.filter(|uid| *uid != uid_current) {
+			if privileges.gid.is_none() || privileges.gids.is_none() {
+				let user = users::get_user_by_uid(uid)
+					.context(format!("No user found for uid {}", uid))?;
+				if privileges.gid.is_none() {
+					privileges.gid = Some(user.primary_group_id());
+				}
+				if privileges.gids.is_none() {
+					let gids = user_gids(user.name(), user.primary_group_id())
+						.context(format!("Failed to lookup supplementary groups for uid {}", uid))?;
+					privileges.gids = Some(gids);
+				}
+			}
+		}
+
+		// only root can switch to other users and groups
+		let is_change = privileges.uid.is_some_and(|uid| uid != uid_current)
+			|| privileges.gid.is_some_and(|gid| gid != gid_current)
+			|| privileges.gids.is_some();
+		if is_change && uid_current != 0 {
+			bail!("The host processor must run as root to change users or groups, but it's running as uid {}", uid_current);
+		}
+
+		Ok(Some(privileges))
+	}
+
+	/// Switches the current process to the user and groups.
+	/// WARNING: This gets called in the child process after fork() but before exec(),
+	///          so only async-signal-safe functions are allowed here. ie, no allocations!
+	pub fn apply(&self) -> io::Result<()> {
+
+		// NOTE: the order here matters: once we switch the uid, we can't change the groups anymore
+
+		if let Some(gids) = &self.gids {
+			match unsafe { libc::setgroups(gids.len(), gids.as_ptr()) } {
+				0 => (), // ok
+				_ => return Err(io::Error::last_os_error())
+			}
+		}
+
+		if let Some(gid) = self.gid {
+			match unsafe { libc::setgid(gid) } {
+				0 => (), // ok
+				_ => return Err(io::Error::last_os_error())
+			}
+		}
+
+		if let Some(uid) = self.uid {
+			match unsafe { libc::setuid(uid) } {
+				0 => (), // ok
+				_ => return Err(io::Error::last_os_error())
+			}
+		}
+
+		Ok(())
+	}
+}
+
+
+/// Looks up all the groups for the user
+/// NOTE: `users::User::groups()` doesn't truncate the list returned by getgrouplist(),
+///       so it reports a spurious group 0 (ie, root), which we definitely don't want here
+fn user_gids(username: &OsStr, gid: u32) -> Result<Vec<u32>> {
+
+	let username = CString::new(username.as_bytes())
+		.context("Invalid username")?;
+
+	let mut gids = vec![0 as libc::gid_t; 256];
+	loop {
+		let mut count = gids.len() as libc::c_int;
+		let result = unsafe { libc::getgrouplist(username.as_ptr(), gid, gids.as_mut_ptr(), &mut count) };
+		if result >= 0 {
+			gids.truncate(count as usize);
+			return Ok(gids);
+		} else if count as usize > gids.len() {
+			// buffer was too small, try again with the size getgrouplist() wanted
+			gids.resize(count as usize, 0);
+		} else {
+			bail!("getgrouplist() failed");
+		}
+	}
+}
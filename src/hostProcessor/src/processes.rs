@@ -1,18 +1,36 @@
+use std::collections::{HashMap, VecDeque};
+use std::{fs, io};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use std::collections::HashMap;
+use anyhow::{Context, Result};
+use tokio::io::AsyncWriteExt;
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::process::ChildStdin;
+use tokio::sync::{watch, Mutex};
 
-use tokio::process::{Child, ChildStdin};
+use crate::logging::ResultExt;
+use crate::pty::PtyMaster;
+use crate::proto::{LimitKind, ProcessEvent, ProcessExit, ProcessInfo, ProcessStatus, ProcessStdio, ResourceUsage};
 
 
 pub struct Processes {
-	procs: HashMap<u32,Proc>
+	procs: HashMap<u32,Proc>,
+	/// how long to keep finished processes around, so clients can still query their status
+	retention: Duration,
+	state_path: Option<PathBuf>
 }
 
 impl Processes {
 
-	pub fn new() -> Self {
+	/// If a state path is given, persistent processes are tracked in a state file,
+	/// so they can be recovered if the host processor restarts
+	pub fn new(retention: Duration, state_path: Option<PathBuf>) -> Self {
 		Self {
 			procs: HashMap::new(),
+			retention,
+			state_path
 		}
 	}
 
@@ -20,26 +38,490 @@ impl Processes {
 		self.procs.contains_key(&pid)
 	}
 
-	pub fn add(&mut self, pid: u32, proc: &mut Child) {
+	pub fn is_running(&self, pid: u32) -> bool {
+		self.procs.get(&pid)
+			.is_some_and(|proc| proc.finished.is_none())
+	}
+
+	pub fn add(&mut self, pid: u32, stdin: Option<ProcStdin>, pty: Option<PtyMaster>, launch: Launch) {
+		self.prune();
+		let proc = Proc {
+			pid,
+			stdin: stdin.map(|stdin| Rc::new(Mutex::new(stdin))),
+			pty,
+			launch: Some(launch),
+			attachment: None,
+			start_time: None,
+			started: SystemTime::now(),
+			finished: None,
+			exited: watch::Sender::new(false)
+		};
+		self.procs.insert(proc.pid, proc);
+	}
+
+	/// Tracks a process that can outlive the connection that launched it
+	pub fn add_persistent(&mut self, pid: u32, stdin: Option<ProcStdin>, pty: Option<PtyMaster>, launch: Launch, attachment: Rc<Mutex<Attachment>>) {
+		self.prune();
+		let proc = Proc {
+			pid,
+			stdin: stdin.map(|stdin| Rc::new(Mutex::new(stdin))),
+			pty,
+			launch: Some(launch),
+			attachment: Some(attachment),
+			start_time: read_start_time(pid),
+			started: SystemTime::now(),
+			finished: None,
+			exited: watch::Sender::new(false)
+		};
+		self.procs.insert(proc.pid, proc);
+		self.save_state();
+	}
+
+	/// Tracks a persistent process that was launched by a previous instance of the host processor
+	pub fn add_recovered(&mut self, pid: u32, start_time: u64, attachment: Rc<Mutex<Attachment>>) {
+		self.prune();
 		let proc = Proc {
 			pid,
-			stdin: proc.stdin.take()
+			stdin: None,
+			pty: None,
+			launch: None,
+			attachment: Some(attachment),
+			start_time: Some(start_time),
+			started: start_time_to_system_time(start_time)
+				.unwrap_or_else(SystemTime::now),
+			finished: None,
+			exited: watch::Sender::new(false)
 		};
 		self.procs.insert(proc.pid, proc);
 	}
 
+	/// Records that a process has exited.
+	/// The process stays around for the retention window, so clients can still query its status.
+	pub fn finish(&mut self, pid: u32, exit: Option<ProcessExit>, usage: Option<ResourceUsage>, limit: Option<LimitKind>) -> Option<()> {
+
+		let proc = self.procs.get_mut(&pid)?;
+
+		// close stdin, since no one is listening anymore
+		proc.stdin = None;
+		proc.pty = None;
+		proc.finished = Some(Finished {
+			time: SystemTime::now(),
+			instant: Instant::now(),
+			exit,
+			usage,
+			limit
+		});
+		proc.exited.send_replace(true);
+
+		// exited processes don't need to be recovered anymore
+		if proc.attachment.is_some() {
+			self.save_state();
+		}
+
+		Some(())
+	}
+
+	pub fn status(&mut self, pid: u32) -> ProcessStatus {
+
+		self.prune();
+
+		let Some(proc) = self.procs.get(&pid)
+			else { return ProcessStatus::Unknown; };
+
+		match &proc.finished {
+			None => ProcessStatus::Running {
+				start_time: millis_since_epoch(proc.started),
+				wall_time: millis_between(proc.started, SystemTime::now())
+			},
+			Some(finished) => ProcessStatus::Exited {
+				start_time: millis_since_epoch(proc.started),
+				wall_time: millis_between(proc.started, finished.time),
+				exit: finished.exit,
+				usage: finished.usage.clone(),
+				limit: finished.limit
+			}
+		}
+	}
+
+	/// Watches for a process to exit, if it's still running.
+	/// The watch closes without changing if the process stops being tracked first.
+	pub fn watch_exit(&self, pid: u32) -> Option<watch::Receiver<bool>> {
+		self.procs.get(&pid)
+			.filter(|proc| proc.finished.is_none())
+			.map(|proc| proc.exited.subscribe())
+	}
+
+	/// Lists all the tracked processes, ordered by pid
+	pub fn list(&mut self) -> Vec<ProcessInfo> {
+
+		self.prune();
+
+		let mut infos = self.procs.values()
+			.map(|proc| proc.info())
+			.collect::<Vec<_>>();
+		infos.sort_by_key(|info| info.pid);
+		infos
+	}
+
+	/// Stops tracking finished processes whose retention window has passed
+	fn prune(&mut self) {
+		let retention = self.retention;
+		self.procs.retain(|_, proc| {
+			let Some(finished) = &proc.finished
+				else { return true; };
+			if finished.instant.elapsed() < retention {
+				return true;
+			}
+			// keep persistent processes until an attached connection has received all their events
+			match &proc.attachment {
+				Some(attachment) => attachment.try_lock()
+					.map(|attachment| !attachment.is_done())
+					.unwrap_or(true),
+				None => false
+			}
+		});
+	}
+
+	pub fn get(&self, pid: u32) -> Option<&Proc> {
+		self.procs.get(&pid)
+	}
+
 	pub fn get_mut(&mut self, pid: u32) -> Option<&mut Proc> {
 		self.procs.get_mut(&pid)
 	}
 
 	pub fn remove(&mut self, pid: u32) -> Option<()> {
-		self.procs.remove(&pid)
-			.map(|_| ())
+		let proc = self.procs.remove(&pid)?;
+		if proc.attachment.is_some() {
+			self.save_state();
+		}
+		Some(())
+	}
+
+	/// The pids of the processes that are still running, ordered by pid
+	pub fn running(&self) -> Vec<u32> {
+		let mut pids = self.procs.values()
+			.filter(|proc| proc.finished.is_none())
+			.map(|proc| proc.pid)
+			.collect::<Vec<_>>();
+		pids.sort();
+		pids
+	}
+
+	fn save_state(&self) {
+
+		let Some(path) = &self.state_path
+			else { return; };
+
+		let entries = self.procs.values()
+			.filter_map(|proc| match (&proc.attachment, proc.start_time, &proc.finished) {
+				(Some(_), Some(start_time), None) => Some((proc.pid, start_time)),
+				_ => None
+			});
+		write_state(path, entries)
+			.context(format!("Failed to write state file: {}", path.to_string_lossy()))
+			.warn_err()
+			.ok();
+	}
+
+	/// Writes every running process, persistent or not, to a handoff file in the same format as the state file,
+	/// so another host processor can recover them
+	pub fn write_handoff(&self, path: &Path) -> Result<()> {
+
+		let mut entries = self.procs.values()
+			.filter(|proc| proc.finished.is_none())
+			.filter_map(|proc| Some((proc.pid, proc.start_time.or_else(|| read_start_time(proc.pid))?)))
+			.collect::<Vec<_>>();
+		entries.sort();
+
+		write_state(path, entries)
+			.context(format!("Failed to write handoff file: {}", path.to_string_lossy()))
+	}
+
+	/// Reads the persistent processes (pid, start time) from a state file, if it exists
+	pub fn load_state(path: &Path) -> Result<Vec<(u32,u64)>> {
+
+		if !path.exists() {
+			return Ok(Vec::new());
+		}
+
+		let state = fs::read_to_string(path)
+			.context(format!("Failed to read state file: {}", path.to_string_lossy()))?;
+
+		state.lines()
+			.filter(|line| !line.trim().is_empty())
+			.map(|line| {
+				let (pid, start_time) = line.split_once(' ')
+					.context(format!("Invalid state file line: {}", line))?;
+				let pid = pid.parse::<u32>()
+					.context(format!("Invalid pid in state file: {}", pid))?;
+				let start_time = start_time.trim().parse::<u64>()
+					.context(format!("Invalid start time in state file: {}", start_time))?;
+				Ok((pid, start_time))
+			})
+			.collect()
 	}
 }
 
+/// Writes one line per process: pid start_time
+fn write_state(path: &Path, entries: impl IntoIterator<Item=(u32,u64)>) -> io::Result<()> {
+
+	let mut state = String::new();
+	for (pid, start_time) in entries {
+		state.push_str(&format!("{} {}\n", pid, start_time));
+	}
+
+	// write to a temp file first, then rename, so we never leave a partially-written state file
+	let tmp_path = path.with_extension("tmp");
+	fs::write(&tmp_path, state)?;
+	fs::rename(&tmp_path, path)
+}
+
 
 pub struct Proc {
 	pid: u32,
-	pub stdin: Option<ChildStdin>
+	/// with its own lock, so writing to a process that isn't reading doesn't hold up the whole collection
+	pub stdin: Option<Rc<Mutex<ProcStdin>>>,
+	/// the pseudo-terminal of the process, for ExecStdout::Pty
+	pub pty: Option<PtyMaster>,
+	/// how the process was launched, if it was launched by this instance of the host processor
+	launch: Option<Launch>,
+	/// for persistent processes, where to send the process events
+	pub attachment: Option<Rc<Mutex<Attachment>>>,
+	/// when the process started, in clock ticks after boot, to tell it apart from other processes that reuse the pid
+	start_time: Option<u64>,
+	started: SystemTime,
+	finished: Option<Finished>,
+	/// tells waiters when the process exits
+	exited: watch::Sender<bool>
+}
+
+impl Proc {
+
+	pub fn is_finished(&self) -> bool {
+		self.finished.is_some()
+	}
+
+	fn info(&self) -> ProcessInfo {
+
+		let (program, args, dir, stdio, connection) = match &self.launch {
+			Some(launch) => (
+				launch.program.clone(),
+				launch.args.clone(),
+				launch.dir.to_string_lossy().to_string(),
+				Some(launch.stdio.clone()),
+				Some(launch.connection)
+			),
+			None => {
+				// recovered process: look at /proc for what we can find
+				let mut cmdline = read_cmdline(self.pid)
+					.unwrap_or_default()
+					.into_iter();
+				let dir = fs::read_link(format!("/proc/{}/cwd", self.pid))
+					.map(|dir| dir.to_string_lossy().to_string())
+					.unwrap_or_default();
+				(cmdline.next().unwrap_or_default(), cmdline.collect(), dir, None, None)
+			}
+		};
+
+		ProcessInfo {
+			pid: self.pid,
+			program,
+			args,
+			dir,
+			start_time: millis_since_epoch(self.started),
+			running: self.finished.is_none(),
+			stdio,
+			connection
+		}
+	}
+}
+
+
+pub enum ProcStdin {
+	Pipe(ChildStdin),
+	Pty(PtyMaster)
+}
+
+impl ProcStdin {
+
+	pub async fn write_all(&mut self, chunk: &[u8]) -> io::Result<()> {
+		match self {
+			Self::Pipe(stdin) => stdin.write_all(chunk).await,
+			Self::Pty(pty) => pty.write_all(chunk).await
+		}
+	}
+
+	pub async fn close(&mut self) -> io::Result<()> {
+		match self {
+
+			// dropping the pipe closes it
+			Self::Pipe(_) => Ok(()),
+
+			// but the terminal stays open until the process exits, so send an end-of-file character instead (ie, Ctrl-D)
+			Self::Pty(pty) => pty.write_all(&[0x04]).await
+		}
+	}
+}
+
+
+/// How a process was launched by an Exec request
+pub struct Launch {
+	pub program: String,
+	pub args: Vec<String>,
+	pub dir: PathBuf,
+	pub stdio: ProcessStdio,
+	/// the id of the connection that sent the Exec request
+	pub connection: u32
+}
+
+
+struct Finished {
+	time: SystemTime,
+	/// for the retention window, since the system clock can change
+	instant: Instant,
+	exit: Option<ProcessExit>,
+	usage: Option<ResourceUsage>,
+	limit: Option<LimitKind>
+}
+
+
+/// Where to send the events of a persistent process.
+/// Events that happen while no connection is attached are buffered, up to a limit.
+pub struct Attachment {
+
+	/// the connection (and the request id on that connection) receiving the events, if any
+	pub socket: Option<(Rc<Mutex<OwnedWriteHalf>>, u32)>,
+
+	buffer: VecDeque<ProcessEvent>,
+	buffer_bytes: usize,
+	dropped_bytes: usize,
+
+	/// true when the process won't send any more events
+	pub closed: bool
+}
+
+impl Attachment {
+
+	/// max number of console bytes to buffer while no connection is attached
+	pub const MAX_BUFFER_BYTES: usize = 16*1024*1024;
+
+	pub fn new(socket: Option<(Rc<Mutex<OwnedWriteHalf>>, u32)>) -> Self {
+		Self {
+			socket,
+			buffer: VecDeque::new(),
+			buffer_bytes: 0,
+			dropped_bytes: 0,
+			closed: false
+		}
+	}
+
+	pub fn buffer(&mut self, event: ProcessEvent) {
+
+		self.buffer_bytes += event_bytes(&event);
+		self.buffer.push_back(event);
+
+		// if the buffer is too big, drop the oldest console output
+		while self.buffer_bytes > Self::MAX_BUFFER_BYTES {
+			let Some(event) = self.buffer.pop_front()
+				else { break; };
+			let bytes = event_bytes(&event);
+			self.buffer_bytes -= bytes;
+			if self.dropped_bytes == 0 {
+				tracing::warn!("Persistent process buffer full, dropping oldest console output");
+			}
+			self.dropped_bytes += bytes;
+		}
+	}
+
+	pub fn unbuffer(&mut self) -> Option<ProcessEvent> {
+		let event = self.buffer.pop_front()?;
+		self.buffer_bytes -= event_bytes(&event);
+		Some(event)
+	}
+
+	/// put back an event that couldn't be sent
+	pub fn rebuffer(&mut self, event: ProcessEvent) {
+		self.buffer_bytes += event_bytes(&event);
+		self.buffer.push_front(event);
+	}
+
+	/// true when there's nothing left to send
+	pub fn is_done(&self) -> bool {
+		self.closed && self.buffer.is_empty()
+	}
+}
+
+
+fn event_bytes(event: &ProcessEvent) -> usize {
+	match event {
+		ProcessEvent::Console { chunk, .. } => chunk.len(),
+		ProcessEvent::ConsoleLine { line, .. } => line.len(),
+		_ => 0
+	}
+}
+
+
+/// Reads the start time of the process from /proc, if the process exists
+pub fn read_start_time(pid: u32) -> Option<u64> {
+
+	let stat = fs::read_to_string(format!("/proc/{}/stat", pid))
+		.ok()?;
+
+	// the second field is the command name in parens, which can contain spaces (and parens!),
+	// so skip past the last paren, which leaves us at the third field
+	// see: https://man7.org/linux/man-pages/man5/proc_pid_stat.5.html
+	let (_, fields) = stat.rsplit_once(')')?;
+
+	// start time is the 22nd field
+	fields.split_whitespace()
+		.nth(22 - 3)?
+		.parse::<u64>()
+		.ok()
+}
+
+
+/// Reads the program and arguments of the process from /proc, if the process exists
+fn read_cmdline(pid: u32) -> Option<Vec<String>> {
+	let cmdline = fs::read(format!("/proc/{}/cmdline", pid))
+		.ok()?;
+	let args = cmdline.split(|&b| b == 0)
+		.filter(|arg| !arg.is_empty())
+		.map(|arg| String::from_utf8_lossy(arg).to_string())
+		.collect();
+	Some(args)
+}
+
+
+/// Converts a process start time from /proc (in clock ticks after boot) into a system time
+fn start_time_to_system_time(start_time: u64) -> Option<SystemTime> {
+
+	// get the boot time, in seconds since the epoch
+	let stat = fs::read_to_string("/proc/stat")
+		.ok()?;
+	let boot_time = stat.lines()
+		.find_map(|line| line.strip_prefix("btime "))?
+		.trim()
+		.parse::<u64>()
+		.ok()?;
+
+	let ticks_per_sec = match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+		t if t > 0 => t as u64,
+		_ => return None
+	};
+
+	let millis = boot_time*1000 + start_time*1000/ticks_per_sec;
+	Some(UNIX_EPOCH + Duration::from_millis(millis))
+}
+
+
+fn millis_since_epoch(time: SystemTime) -> u64 {
+	millis_between(UNIX_EPOCH, time)
+}
+
+
+fn millis_between(start: SystemTime, end: SystemTime) -> u64 {
+	end.duration_since(start)
+		.map(|d| d.as_millis() as u64)
+		.unwrap_or(0)
 }
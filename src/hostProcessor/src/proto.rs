@@ -12,6 +12,7 @@ pub struct RequestEnvelope {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::large_enum_variant)]
 pub enum Request {
 
 	Ping,
@@ -27,15 +28,20 @@ pub enum Request {
 	/// send a chunk to the stdin of a process launched with Exec
 	WriteStdin {
 		pid: u32,
-		chunk: Vec<u8>
+		chunk: Vec<u8>,
+		/// respond with a Stdin response when the chunk is written, or when writing fails,
+		/// otherwise errors are only logged
+		ack: bool
 	},
 
-	/// send a chunk to the stdin of a process launched with Exec
+	/// close the stdin of a process launched with Exec
 	CloseStdin {
-		pid: u32
+		pid: u32,
+		/// respond with a Stdin response when stdin is closed, or when closing fails
+		ack: bool
 	},
 
-	/// send SIGTERM to a process launched with Exec
+	/// send a signal to a process launched with Exec
 	Kill {
 		signal: KillSignal,
 		pid: u32,
@@ -65,7 +71,44 @@ pub enum Request {
 	/// lookup the gids for a uid
 	Gids {
 		uid: u32
-	}
+	},
+
+	/// receive the events of a persistent process launched with Exec,
+	/// including any events buffered while no connection was attached
+	Attach {
+		pid: u32
+	},
+
+	/// list all the processes launched with Exec that are still tracked
+	ListProcesses,
+
+	/// send SIGTERM to the process group of a process launched with Exec,
+	/// then SIGKILL if the process hasn't exited after the timeout
+	KillGracefully {
+		pid: u32,
+		/// in milliseconds
+		timeout: u32
+	},
+
+	/// change the terminal size of a process launched with ExecStdout::Pty
+	ResizePty {
+		pid: u32,
+		rows: u16,
+		cols: u16
+	},
+
+	/// get counters about the host processor itself, eg for monitoring
+	Stats,
+
+	/// wait for a process launched with Exec to exit, from any connection
+	Wait {
+		pid: u32,
+		/// in milliseconds, or None to wait as long as it takes
+		timeout: Option<u32>
+	},
+
+	/// launch a pipeline of processes, each one reading the stdout of the one before it
+	ExecPipeline(ExecPipelineRequest)
 }
 
 impl Request {
@@ -80,9 +123,42 @@ impl Request {
 	const ID_GROUPNAME: u32 = 9;
 	const ID_GID: u32 = 10;
 	const ID_GIDS: u32 = 11;
+	const ID_ATTACH: u32 = 12;
+	const ID_LIST_PROCESSES: u32 = 13;
+	const ID_KILL_GRACEFULLY: u32 = 14;
+	const ID_RESIZE_PTY: u32 = 15;
+	const ID_STATS: u32 = 16;
+	const ID_WAIT: u32 = 17;
+	const ID_EXEC_PIPELINE: u32 = 18;
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Ping => "Ping",
+			Self::Exec(..) => "Exec",
+			Self::Status { .. } => "Status",
+			Self::WriteStdin { .. } => "WriteStdin",
+			Self::CloseStdin { .. } => "CloseStdin",
+			Self::Kill { .. } => "Kill",
+			Self::Username { .. } => "Username",
+			Self::Uid { .. } => "Uid",
+			Self::Groupname { .. } => "Groupname",
+			Self::Gid { .. } => "Gid",
+			Self::Gids { .. } => "Gids",
+			Self::Attach { .. } => "Attach",
+			Self::ListProcesses => "ListProcesses",
+			Self::KillGracefully { .. } => "KillGracefully",
+			Self::ResizePty { .. } => "ResizePty",
+			Self::Stats => "Stats",
+			Self::Wait { .. } => "Wait",
+			Self::ExecPipeline(..) => "ExecPipeline"
+		}
+	}
 }
 
 
+/// Launches a process.
+/// Any stdio streams set to Fd use file descriptors sent in SCM_RIGHTS ancillary data along with the request's frame,
+/// one for each such stream, in stdin, stdout, stderr order
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ExecRequest {
 	pub program: String,
@@ -92,19 +168,526 @@ pub struct ExecRequest {
 	pub stdin: ExecStdin,
 	pub stdout: ExecStdout,
 	pub stderr: ExecStderr,
-	pub stream_fin: bool
+	pub stream_fin: bool,
+	pub limits: Option<ExecLimits>,
+	/// run the process as this user, instead of the host processor's user
+	pub uid: Option<u32>,
+	/// run the process in this group, defaults to the primary group of `uid`
+	pub gid: Option<u32>,
+	/// supplementary groups for the process, defaults to the groups of `uid`
+	pub gids: Option<Vec<u32>>,
+	/// keep the process (and buffer its events) after the launching connection closes,
+	/// so another connection can Attach to it later
+	pub persist: bool,
+	/// stream the console as whole lines (in ProcessEvent::ConsoleLine) instead of raw chunks
+	pub lines: Option<ConsoleLines>,
+	/// which of the host processor's environment variables the process gets before `envvars` are applied,
+	/// or None to get all of them
+	pub env: Option<ExecEnv>,
+	/// run the program inside this container, instead of directly on the host
+	pub container: Option<ExecContainer>
+}
+
+
+/// Launches a pipeline of processes, like `a | b | c` in a shell, but without the shell.
+/// The stages all share one process group, whose id is the pid of the first stage,
+/// and the pipeline is tracked as that one process, eg for Status, Wait, and Kill.
+/// File descriptors for stdio streams set to Fd are sent the same way as for ExecRequest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecPipelineRequest {
+	/// the processes to launch, where each stage's stdout feeds the next stage's stdin
+	pub stages: Vec<PipelineStage>,
+	pub dir: Option<String>,
+	/// for the first stage
+	pub stdin: ExecStdin,
+	/// for the last stage, anything except Pty
+	pub stdout: ExecStdout,
+	/// for every stage
+	pub stderr: ExecStderr,
+	pub stream_fin: bool,
+	/// limits for the whole pipeline, eg the wall time counts from when the first stage started
+	pub limits: Option<ExecLimits>,
+	pub uid: Option<u32>,
+	pub gid: Option<u32>,
+	pub gids: Option<Vec<u32>>,
+	pub lines: Option<ConsoleLines>,
+	pub env: Option<ExecEnv>
+}
+
+/// one process in a pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineStage {
+	pub program: String,
+	pub args: Vec<String>,
+	pub envvars: Vec<(String,String)>
+}
+
+impl PipelineStage {
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		out.write_utf8(&self.program)?;
+		out.write_vec(&self.args, |out, arg| {
+			out.write_utf8(arg)
+		})?;
+		out.write_vec(&self.envvars, |out, (k, v)| {
+			out.write_utf8(k)?;
+			out.write_utf8(v)?;
+			Ok(())
+		})?;
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		Ok(Self {
+			program: reader.read_utf8()?,
+			args: reader.read_vec(|r| r.read_utf8())?,
+			envvars: reader.read_vec(|r| {
+				let k = r.read_utf8()?;
+				let v = r.read_utf8()?;
+				Ok((k, v))
+			})?
+		})
+	}
+}
+
+
+/// how to split streamed console output into lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsoleLines {
+
+	/// longer lines are cut off at this many bytes and the rest of the line is dropped
+	pub max_line_length: u32,
+
+	/// once this many bytes of a line are waiting for a newline, send them as a partial line
+	pub max_partial: u32
+}
+
+impl ConsoleLines {
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		out.write_u32::<BigEndian>(self.max_line_length)?;
+		out.write_u32::<BigEndian>(self.max_partial)?;
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		Ok(Self {
+			max_line_length: reader.read_u32::<BigEndian>()?,
+			max_partial: reader.read_u32::<BigEndian>()?
+		})
+	}
+}
+
+
+/// the environment for a process launched with Exec
+/// NOTE: the host processor's own [exec] env settings always apply first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecEnv {
+
+	pub mode: EnvMode,
+
+	/// variables to remove from the environment, after applying the mode
+	pub unset: Vec<String>
+}
+
+impl ExecEnv {
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		self.mode.write(out)?;
+		out.write_vec(&self.unset, |out, name| {
+			out.write_utf8(name)?;
+			Ok(())
+		})?;
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		Ok(Self {
+			mode: EnvMode::read(reader)?,
+			unset: reader.read_vec(|r| r.read_utf8())?
+		})
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvMode {
+	/// pass all of the host processor's environment variables
+	Inherit,
+	/// pass none of them
+	Clear,
+	/// pass only these ones
+	Allow(Vec<String>)
+}
+
+impl EnvMode {
+
+	const ID_INHERIT: u32 = 1;
+	const ID_CLEAR: u32 = 2;
+	const ID_ALLOW: u32 = 3;
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		match self {
+			Self::Inherit => {
+				out.write_u32::<BigEndian>(Self::ID_INHERIT)?;
+			}
+			Self::Clear => {
+				out.write_u32::<BigEndian>(Self::ID_CLEAR)?;
+			}
+			Self::Allow(names) => {
+				out.write_u32::<BigEndian>(Self::ID_ALLOW)?;
+				out.write_vec(names, |out, name| {
+					out.write_utf8(name)?;
+					Ok(())
+				})?;
+			}
+		}
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		let type_id = reader.read_u32::<BigEndian>()
+			.context("Failed to read env mode type id")?;
+		if type_id == Self::ID_INHERIT {
+			Ok(Self::Inherit)
+		} else if type_id == Self::ID_CLEAR {
+			Ok(Self::Clear)
+		} else if type_id == Self::ID_ALLOW {
+			Ok(Self::Allow(reader.read_vec(|r| r.read_utf8())?))
+		} else {
+			bail!("Unrecognized env mode type id: {}", type_id);
+		}
+	}
+}
+
+
+/// a container to run a process launched with Exec in, using apptainer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecContainer {
+
+	/// the container image, eg the path to a .sif file
+	pub image: String,
+
+	/// host folders to mount in the container, which must be inside the configured allowed_binds
+	pub binds: Vec<ContainerBind>,
+
+	/// variables to set inside the container, in addition to the ones apptainer passes through from the host
+	pub envvars: Vec<(String,String)>,
+
+	pub flags: Vec<ContainerFlag>,
+
+	/// the working folder inside the container, or None for apptainer's default
+	pub workdir: Option<String>
+}
+
+impl ExecContainer {
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		out.write_utf8(&self.image)?;
+		out.write_vec(&self.binds, |out, bind| bind.write(out))?;
+		out.write_vec(&self.envvars, |out, (k, v)| {
+			out.write_utf8(k)?;
+			out.write_utf8(v)?;
+			Ok(())
+		})?;
+		out.write_vec(&self.flags, |out, flag| flag.write(out))?;
+		out.write_option(&self.workdir, |out, dir| {
+			out.write_utf8(dir)
+		})?;
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		Ok(Self {
+			image: reader.read_utf8()?,
+			binds: reader.read_vec(ContainerBind::read)?,
+			envvars: reader.read_vec(|r| {
+				let k = r.read_utf8()?;
+				let v = r.read_utf8()?;
+				Ok((k, v))
+			})?,
+			flags: reader.read_vec(ContainerFlag::read)?,
+			workdir: reader.read_option(|r| r.read_utf8())?
+		})
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerBind {
+	/// the folder or file on the host
+	pub src: String,
+	/// where to mount it in the container, or None for the same path as the host
+	pub dst: Option<String>,
+	pub read_only: bool
+}
+
+impl ContainerBind {
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		out.write_utf8(&self.src)?;
+		out.write_option(&self.dst, |out, dst| {
+			out.write_utf8(dst)
+		})?;
+		out.write_bool(self.read_only)?;
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		Ok(Self {
+			src: reader.read_utf8()?,
+			dst: reader.read_option(|r| r.read_utf8())?,
+			read_only: reader.read_bool()?
+		})
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContainerFlag {
+	/// NVIDIA GPU support, ie --nv
+	Nv,
+	/// AMD GPU support, ie --rocm
+	Rocm,
+	/// don't pass the host's environment into the container, ie --cleanenv
+	CleanEnv,
+	/// don't mount the host's home, /tmp, etc in the container, ie --contain
+	Contain,
+	/// don't mount the home folder in the container, ie --no-home
+	NoHome
+}
+
+impl ContainerFlag {
+
+	const ID_NV: u32 = 1;
+	const ID_ROCM: u32 = 2;
+	const ID_CLEAN_ENV: u32 = 3;
+	const ID_CONTAIN: u32 = 4;
+	const ID_NO_HOME: u32 = 5;
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		out.write_u32::<BigEndian>(match self {
+			Self::Nv => Self::ID_NV,
+			Self::Rocm => Self::ID_ROCM,
+			Self::CleanEnv => Self::ID_CLEAN_ENV,
+			Self::Contain => Self::ID_CONTAIN,
+			Self::NoHome => Self::ID_NO_HOME
+		})?;
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		let type_id = reader.read_u32::<BigEndian>()
+			.context("Failed to read container flag type id")?;
+		match type_id {
+			Self::ID_NV => Ok(Self::Nv),
+			Self::ID_ROCM => Ok(Self::Rocm),
+			Self::ID_CLEAN_ENV => Ok(Self::CleanEnv),
+			Self::ID_CONTAIN => Ok(Self::Contain),
+			Self::ID_NO_HOME => Ok(Self::NoHome),
+			_ => bail!("Unrecognized container flag type id: {}", type_id)
+		}
+	}
+}
+
+
+/// resource limits to apply to a process launched with Exec
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecLimits {
+
+	/// max size of the virtual address space, in bytes (RLIMIT_AS)
+	pub address_space: Option<u64>,
+
+	/// max CPU time, in seconds (RLIMIT_CPU)
+	pub cpu_time: Option<u64>,
+
+	/// max number of open file descriptors (RLIMIT_NOFILE)
+	pub open_files: Option<u64>,
+
+	/// max number of processes for the user (RLIMIT_NPROC)
+	/// NOTE: this counts every process owned by the user, not just the ones started by this process
+	pub processes: Option<u64>,
+
+	/// limits enforced by a cgroup (v2), for the process and all its subprocesses
+	pub cgroup: Option<ExecCgroup>,
+
+	/// max wall-clock time since the process started, in milliseconds,
+	/// after which the host processor terminates the process group
+	pub wall_time: Option<u64>,
+
+	/// max time without any console output, in milliseconds,
+	/// after which the host processor terminates the process group
+	/// NOTE: only output the host processor reads counts, so output sent to ExecStdout::Ignore or ExecStdout::Fd never resets the timer
+	pub idle_time: Option<u64>
+}
+
+impl ExecLimits {
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		out.write_option(&self.address_space, |out, bytes| {
+			out.write_u64::<BigEndian>(*bytes)?;
+			Ok(())
+		})?;
+		out.write_option(&self.cpu_time, |out, secs| {
+			out.write_u64::<BigEndian>(*secs)?;
+			Ok(())
+		})?;
+		out.write_option(&self.open_files, |out, num| {
+			out.write_u64::<BigEndian>(*num)?;
+			Ok(())
+		})?;
+		out.write_option(&self.processes, |out, num| {
+			out.write_u64::<BigEndian>(*num)?;
+			Ok(())
+		})?;
+		out.write_option(&self.cgroup, |out, cgroup| {
+			out.write_option(&cgroup.memory_max, |out, bytes| {
+				out.write_u64::<BigEndian>(*bytes)?;
+				Ok(())
+			})?;
+			out.write_option(&cgroup.cpu_max, |out, cpu_max| {
+				out.write_u64::<BigEndian>(cpu_max.quota)?;
+				out.write_u64::<BigEndian>(cpu_max.period)?;
+				Ok(())
+			})
+		})?;
+		out.write_option(&self.wall_time, |out, ms| {
+			out.write_u64::<BigEndian>(*ms)?;
+			Ok(())
+		})?;
+		out.write_option(&self.idle_time, |out, ms| {
+			out.write_u64::<BigEndian>(*ms)?;
+			Ok(())
+		})
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		Ok(Self {
+			address_space: reader.read_option(|r| {
+				let bytes = r.read_u64::<BigEndian>()?;
+				Ok(bytes)
+			})?,
+			cpu_time: reader.read_option(|r| {
+				let secs = r.read_u64::<BigEndian>()?;
+				Ok(secs)
+			})?,
+			open_files: reader.read_option(|r| {
+				let num = r.read_u64::<BigEndian>()?;
+				Ok(num)
+			})?,
+			processes: reader.read_option(|r| {
+				let num = r.read_u64::<BigEndian>()?;
+				Ok(num)
+			})?,
+			cgroup: reader.read_option(|r| {
+				Ok(ExecCgroup {
+					memory_max: r.read_option(|r| {
+						let bytes = r.read_u64::<BigEndian>()?;
+						Ok(bytes)
+					})?,
+					cpu_max: r.read_option(|r| {
+						Ok(CpuMax {
+							quota: r.read_u64::<BigEndian>()?,
+							period: r.read_u64::<BigEndian>()?
+						})
+					})?
+				})
+			})?,
+			wall_time: reader.read_option(|r| {
+				let ms = r.read_u64::<BigEndian>()?;
+				Ok(ms)
+			})?,
+			idle_time: reader.read_option(|r| {
+				let ms = r.read_u64::<BigEndian>()?;
+				Ok(ms)
+			})?
+		})
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecCgroup {
+
+	/// max memory usage, in bytes (memory.max)
+	pub memory_max: Option<u64>,
+
+	/// max CPU bandwidth (cpu.max)
+	pub cpu_max: Option<CpuMax>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuMax {
+
+	/// CPU time allowed in each period, in microseconds
+	pub quota: u64,
+
+	/// length of the period, in microseconds
+	pub period: u64
 }
 
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExecStdin {
 	Stream,
-	Ignore
+	Ignore,
+	/// read from a file descriptor sent with the request, see ExecRequest
+	Fd,
+	/// read from a file, which the host processor opens
+	File {
+		path: String
+	},
+	/// read these bytes, then end of file
+	/// NOTE: meant for small payloads, since the whole request has to fit in memory
+	Bytes(Vec<u8>)
 }
 
 impl ExecStdin {
+
 	const ID_STREAM: u32 = 1;
 	const ID_IGNORE: u32 = 2;
+	const ID_FD: u32 = 3;
+	const ID_FILE: u32 = 4;
+	const ID_BYTES: u32 = 5;
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		match self {
+			Self::Stream => {
+				out.write_u32::<BigEndian>(Self::ID_STREAM)?;
+			}
+			Self::Ignore => {
+				out.write_u32::<BigEndian>(Self::ID_IGNORE)?;
+			}
+			Self::Fd => {
+				out.write_u32::<BigEndian>(Self::ID_FD)?;
+			}
+			Self::File { path } => {
+				out.write_u32::<BigEndian>(Self::ID_FILE)?;
+				out.write_utf8(path)?;
+			}
+			Self::Bytes(bytes) => {
+				out.write_u32::<BigEndian>(Self::ID_BYTES)?;
+				out.write_bytes(bytes)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		let type_id = reader.read_u32::<BigEndian>()
+			.context("Failed to read exec stdin type id")?;
+		if type_id == Self::ID_STREAM {
+			Ok(Self::Stream)
+		} else if type_id == Self::ID_IGNORE {
+			Ok(Self::Ignore)
+		} else if type_id == Self::ID_FD {
+			Ok(Self::Fd)
+		} else if type_id == Self::ID_FILE {
+			Ok(Self::File {
+				path: reader.read_utf8()?
+			})
+		} else if type_id == Self::ID_BYTES {
+			Ok(Self::Bytes(reader.read_bytes()?))
+		} else {
+			bail!("Unrecognized exec stdin type id: {}", type_id);
+		}
+	}
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -114,14 +697,78 @@ pub enum ExecStdout {
 		path: String
 	},
 	Log,
-	Ignore
+	Ignore,
+	/// run the process in a pseudo-terminal and stream its output,
+	/// the terminal is also stdin for ExecStdin::Stream and stderr for ExecStderr::Merge
+	Pty,
+	/// write to a file, with options for appending, rotation, and timestamps
+	LogFile(LogFile),
+	/// write to a file descriptor sent with the request, see ExecRequest
+	Fd
 }
 
 impl ExecStdout {
+
 	const ID_STREAM: u32 = 1;
 	const ID_WRITE: u32 = 2;
 	const ID_LOG: u32 = 3;
 	const ID_IGNORE: u32 = 4;
+	const ID_PTY: u32 = 5;
+	const ID_LOG_FILE: u32 = 6;
+	const ID_FD: u32 = 7;
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		match self {
+			Self::Stream => {
+				out.write_u32::<BigEndian>(Self::ID_STREAM)?;
+			}
+			Self::Write { path } => {
+				out.write_u32::<BigEndian>(Self::ID_WRITE)?;
+				out.write_utf8(path)?;
+			}
+			Self::Log => {
+				out.write_u32::<BigEndian>(Self::ID_LOG)?;
+			}
+			Self::Ignore => {
+				out.write_u32::<BigEndian>(Self::ID_IGNORE)?;
+			}
+			Self::Pty => {
+				out.write_u32::<BigEndian>(Self::ID_PTY)?;
+			}
+			Self::LogFile(log_file) => {
+				out.write_u32::<BigEndian>(Self::ID_LOG_FILE)?;
+				log_file.write(out)?;
+			}
+			Self::Fd => {
+				out.write_u32::<BigEndian>(Self::ID_FD)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		let type_id = reader.read_u32::<BigEndian>()
+			.context("Failed to read exec stdout type id")?;
+		if type_id == Self::ID_STREAM {
+			Ok(Self::Stream)
+		} else if type_id == Self::ID_WRITE {
+			Ok(Self::Write {
+				path: reader.read_utf8()?
+			})
+		} else if type_id == Self::ID_LOG {
+			Ok(Self::Log)
+		} else if type_id == Self::ID_IGNORE {
+			Ok(Self::Ignore)
+		} else if type_id == Self::ID_PTY {
+			Ok(Self::Pty)
+		} else if type_id == Self::ID_LOG_FILE {
+			Ok(Self::LogFile(LogFile::read(reader)?))
+		} else if type_id == Self::ID_FD {
+			Ok(Self::Fd)
+		} else {
+			bail!("Unrecognized exec stdout type id: {}", type_id);
+		}
+	}
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -132,41 +779,183 @@ pub enum ExecStderr {
 	},
 	Merge,
 	Log,
-	Ignore
+	Ignore,
+	/// write to a file, with options for appending, rotation, and timestamps
+	LogFile(LogFile),
+	/// write to a file descriptor sent with the request, see ExecRequest
+	Fd
 }
 
 impl ExecStderr {
+
 	const ID_STREAM: u32 = 1;
 	const ID_WRITE: u32 = 2;
 	const ID_MERGE: u32 = 3;
 	const ID_LOG: u32 = 4;
 	const ID_IGNORE: u32 = 5;
+	const ID_LOG_FILE: u32 = 6;
+	const ID_FD: u32 = 7;
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		match self {
+			Self::Stream => {
+				out.write_u32::<BigEndian>(Self::ID_STREAM)?;
+			}
+			Self::Write { path } => {
+				out.write_u32::<BigEndian>(Self::ID_WRITE)?;
+				out.write_utf8(path)?;
+			}
+			Self::Merge => {
+				out.write_u32::<BigEndian>(Self::ID_MERGE)?;
+			}
+			Self::Log => {
+				out.write_u32::<BigEndian>(Self::ID_LOG)?;
+			}
+			Self::Ignore => {
+				out.write_u32::<BigEndian>(Self::ID_IGNORE)?;
+			}
+			Self::LogFile(log_file) => {
+				out.write_u32::<BigEndian>(Self::ID_LOG_FILE)?;
+				log_file.write(out)?;
+			}
+			Self::Fd => {
+				out.write_u32::<BigEndian>(Self::ID_FD)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		let type_id = reader.read_u32::<BigEndian>()
+			.context("Failed to read exec stderr type id")?;
+		if type_id == Self::ID_STREAM {
+			Ok(Self::Stream)
+		} else if type_id == Self::ID_WRITE {
+			Ok(Self::Write {
+				path: reader.read_utf8()?
+			})
+		} else if type_id == Self::ID_MERGE {
+			Ok(Self::Merge)
+		} else if type_id == Self::ID_LOG {
+			Ok(Self::Log)
+		} else if type_id == Self::ID_IGNORE {
+			Ok(Self::Ignore)
+		} else if type_id == Self::ID_LOG_FILE {
+			Ok(Self::LogFile(LogFile::read(reader)?))
+		} else if type_id == Self::ID_FD {
+			Ok(Self::Fd)
+		} else {
+			bail!("Unrecognized exec stderr type id: {}", type_id);
+		}
+	}
 }
 
 
+/// a file for the output of a process launched with Exec
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFile {
+
+	pub path: String,
+
+	/// append to the file if it already exists, instead of truncating it
+	pub append: bool,
+
+	/// start a new file when this one gets too big, if any
+	pub rotation: Option<LogRotation>,
+
+	/// prefix each line with the local time
+	pub timestamps: bool
+}
+
+impl LogFile {
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		out.write_utf8(&self.path)?;
+		out.write_bool(self.append)?;
+		out.write_option(&self.rotation, |out, rotation| {
+			out.write_u64::<BigEndian>(rotation.max_size)?;
+			out.write_u32::<BigEndian>(rotation.generations)?;
+			out.write_bool(rotation.gzip)?;
+			Ok(())
+		})?;
+		out.write_bool(self.timestamps)?;
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		Ok(Self {
+			path: reader.read_utf8()?,
+			append: reader.read_bool()?,
+			rotation: reader.read_option(|r| {
+				Ok(LogRotation {
+					max_size: r.read_u64::<BigEndian>()?,
+					generations: r.read_u32::<BigEndian>()?,
+					gzip: r.read_bool()?
+				})
+			})?,
+			timestamps: reader.read_bool()?
+		})
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogRotation {
+
+	/// rotate the file once it reaches this size, in bytes
+	pub max_size: u64,
+
+	/// how many rotated files to keep, named eg `path.1`, `path.2`, etc, with `path.1` being the newest
+	pub generations: u32,
+
+	/// compress the rotated files with gzip, adding a `.gz` extension
+	pub gzip: bool
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KillSignal {
 	Interrupt,
-	Kill
+	Kill,
+	Terminate,
+	Hangup,
+	User1,
+	User2,
+	Stop,
+	Continue,
+	Quit
 }
 
 impl KillSignal {
 
+	pub const ALL: [Self; 9] = [
+		Self::Interrupt,
+		Self::Kill,
+		Self::Terminate,
+		Self::Hangup,
+		Self::User1,
+		Self::User2,
+		Self::Stop,
+		Self::Continue,
+		Self::Quit
+	];
+
 	pub fn name(&self) -> &'static str {
 		match self {
 			Self::Interrupt => "SIGINT",
-			Self::Kill => "SIGKILL"
+			Self::Kill => "SIGKILL",
+			Self::Terminate => "SIGTERM",
+			Self::Hangup => "SIGHUP",
+			Self::User1 => "SIGUSR1",
+			Self::User2 => "SIGUSR2",
+			Self::Stop => "SIGSTOP",
+			Self::Continue => "SIGCONT",
+			Self::Quit => "SIGQUIT"
 		}
 	}
 
 	fn from(s: &str) -> Option<Self> {
-		if Self::Interrupt.name() == s {
-			Some(Self::Interrupt)
-		} else if Self::Kill.name() == s {
-			Some(Self::Kill)
-		} else {
-			None
-		}
+		Self::ALL.into_iter()
+			.find(|signal| signal.name() == s)
 	}
 }
 
@@ -199,48 +988,29 @@ impl RequestEnvelope {
 					out.write_utf8(v)?;
 					Ok(())
 				})?;
-				match &request.stdin {
-					ExecStdin::Stream => {
-						out.write_u32::<BigEndian>(ExecStdin::ID_STREAM)?;
-					}
-					ExecStdin::Ignore => {
-						out.write_u32::<BigEndian>(ExecStdin::ID_IGNORE)?;
-					}
-				}
-				match &request.stdout {
-					ExecStdout::Stream => {
-						out.write_u32::<BigEndian>(ExecStdout::ID_STREAM)?;
-					}
-					ExecStdout::Write { path } => {
-						out.write_u32::<BigEndian>(ExecStdout::ID_WRITE)?;
-						out.write_utf8(path)?;
-					}
-					ExecStdout::Log => {
-						out.write_u32::<BigEndian>(ExecStdout::ID_LOG)?;
-					}
-					ExecStdout::Ignore => {
-						out.write_u32::<BigEndian>(ExecStdout::ID_IGNORE)?;
-					}
-				}
-				match &request.stderr {
-					ExecStderr::Stream => {
-						out.write_u32::<BigEndian>(ExecStderr::ID_STREAM)?;
-					}
-					ExecStderr::Write { path } => {
-						out.write_u32::<BigEndian>(ExecStderr::ID_WRITE)?;
-						out.write_utf8(path)?;
-					}
-					ExecStderr::Merge => {
-						out.write_u32::<BigEndian>(ExecStderr::ID_MERGE)?;
-					}
-					ExecStderr::Log => {
-						out.write_u32::<BigEndian>(ExecStderr::ID_LOG)?;
-					}
-					ExecStderr::Ignore => {
-						out.write_u32::<BigEndian>(ExecStderr::ID_IGNORE)?;
-					}
-				}
+				request.stdin.write(&mut out)?;
+				request.stdout.write(&mut out)?;
+				request.stderr.write(&mut out)?;
 				out.write_bool(request.stream_fin)?;
+				out.write_option(&request.limits, |out, limits| limits.write(out))?;
+				out.write_option(&request.uid, |out, uid| {
+					out.write_u32::<BigEndian>(*uid)?;
+					Ok(())
+				})?;
+				out.write_option(&request.gid, |out, gid| {
+					out.write_u32::<BigEndian>(*gid)?;
+					Ok(())
+				})?;
+				out.write_option(&request.gids, |out, gids| {
+					out.write_vec(gids, |out, gid| {
+						out.write_u32::<BigEndian>(*gid)?;
+						Ok(())
+					})
+				})?;
+				out.write_bool(request.persist)?;
+				out.write_option(&request.lines, |out, lines| lines.write(out))?;
+				out.write_option(&request.env, |out, env| env.write(out))?;
+				out.write_option(&request.container, |out, container| container.write(out))?;
 			}
 
 			Request::Status { pid } => {
@@ -248,15 +1018,17 @@ impl RequestEnvelope {
 				out.write_u32::<BigEndian>(*pid)?;
 			}
 
-			Request::WriteStdin { pid, chunk } => {
+			Request::WriteStdin { pid, chunk, ack } => {
 				out.write_u32::<BigEndian>(Request::ID_WRITE_STDIN)?;
 				out.write_u32::<BigEndian>(*pid)?;
 				out.write_bytes(chunk)?;
+				out.write_bool(*ack)?;
 			}
 
-			Request::CloseStdin { pid} => {
+			Request::CloseStdin { pid, ack } => {
 				out.write_u32::<BigEndian>(Request::ID_CLOSE_STDIN)?;
 				out.write_u32::<BigEndian>(*pid)?;
+				out.write_bool(*ack)?;
 			}
 
 			Request::Kill { signal, pid, process_group } => {
@@ -290,6 +1062,72 @@ impl RequestEnvelope {
 				out.write_u32::<BigEndian>(Request::ID_GIDS)?;
 				out.write_u32::<BigEndian>(*uid)?;
 			}
+
+			Request::Attach { pid } => {
+				out.write_u32::<BigEndian>(Request::ID_ATTACH)?;
+				out.write_u32::<BigEndian>(*pid)?;
+			}
+
+			Request::ListProcesses => {
+				// only need the type id
+				out.write_u32::<BigEndian>(Request::ID_LIST_PROCESSES)?;
+			}
+
+			Request::KillGracefully { pid, timeout } => {
+				out.write_u32::<BigEndian>(Request::ID_KILL_GRACEFULLY)?;
+				out.write_u32::<BigEndian>(*pid)?;
+				out.write_u32::<BigEndian>(*timeout)?;
+			}
+
+			Request::Stats => {
+				// only need the type id
+				out.write_u32::<BigEndian>(Request::ID_STATS)?;
+			}
+
+			Request::Wait { pid, timeout } => {
+				out.write_u32::<BigEndian>(Request::ID_WAIT)?;
+				out.write_u32::<BigEndian>(*pid)?;
+				out.write_option(timeout, |out, timeout| {
+					out.write_u32::<BigEndian>(*timeout)?;
+					Ok(())
+				})?;
+			}
+
+			Request::ExecPipeline(request) => {
+				out.write_u32::<BigEndian>(Request::ID_EXEC_PIPELINE)?;
+				out.write_vec(&request.stages, |out, stage| stage.write(out))?;
+				out.write_option(&request.dir, |out, dir| {
+					out.write_utf8(dir)
+				})?;
+				request.stdin.write(&mut out)?;
+				request.stdout.write(&mut out)?;
+				request.stderr.write(&mut out)?;
+				out.write_bool(request.stream_fin)?;
+				out.write_option(&request.limits, |out, limits| limits.write(out))?;
+				out.write_option(&request.uid, |out, uid| {
+					out.write_u32::<BigEndian>(*uid)?;
+					Ok(())
+				})?;
+				out.write_option(&request.gid, |out, gid| {
+					out.write_u32::<BigEndian>(*gid)?;
+					Ok(())
+				})?;
+				out.write_option(&request.gids, |out, gids| {
+					out.write_vec(gids, |out, gid| {
+						out.write_u32::<BigEndian>(*gid)?;
+						Ok(())
+					})
+				})?;
+				out.write_option(&request.lines, |out, lines| lines.write(out))?;
+				out.write_option(&request.env, |out, env| env.write(out))?;
+			}
+
+			Request::ResizePty { pid, rows, cols } => {
+				out.write_u32::<BigEndian>(Request::ID_RESIZE_PTY)?;
+				out.write_u32::<BigEndian>(*pid)?;
+				out.write_u16::<BigEndian>(*rows)?;
+				out.write_u16::<BigEndian>(*cols)?;
+			}
 		}
 
 		Ok(out)
@@ -323,57 +1161,29 @@ impl RequestEnvelope {
 						let v = r.read_utf8()?;
 						Ok((k, v))
 					}).map_err(|e| (e, Some(request_id)))?,
-					stdin: {
-						let stdin_type_id = reader.read_u32::<BigEndian>()
-							.context("Failed to read exec stdin type id")
-							.map_err(|e| (e, Some(request_id)))?;
-						if stdin_type_id == ExecStdin::ID_STREAM {
-							ExecStdin::Stream
-						} else if stdin_type_id == ExecStdin::ID_IGNORE {
-							ExecStdin::Ignore
-						} else {
-							return Err((anyhow!("Unrecognized exec stdin type id: {}", stdin_type_id), Some(request_id)));
-						}
-					},
-					stdout: {
-						let stdout_type_id = reader.read_u32::<BigEndian>()
-							.context("Failed to read exec stdout type id")
-							.map_err(|e| (e, Some(request_id)))?;
-						if stdout_type_id == ExecStdout::ID_STREAM {
-							ExecStdout::Stream
-						} else if stdout_type_id == ExecStdout::ID_WRITE {
-							ExecStdout::Write {
-								path: reader.read_utf8().map_err(|e| (e, Some(request_id)))?
-							}
-						} else if stdout_type_id == ExecStdout::ID_LOG {
-							ExecStdout::Log
-						} else if stdout_type_id == ExecStdout::ID_IGNORE {
-							ExecStdout::Ignore
-						} else {
-							return Err((anyhow!("Unrecognized exec stdin type id: {}", stdout_type_id), Some(request_id)));
-						}
-					},
-					stderr: {
-						let stderr_type_id = reader.read_u32::<BigEndian>()
-							.context("Failed to read exec stderr type id")
-							.map_err(|e| (e, Some(request_id)))?;
-						if stderr_type_id == ExecStderr::ID_STREAM {
-							ExecStderr::Stream
-						} else if stderr_type_id == ExecStderr::ID_WRITE {
-							ExecStderr::Write {
-								path: reader.read_utf8().map_err(|e| (e, Some(request_id)))?
-							}
-						} else if stderr_type_id == ExecStderr::ID_MERGE {
-							ExecStderr::Merge
-						} else if stderr_type_id == ExecStderr::ID_LOG {
-							ExecStderr::Log
-						} else if stderr_type_id == ExecStderr::ID_IGNORE {
-							ExecStderr::Ignore
-						} else {
-							return Err((anyhow!("Unrecognized exec stdin type id: {}", stderr_type_id), Some(request_id)));
-						}
-					},
-					stream_fin: reader.read_bool().map_err(|e| (e, Some(request_id)))?
+					stdin: ExecStdin::read(&mut reader).map_err(|e| (e, Some(request_id)))?,
+					stdout: ExecStdout::read(&mut reader).map_err(|e| (e, Some(request_id)))?,
+					stderr: ExecStderr::read(&mut reader).map_err(|e| (e, Some(request_id)))?,
+					stream_fin: reader.read_bool().map_err(|e| (e, Some(request_id)))?,
+					limits: reader.read_option(ExecLimits::read).map_err(|e| (e, Some(request_id)))?,
+					uid: reader.read_option(|r| {
+						let uid = r.read_u32::<BigEndian>()?;
+						Ok(uid)
+					}).map_err(|e| (e, Some(request_id)))?,
+					gid: reader.read_option(|r| {
+						let gid = r.read_u32::<BigEndian>()?;
+						Ok(gid)
+					}).map_err(|e| (e, Some(request_id)))?,
+					gids: reader.read_option(|r| {
+						r.read_vec(|r| {
+							let gid = r.read_u32::<BigEndian>()?;
+							Ok(gid)
+						})
+					}).map_err(|e| (e, Some(request_id)))?,
+					persist: reader.read_bool().map_err(|e| (e, Some(request_id)))?,
+					lines: reader.read_option(ConsoleLines::read).map_err(|e| (e, Some(request_id)))?,
+					env: reader.read_option(ExecEnv::read).map_err(|e| (e, Some(request_id)))?,
+					container: reader.read_option(ExecContainer::read).map_err(|e| (e, Some(request_id)))?
 				})
 			} else if type_id == Request::ID_STATUS {
 				Request::Status {
@@ -382,23 +1192,25 @@ impl RequestEnvelope {
 			} else if type_id == Request::ID_WRITE_STDIN {
 				Request::WriteStdin {
 					pid: reader.read_u32::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?,
-					chunk: reader.read_bytes().map_err(|e| (e, Some(request_id)))?
+					chunk: reader.read_bytes().map_err(|e| (e, Some(request_id)))?,
+					ack: reader.read_bool().map_err(|e| (e, Some(request_id)))?
 				}
 			} else if type_id == Request::ID_CLOSE_STDIN {
 				Request::CloseStdin {
-					pid: reader.read_u32::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?
+					pid: reader.read_u32::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?,
+					ack: reader.read_bool().map_err(|e| (e, Some(request_id)))?
 				}
 			} else if type_id == Request::ID_KILL {
 				Request::Kill {
 					signal: {
-						let signal_str = reader.read_utf8().map_err(|e| (e.into(), Some(request_id)))?;
+						let signal_str = reader.read_utf8().map_err(|e| (e, Some(request_id)))?;
 						match KillSignal::from(&signal_str) {
 							Some(s) => s,
 							None => return Err((anyhow!("Unrecognized kill signal: {}", &signal_str), Some(request_id)))
 						}
 					},
 					pid: reader.read_u32::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?,
-					process_group: reader.read_bool().map_err(|e| (e.into(), Some(request_id)))?
+					process_group: reader.read_bool().map_err(|e| (e, Some(request_id)))?
 				}
 			} else if type_id == Request::ID_USERNAME {
 				Request::Username {
@@ -420,6 +1232,59 @@ impl RequestEnvelope {
 				Request::Gids {
 					uid: reader.read_u32::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?
 				}
+			} else if type_id == Request::ID_ATTACH {
+				Request::Attach {
+					pid: reader.read_u32::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?
+				}
+			} else if type_id == Request::ID_LIST_PROCESSES {
+				Request::ListProcesses
+			} else if type_id == Request::ID_KILL_GRACEFULLY {
+				Request::KillGracefully {
+					pid: reader.read_u32::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?,
+					timeout: reader.read_u32::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?
+				}
+			} else if type_id == Request::ID_RESIZE_PTY {
+				Request::ResizePty {
+					pid: reader.read_u32::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?,
+					rows: reader.read_u16::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?,
+					cols: reader.read_u16::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?
+				}
+			} else if type_id == Request::ID_STATS {
+				Request::Stats
+			} else if type_id == Request::ID_WAIT {
+				Request::Wait {
+					pid: reader.read_u32::<BigEndian>().map_err(|e| (e.into(), Some(request_id)))?,
+					timeout: reader.read_option(|r| {
+						let timeout = r.read_u32::<BigEndian>()?;
+						Ok(timeout)
+					}).map_err(|e| (e, Some(request_id)))?
+				}
+			} else if type_id == Request::ID_EXEC_PIPELINE {
+				Request::ExecPipeline(ExecPipelineRequest {
+					stages: reader.read_vec(PipelineStage::read).map_err(|e| (e, Some(request_id)))?,
+					dir: reader.read_option(|r| r.read_utf8()).map_err(|e| (e, Some(request_id)))?,
+					stdin: ExecStdin::read(&mut reader).map_err(|e| (e, Some(request_id)))?,
+					stdout: ExecStdout::read(&mut reader).map_err(|e| (e, Some(request_id)))?,
+					stderr: ExecStderr::read(&mut reader).map_err(|e| (e, Some(request_id)))?,
+					stream_fin: reader.read_bool().map_err(|e| (e, Some(request_id)))?,
+					limits: reader.read_option(ExecLimits::read).map_err(|e| (e, Some(request_id)))?,
+					uid: reader.read_option(|r| {
+						let uid = r.read_u32::<BigEndian>()?;
+						Ok(uid)
+					}).map_err(|e| (e, Some(request_id)))?,
+					gid: reader.read_option(|r| {
+						let gid = r.read_u32::<BigEndian>()?;
+						Ok(gid)
+					}).map_err(|e| (e, Some(request_id)))?,
+					gids: reader.read_option(|r| {
+						r.read_vec(|r| {
+							let gid = r.read_u32::<BigEndian>()?;
+							Ok(gid)
+						})
+					}).map_err(|e| (e, Some(request_id)))?,
+					lines: reader.read_option(ConsoleLines::read).map_err(|e| (e, Some(request_id)))?,
+					env: reader.read_option(ExecEnv::read).map_err(|e| (e, Some(request_id)))?
+				})
 			} else {
 				return Err((anyhow!("Unrecognized request type id: {}", type_id), Some(request_id)));
 			};
@@ -446,15 +1311,20 @@ pub enum Response {
 	Pong,
 	Exec(ExecResponse),
 	ProcessEvent(ProcessEvent),
-	Status(bool),
-
-	// no response needed for kill
-
+	Status(ProcessStatus),
 	Username(Option<String>),
 	Uid(Option<u32>),
 	Groupname(Option<String>),
 	Gid(Option<u32>),
-	Gids(Option<Vec<u32>>)
+	Gids(Option<Vec<u32>>),
+
+	Attach(AttachResponse),
+	ListProcesses(Vec<ProcessInfo>),
+	Kill(KillResponse),
+	KillGracefully(KillGracefullyResponse),
+	Stats(HostStats),
+	Wait(WaitResponse),
+	Stdin(StdinResponse)
 }
 
 impl Response {
@@ -468,6 +1338,13 @@ impl Response {
 	const ID_GROUPNAME: u32 = 8;
 	const ID_GID: u32 = 9;
 	const ID_GIDS: u32 = 10;
+	const ID_ATTACH: u32 = 11;
+	const ID_LIST_PROCESSES: u32 = 12;
+	const ID_KILL: u32 = 13;
+	const ID_KILL_GRACEFULLY: u32 = 14;
+	const ID_STATS: u32 = 15;
+	const ID_WAIT: u32 = 16;
+	const ID_STDIN: u32 = 17;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -485,6 +1362,19 @@ impl ExecResponse {
 	const ID_FAILURE: u32 = 2;
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AttachResponse {
+	Success,
+	Failure {
+		reason: String
+	}
+}
+
+impl AttachResponse {
+	const ID_SUCCESS: u32 = 1;
+	const ID_FAILURE: u32 = 2;
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ProcessEvent {
 	Console {
@@ -492,13 +1382,293 @@ pub enum ProcessEvent {
 		chunk: Vec<u8>
 	},
 	Fin {
-		exit_code: Option<i32>
-	}
+		exit_code: Option<i32>,
+		/// the resource limit that ended the process, if any
+		limit: Option<LimitKind>,
+		/// for ExecPipeline requests, how each stage exited, in order, if known,
+		/// and `exit_code` is from the last stage, like in a shell.
+		/// Empty for Exec requests.
+		stages: Vec<Option<ProcessExit>>
+	},
+	/// a line of console output, for Exec requests with ConsoleLines
+	ConsoleLine {
+		kind: ConsoleKind,
+		/// when the line was read, in microseconds since the process started, from a monotonic clock
+		timestamp: u64,
+		/// the line, without the line ending
+		line: Vec<u8>,
+		/// the line continues in the next ConsoleLine of the same kind,
+		/// or the stream ended before the line did
+		partial: bool,
+		/// part of the line was dropped, because the line was longer than the max line length
+		truncated: bool
+	},
+	/// console output was dropped here, because the client wasn't keeping up
+	Dropped {
+		kind: ConsoleKind,
+		bytes: u64
+	},
+	/// the host processor shut down while the process was still running,
+	/// so there won't be any more events, not even a Fin
+	Detached
 }
 
 impl ProcessEvent {
 	const ID_CONSOLE: u32 = 1;
 	const ID_FIN: u32 = 2;
+	const ID_CONSOLE_LINE: u32 = 3;
+	const ID_DROPPED: u32 = 4;
+	const ID_DETACHED: u32 = 5;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KillResponse {
+	Success,
+	Failure(KillFailure)
+}
+
+impl KillResponse {
+	const ID_SUCCESS: u32 = 1;
+	const ID_FAILURE: u32 = 2;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KillGracefullyResponse {
+	/// the process exited after SIGTERM
+	Terminated,
+	/// the process didn't exit after SIGTERM before the timeout, so its process group got SIGKILL
+	Killed,
+	Failure(KillFailure)
+}
+
+impl KillGracefullyResponse {
+	const ID_TERMINATED: u32 = 1;
+	const ID_KILLED: u32 = 2;
+	const ID_FAILURE: u32 = 3;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WaitResponse {
+	/// the process exited, maybe before the request
+	Exited {
+		/// how the process exited, if known
+		exit: Option<ProcessExit>
+	},
+	/// the process was still running after the timeout
+	Timeout,
+	/// the process wasn't launched by Exec, or it finished longer ago than the retention window
+	Unknown
+}
+
+impl WaitResponse {
+	const ID_EXITED: u32 = 1;
+	const ID_TIMEOUT: u32 = 2;
+	const ID_UNKNOWN: u32 = 3;
+}
+
+/// the answer to a WriteStdin or CloseStdin request that asked for an ack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StdinResponse {
+	/// the whole chunk was written to stdin
+	Written {
+		bytes: u32,
+		/// how many more bytes of acked writes the connection can send before it has to wait for more acks
+		credit: u32
+	},
+	Closed,
+	Failure {
+		reason: String
+	}
+}
+
+impl StdinResponse {
+	const ID_WRITTEN: u32 = 1;
+	const ID_CLOSED: u32 = 2;
+	const ID_FAILURE: u32 = 3;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KillFailure {
+	/// the error number from kill(2), if the failure came from there, eg ESRCH or EPERM
+	pub errno: Option<i32>,
+	pub reason: String
+}
+
+impl KillFailure {
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		out.write_option(&self.errno, |out, errno| {
+			out.write_i32::<BigEndian>(*errno)?;
+			Ok(())
+		})?;
+		out.write_utf8(&self.reason)?;
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		Ok(Self {
+			errno: reader.read_option(|reader| {
+				let errno = reader.read_i32::<BigEndian>()?;
+				Ok(errno)
+			})?,
+			reason: reader.read_utf8()?
+		})
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+	pub pid: u32,
+	pub program: String,
+	pub args: Vec<String>,
+	/// the working directory of the process
+	pub dir: String,
+	/// when the process started, in milliseconds since the Unix epoch
+	pub start_time: u64,
+	pub running: bool,
+	/// how the process stdio was configured by Exec, if known
+	pub stdio: Option<ProcessStdio>,
+	/// the id of the connection that launched the process, if known
+	pub connection: Option<u32>
+}
+
+/// Counters about the host processor itself, since it started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostStats {
+	/// in milliseconds
+	pub uptime: u64,
+	pub version: String,
+	/// connections that are open right now
+	pub connections: u32,
+	/// processes launched by Exec that are still running, including any recovered persistent processes
+	pub live_processes: u32,
+	/// processes launched by Exec
+	pub total_processes: u64,
+	/// console output read from processes to stream to clients, in bytes
+	pub bytes_streamed: u64,
+	/// requests that couldn't be decoded
+	pub decode_errors: u64,
+	/// Exec requests that didn't launch a process
+	pub exec_failures: u64
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessStdio {
+	pub stdin: ExecStdin,
+	pub stdout: ExecStdout,
+	pub stderr: ExecStderr
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessStatus {
+
+	/// the process wasn't launched by Exec, or it finished longer ago than the retention window
+	Unknown,
+
+	Running {
+		/// when the process started, in milliseconds since the Unix epoch
+		start_time: u64,
+		/// how long the process has been running, in milliseconds
+		wall_time: u64
+	},
+
+	Exited {
+		/// when the process started, in milliseconds since the Unix epoch
+		start_time: u64,
+		/// how long the process ran, in milliseconds
+		wall_time: u64,
+		/// how the process exited, if known
+		exit: Option<ProcessExit>,
+		/// resources used by the process and its waited-for subprocesses, if known
+		usage: Option<ResourceUsage>,
+		/// the resource limit that ended the process, if any
+		limit: Option<LimitKind>
+	}
+}
+
+impl ProcessStatus {
+
+	const ID_UNKNOWN: u32 = 1;
+	const ID_RUNNING: u32 = 2;
+	const ID_EXITED: u32 = 3;
+
+	pub fn is_running(&self) -> bool {
+		matches!(self, Self::Running { .. })
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessExit {
+	Code(i32),
+	Signal(i32)
+}
+
+impl ProcessExit {
+
+	const ID_CODE: u32 = 1;
+	const ID_SIGNAL: u32 = 2;
+
+	fn write(&self, out: &mut impl WriteBytesExt) -> Result<()> {
+		match self {
+			Self::Code(code) => {
+				out.write_u32::<BigEndian>(Self::ID_CODE)?;
+				out.write_i32::<BigEndian>(*code)?;
+			}
+			Self::Signal(signal) => {
+				out.write_u32::<BigEndian>(Self::ID_SIGNAL)?;
+				out.write_i32::<BigEndian>(*signal)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn read(reader: &mut impl ReadBytesExt) -> Result<Self> {
+		let kind = reader.read_u32::<BigEndian>()?;
+		match kind {
+			Self::ID_CODE => Ok(Self::Code(reader.read_i32::<BigEndian>()?)),
+			Self::ID_SIGNAL => Ok(Self::Signal(reader.read_i32::<BigEndian>()?)),
+			_ => bail!("Unrecognized process exit kind: {}", kind)
+		}
+	}
+}
+
+/// resource usage reported by wait4()
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+
+	/// CPU time spent in user mode, in microseconds
+	pub user_time: u64,
+
+	/// CPU time spent in kernel mode, in microseconds
+	pub system_time: u64,
+
+	/// max resident set size, in KiB
+	pub max_rss: u64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+	CpuTime,
+	Memory,
+	WallTime,
+	IdleTime
+}
+
+impl LimitKind {
+
+	pub const ID_CPU_TIME: u32 = 1;
+	pub const ID_MEMORY: u32 = 2;
+	pub const ID_WALL_TIME: u32 = 3;
+	pub const ID_IDLE_TIME: u32 = 4;
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::CpuTime => "cpu time",
+			Self::Memory => "memory",
+			Self::WallTime => "wall time",
+			Self::IdleTime => "idle time"
+		}
+	}
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -564,19 +1734,83 @@ impl ResponseEnvelope {
 						})?;
 						out.write_bytes(chunk.as_slice())?;
 					}
-					ProcessEvent::Fin { exit_code } => {
+					ProcessEvent::Fin { exit_code, limit, stages } => {
 						out.write_u32::<BigEndian>(ProcessEvent::ID_FIN)?;
-						out.write_option(&exit_code, |out, exit_code| {
+						out.write_option(exit_code, |out, exit_code| {
 							out.write_i32::<BigEndian>(*exit_code)?;
 							Ok(())
-						})?
+						})?;
+						out.write_option(limit, |out, limit| {
+							out.write_u32::<BigEndian>(match limit {
+								LimitKind::CpuTime => LimitKind::ID_CPU_TIME,
+								LimitKind::Memory => LimitKind::ID_MEMORY,
+								LimitKind::WallTime => LimitKind::ID_WALL_TIME,
+								LimitKind::IdleTime => LimitKind::ID_IDLE_TIME
+							})?;
+							Ok(())
+						})?;
+						out.write_vec(stages, |out, exit| {
+							out.write_option(exit, |out, exit| exit.write(out))
+						})?;
+					}
+					ProcessEvent::ConsoleLine { kind, timestamp, line, partial, truncated } => {
+						out.write_u32::<BigEndian>(ProcessEvent::ID_CONSOLE_LINE)?;
+						out.write_u32::<BigEndian>(match kind {
+							ConsoleKind::Stdout => ConsoleKind::ID_STDOUT,
+							ConsoleKind::Stderr => ConsoleKind::ID_STDERR
+						})?;
+						out.write_u64::<BigEndian>(*timestamp)?;
+						out.write_bytes(line.as_slice())?;
+						out.write_bool(*partial)?;
+						out.write_bool(*truncated)?;
+					}
+					ProcessEvent::Dropped { kind, bytes } => {
+						out.write_u32::<BigEndian>(ProcessEvent::ID_DROPPED)?;
+						out.write_u32::<BigEndian>(match kind {
+							ConsoleKind::Stdout => ConsoleKind::ID_STDOUT,
+							ConsoleKind::Stderr => ConsoleKind::ID_STDERR
+						})?;
+						out.write_u64::<BigEndian>(*bytes)?;
+					}
+					ProcessEvent::Detached => {
+						out.write_u32::<BigEndian>(ProcessEvent::ID_DETACHED)?;
 					}
 				}
 			}
 
 			Response::Status(status) => {
 				out.write_u32::<BigEndian>(Response::ID_STATUS)?;
-				out.write_bool(*status)?;
+				match status {
+					ProcessStatus::Unknown => {
+						out.write_u32::<BigEndian>(ProcessStatus::ID_UNKNOWN)?;
+					}
+					ProcessStatus::Running { start_time, wall_time } => {
+						out.write_u32::<BigEndian>(ProcessStatus::ID_RUNNING)?;
+						out.write_u64::<BigEndian>(*start_time)?;
+						out.write_u64::<BigEndian>(*wall_time)?;
+					}
+					ProcessStatus::Exited { start_time, wall_time, exit, usage, limit } => {
+						out.write_u32::<BigEndian>(ProcessStatus::ID_EXITED)?;
+						out.write_u64::<BigEndian>(*start_time)?;
+						out.write_u64::<BigEndian>(*wall_time)?;
+						out.write_option(exit, |out, exit| exit.write(out))?;
+						out.write_option(usage, |out, usage| {
+							out.write_u64::<BigEndian>(usage.user_time)?;
+							out.write_u64::<BigEndian>(usage.system_time)?;
+							out.write_u64::<BigEndian>(usage.max_rss)?;
+							Ok(())
+						})?;
+						out.write_option(limit, |out, limit| {
+							out.write_u32::<BigEndian>(match limit {
+								LimitKind::CpuTime => LimitKind::ID_CPU_TIME,
+								LimitKind::Memory => LimitKind::ID_MEMORY,
+								LimitKind::WallTime => LimitKind::ID_WALL_TIME,
+								LimitKind::IdleTime => LimitKind::ID_IDLE_TIME
+							})?;
+							Ok(())
+						})?;
+					}
+				}
 			}
 
 			Response::Username(username) => {
@@ -618,6 +1852,119 @@ impl ResponseEnvelope {
 					})
 				})?
 			}
+
+			Response::Attach(response) => {
+				out.write_u32::<BigEndian>(Response::ID_ATTACH)?;
+				match response {
+					AttachResponse::Success => {
+						out.write_u32::<BigEndian>(AttachResponse::ID_SUCCESS)?;
+					}
+					AttachResponse::Failure { reason } => {
+						out.write_u32::<BigEndian>(AttachResponse::ID_FAILURE)?;
+						out.write_utf8(reason)?;
+					}
+				}
+			}
+
+			Response::ListProcesses(procs) => {
+				out.write_u32::<BigEndian>(Response::ID_LIST_PROCESSES)?;
+				out.write_vec(procs, |out, proc| {
+					out.write_u32::<BigEndian>(proc.pid)?;
+					out.write_utf8(&proc.program)?;
+					out.write_vec(&proc.args, |out, arg| {
+						out.write_utf8(arg)
+					})?;
+					out.write_utf8(&proc.dir)?;
+					out.write_u64::<BigEndian>(proc.start_time)?;
+					out.write_bool(proc.running)?;
+					out.write_option(&proc.stdio, |out, stdio| {
+						stdio.stdin.write(out)?;
+						stdio.stdout.write(out)?;
+						stdio.stderr.write(out)?;
+						Ok(())
+					})?;
+					out.write_option(&proc.connection, |out, id| {
+						out.write_u32::<BigEndian>(*id)?;
+						Ok(())
+					})?;
+					Ok(())
+				})?;
+			}
+
+			Response::Kill(response) => {
+				out.write_u32::<BigEndian>(Response::ID_KILL)?;
+				match response {
+					KillResponse::Success => {
+						out.write_u32::<BigEndian>(KillResponse::ID_SUCCESS)?;
+					}
+					KillResponse::Failure(failure) => {
+						out.write_u32::<BigEndian>(KillResponse::ID_FAILURE)?;
+						failure.write(&mut out)?;
+					}
+				}
+			}
+
+			Response::KillGracefully(response) => {
+				out.write_u32::<BigEndian>(Response::ID_KILL_GRACEFULLY)?;
+				match response {
+					KillGracefullyResponse::Terminated => {
+						out.write_u32::<BigEndian>(KillGracefullyResponse::ID_TERMINATED)?;
+					}
+					KillGracefullyResponse::Killed => {
+						out.write_u32::<BigEndian>(KillGracefullyResponse::ID_KILLED)?;
+					}
+					KillGracefullyResponse::Failure(failure) => {
+						out.write_u32::<BigEndian>(KillGracefullyResponse::ID_FAILURE)?;
+						failure.write(&mut out)?;
+					}
+				}
+			}
+
+			Response::Stats(stats) => {
+				out.write_u32::<BigEndian>(Response::ID_STATS)?;
+				out.write_u64::<BigEndian>(stats.uptime)?;
+				out.write_utf8(&stats.version)?;
+				out.write_u32::<BigEndian>(stats.connections)?;
+				out.write_u32::<BigEndian>(stats.live_processes)?;
+				out.write_u64::<BigEndian>(stats.total_processes)?;
+				out.write_u64::<BigEndian>(stats.bytes_streamed)?;
+				out.write_u64::<BigEndian>(stats.decode_errors)?;
+				out.write_u64::<BigEndian>(stats.exec_failures)?;
+			}
+
+			Response::Wait(response) => {
+				out.write_u32::<BigEndian>(Response::ID_WAIT)?;
+				match response {
+					WaitResponse::Exited { exit } => {
+						out.write_u32::<BigEndian>(WaitResponse::ID_EXITED)?;
+						out.write_option(exit, |out, exit| exit.write(out))?;
+					}
+					WaitResponse::Timeout => {
+						out.write_u32::<BigEndian>(WaitResponse::ID_TIMEOUT)?;
+					}
+					WaitResponse::Unknown => {
+						out.write_u32::<BigEndian>(WaitResponse::ID_UNKNOWN)?;
+					}
+				}
+			}
+
+			Response::Stdin(response) => {
+				out.write_u32::<BigEndian>(Response::ID_STDIN)?;
+				match response {
+					StdinResponse::Written { bytes, credit } => {
+						out.write_u32::<BigEndian>(StdinResponse::ID_WRITTEN)?;
+						out.write_u32::<BigEndian>(*bytes)?;
+						out.write_u32::<BigEndian>(*credit)?;
+					}
+					StdinResponse::Closed => {
+						out.write_u32::<BigEndian>(StdinResponse::ID_CLOSED)?;
+					}
+					StdinResponse::Failure { reason } => {
+						out.write_u32::<BigEndian>(StdinResponse::ID_FAILURE)?;
+						out.write_utf8(reason)?;
+					}
+				}
+			}
 		}
 
 		Ok(out)
@@ -676,15 +2023,82 @@ impl ResponseEnvelope {
 							exit_code: reader.read_option(|reader| {
 								let c = reader.read_i32::<BigEndian>()?;
 								Ok(c)
-							})?
+							})?,
+							limit: reader.read_option(|reader| {
+								let kind = reader.read_u32::<BigEndian>()?;
+								match kind {
+									LimitKind::ID_CPU_TIME => Ok(LimitKind::CpuTime),
+									LimitKind::ID_MEMORY => Ok(LimitKind::Memory),
+									LimitKind::ID_WALL_TIME => Ok(LimitKind::WallTime),
+									LimitKind::ID_IDLE_TIME => Ok(LimitKind::IdleTime),
+									_ => bail!("Unrecognized limit kind: {}", kind)
+								}
+							})?,
+							stages: reader.read_vec(|reader| reader.read_option(ProcessExit::read))?
+						},
+						ProcessEvent::ID_CONSOLE_LINE => ProcessEvent::ConsoleLine {
+							kind: {
+								let kind = reader.read_u32::<BigEndian>()?;
+								match kind {
+									ConsoleKind::ID_STDOUT => ConsoleKind::Stdout,
+									ConsoleKind::ID_STDERR => ConsoleKind::Stderr,
+									_ => bail!("Unrecognized console kind: {}", kind)
+								}
+							},
+							timestamp: reader.read_u64::<BigEndian>()?,
+							line: reader.read_bytes()?,
+							partial: reader.read_bool()?,
+							truncated: reader.read_bool()?
+						},
+						ProcessEvent::ID_DROPPED => ProcessEvent::Dropped {
+							kind: {
+								let kind = reader.read_u32::<BigEndian>()?;
+								match kind {
+									ConsoleKind::ID_STDOUT => ConsoleKind::Stdout,
+									ConsoleKind::ID_STDERR => ConsoleKind::Stderr,
+									_ => bail!("Unrecognized console kind: {}", kind)
+								}
+							},
+							bytes: reader.read_u64::<BigEndian>()?
 						},
+						ProcessEvent::ID_DETACHED => ProcessEvent::Detached,
 						_ => bail!("Unrecognized response process event kind: {}", kind)
 					}
 				})
 			} else if type_id == Response::ID_STATUS {
-				Response::Status(
-					reader.read_bool()?
-				)
+				Response::Status({
+					let kind = reader.read_u32::<BigEndian>()?;
+					match kind {
+						ProcessStatus::ID_UNKNOWN => ProcessStatus::Unknown,
+						ProcessStatus::ID_RUNNING => ProcessStatus::Running {
+							start_time: reader.read_u64::<BigEndian>()?,
+							wall_time: reader.read_u64::<BigEndian>()?
+						},
+						ProcessStatus::ID_EXITED => ProcessStatus::Exited {
+							start_time: reader.read_u64::<BigEndian>()?,
+							wall_time: reader.read_u64::<BigEndian>()?,
+							exit: reader.read_option(ProcessExit::read)?,
+							usage: reader.read_option(|reader| {
+								Ok(ResourceUsage {
+									user_time: reader.read_u64::<BigEndian>()?,
+									system_time: reader.read_u64::<BigEndian>()?,
+									max_rss: reader.read_u64::<BigEndian>()?
+								})
+							})?,
+							limit: reader.read_option(|reader| {
+								let kind = reader.read_u32::<BigEndian>()?;
+								match kind {
+									LimitKind::ID_CPU_TIME => Ok(LimitKind::CpuTime),
+									LimitKind::ID_MEMORY => Ok(LimitKind::Memory),
+									LimitKind::ID_WALL_TIME => Ok(LimitKind::WallTime),
+									LimitKind::ID_IDLE_TIME => Ok(LimitKind::IdleTime),
+									_ => bail!("Unrecognized limit kind: {}", kind)
+								}
+							})?
+						},
+						_ => bail!("Unrecognized process status kind: {}", kind)
+					}
+				})
 			} else if type_id == Response::ID_USERNAME {
 				Response::Username(
 					reader.read_option(|reader| {
@@ -720,6 +2134,98 @@ impl ResponseEnvelope {
 						})
 					})?
 				)
+			} else if type_id == Response::ID_ATTACH {
+				Response::Attach({
+					let kind = reader.read_u32::<BigEndian>()?;
+					match kind {
+						AttachResponse::ID_SUCCESS => AttachResponse::Success,
+						AttachResponse::ID_FAILURE => AttachResponse::Failure {
+							reason: reader.read_utf8()?
+						},
+						_ => bail!("Unrecognized response attach kind: {}", kind)
+					}
+				})
+			} else if type_id == Response::ID_LIST_PROCESSES {
+				Response::ListProcesses(
+					reader.read_vec(|reader| {
+						Ok(ProcessInfo {
+							pid: reader.read_u32::<BigEndian>()?,
+							program: reader.read_utf8()?,
+							args: reader.read_vec(|reader| reader.read_utf8())?,
+							dir: reader.read_utf8()?,
+							start_time: reader.read_u64::<BigEndian>()?,
+							running: reader.read_bool()?,
+							stdio: reader.read_option(|reader| {
+								Ok(ProcessStdio {
+									stdin: ExecStdin::read(reader)?,
+									stdout: ExecStdout::read(reader)?,
+									stderr: ExecStderr::read(reader)?
+								})
+							})?,
+							connection: reader.read_option(|reader| {
+								let id = reader.read_u32::<BigEndian>()?;
+								Ok(id)
+							})?
+						})
+					})?
+				)
+			} else if type_id == Response::ID_KILL {
+				Response::Kill({
+					let kind = reader.read_u32::<BigEndian>()?;
+					match kind {
+						KillResponse::ID_SUCCESS => KillResponse::Success,
+						KillResponse::ID_FAILURE => KillResponse::Failure(KillFailure::read(&mut reader)?),
+						_ => bail!("Unrecognized response kill kind: {}", kind)
+					}
+				})
+			} else if type_id == Response::ID_KILL_GRACEFULLY {
+				Response::KillGracefully({
+					let kind = reader.read_u32::<BigEndian>()?;
+					match kind {
+						KillGracefullyResponse::ID_TERMINATED => KillGracefullyResponse::Terminated,
+						KillGracefullyResponse::ID_KILLED => KillGracefullyResponse::Killed,
+						KillGracefullyResponse::ID_FAILURE => KillGracefullyResponse::Failure(KillFailure::read(&mut reader)?),
+						_ => bail!("Unrecognized response kill gracefully kind: {}", kind)
+					}
+				})
+			} else if type_id == Response::ID_STATS {
+				Response::Stats(HostStats {
+					uptime: reader.read_u64::<BigEndian>()?,
+					version: reader.read_utf8()?,
+					connections: reader.read_u32::<BigEndian>()?,
+					live_processes: reader.read_u32::<BigEndian>()?,
+					total_processes: reader.read_u64::<BigEndian>()?,
+					bytes_streamed: reader.read_u64::<BigEndian>()?,
+					decode_errors: reader.read_u64::<BigEndian>()?,
+					exec_failures: reader.read_u64::<BigEndian>()?
+				})
+			} else if type_id == Response::ID_WAIT {
+				Response::Wait({
+					let kind = reader.read_u32::<BigEndian>()?;
+					match kind {
+						WaitResponse::ID_EXITED => WaitResponse::Exited {
+							exit: reader.read_option(ProcessExit::read)?
+						},
+						WaitResponse::ID_TIMEOUT => WaitResponse::Timeout,
+						WaitResponse::ID_UNKNOWN => WaitResponse::Unknown,
+						_ => bail!("Unrecognized response wait kind: {}", kind)
+					}
+				})
+			} else if type_id == Response::ID_STDIN {
+				Response::Stdin({
+					let kind = reader.read_u32::<BigEndian>()?;
+					match kind {
+						StdinResponse::ID_WRITTEN => StdinResponse::Written {
+							bytes: reader.read_u32::<BigEndian>()?,
+							credit: reader.read_u32::<BigEndian>()?
+						},
+						StdinResponse::ID_CLOSED => StdinResponse::Closed,
+						StdinResponse::ID_FAILURE => StdinResponse::Failure {
+							reason: reader.read_utf8()?
+						},
+						_ => bail!("Unrecognized response stdin kind: {}", kind)
+					}
+				})
 			} else {
 				bail!("Unrecognized response type id: {}", type_id);
 			};
@@ -736,7 +2242,7 @@ trait WriteExt {
 	fn write_bool(&mut self, b: bool) -> Result<()>;
 	fn write_bytes(&mut self, bytes: impl AsRef<[u8]>) -> Result<()>;
 	fn write_utf8(&mut self, s: impl AsRef<str>) -> Result<()>;
-	fn write_vec<T,F>(&mut self, v: &Vec<T>, f: F) -> Result<()>
+	fn write_vec<T,F>(&mut self, v: &[T], f: F) -> Result<()>
 		where F: Fn(&mut Self, &T) -> Result<()>;
 	fn write_option<T,F>(&mut self, opt: &Option<T>, f: F) -> Result<()>
 		where F: Fn(&mut Self, &T) -> Result<()>;
@@ -781,7 +2287,7 @@ impl<W> WriteExt for W
 		self.write_bytes(s.as_ref().as_bytes())
 	}
 
-	fn write_vec<T,F>(&mut self, v: &Vec<T>, f: F) -> Result<()>
+	fn write_vec<T,F>(&mut self, v: &[T], f: F) -> Result<()>
 		where
 			F: Fn(&mut Self, &T) -> Result<()>
 	{
@@ -931,7 +2437,15 @@ mod test {
 			stdin: ExecStdin::Stream,
 			stdout: ExecStdout::Stream,
 			stderr: ExecStderr::Stream,
-			stream_fin: true
+			stream_fin: true,
+			limits: None,
+			uid: None,
+			gid: None,
+			gids: None,
+			persist: false,
+			lines: None,
+			env: None,
+			container: None
 		}));
 		assert_roundtrip(Request::Exec(ExecRequest {
 			program: "program".to_string(),
@@ -944,31 +2458,131 @@ mod test {
 			stdin: ExecStdin::Ignore,
 			stdout: ExecStdout::Ignore,
 			stderr: ExecStderr::Ignore,
-			stream_fin: false
+			stream_fin: false,
+			limits: None,
+			uid: None,
+			gid: None,
+			gids: None,
+			persist: false,
+			lines: None,
+			env: None,
+			container: None
 		}));
 		assert_roundtrip(Request::Exec(ExecRequest {
 			program: "program".to_string(),
 			args: vec![],
 			dir: Some("/path/to/dir".to_string()),
 			envvars: vec![],
-			stdin: ExecStdin::Ignore,
+			stdin: ExecStdin::File {
+				path: "/path/to/in".to_string()
+			},
 			stdout: ExecStdout::Write {
 				path: "file".to_string()
 			},
 			stderr: ExecStderr::Write {
 				path: "file".to_string()
 			},
-			stream_fin: false
+			stream_fin: false,
+			limits: None,
+			uid: None,
+			gid: None,
+			gids: None,
+			persist: false,
+			lines: None,
+			env: None,
+			container: None
 		}));
 		assert_roundtrip(Request::Exec(ExecRequest {
 			program: "program".to_string(),
 			args: vec![],
 			dir: Some("/path/to/dir".to_string()),
 			envvars: vec![],
-			stdin: ExecStdin::Ignore,
+			stdin: ExecStdin::Bytes(b"hello".to_vec()),
 			stdout: ExecStdout::Stream,
 			stderr: ExecStderr::Merge,
-			stream_fin: false
+			stream_fin: false,
+			limits: None,
+			uid: None,
+			gid: None,
+			gids: None,
+			persist: false,
+			lines: None,
+			env: None,
+			container: None
+		}));
+		assert_roundtrip(Request::Exec(ExecRequest {
+			program: "program".to_string(),
+			args: vec![],
+			dir: None,
+			envvars: vec![],
+			stdin: ExecStdin::Ignore,
+			stdout: ExecStdout::LogFile(LogFile {
+				path: "out".to_string(),
+				append: true,
+				rotation: Some(LogRotation {
+					max_size: 1024*1024,
+					generations: 5,
+					gzip: true
+				}),
+				timestamps: true
+			}),
+			stderr: ExecStderr::LogFile(LogFile {
+				path: "err".to_string(),
+				append: false,
+				rotation: None,
+				timestamps: false
+			}),
+			stream_fin: false,
+			limits: None,
+			uid: None,
+			gid: None,
+			gids: None,
+			persist: false,
+			lines: None,
+			env: None,
+			container: None
+		}));
+		assert_roundtrip(Request::Exec(ExecRequest {
+			program: "program".to_string(),
+			args: vec![],
+			dir: None,
+			envvars: vec![],
+			stdin: ExecStdin::Stream,
+			stdout: ExecStdout::Pty,
+			stderr: ExecStderr::Merge,
+			stream_fin: true,
+			limits: None,
+			uid: None,
+			gid: None,
+			gids: None,
+			persist: false,
+			lines: Some(ConsoleLines {
+				max_line_length: 4096,
+				max_partial: 1024
+			}),
+			env: Some(ExecEnv {
+				mode: EnvMode::Allow(vec!["PATH".to_string(), "LANG".to_string()]),
+				unset: vec!["SECRET".to_string()]
+			}),
+			container: None
+		}));
+		assert_roundtrip(Request::Exec(ExecRequest {
+			program: "program".to_string(),
+			args: vec![],
+			dir: None,
+			envvars: vec![],
+			stdin: ExecStdin::Fd,
+			stdout: ExecStdout::Fd,
+			stderr: ExecStderr::Fd,
+			stream_fin: true,
+			limits: None,
+			uid: None,
+			gid: None,
+			gids: None,
+			persist: false,
+			lines: None,
+			env: None,
+			container: None
 		}));
 		assert_roundtrip(Request::Exec(ExecRequest {
 			program: "program".to_string(),
@@ -978,7 +2592,159 @@ mod test {
 			stdin: ExecStdin::Ignore,
 			stdout: ExecStdout::Ignore,
 			stderr: ExecStderr::Ignore,
-			stream_fin: false
+			stream_fin: false,
+			limits: None,
+			uid: None,
+			gid: None,
+			gids: None,
+			persist: false,
+			lines: None,
+			env: None,
+			container: None
+		}));
+
+		assert_roundtrip(Request::Exec(ExecRequest {
+			program: "program".to_string(),
+			args: vec![],
+			dir: None,
+			envvars: vec![],
+			stdin: ExecStdin::Ignore,
+			stdout: ExecStdout::Ignore,
+			stderr: ExecStderr::Ignore,
+			stream_fin: false,
+			limits: Some(ExecLimits {
+				address_space: Some(1024*1024*1024),
+				cpu_time: Some(60),
+				open_files: None,
+				processes: Some(5),
+				cgroup: Some(ExecCgroup {
+					memory_max: Some(512*1024*1024),
+					cpu_max: Some(CpuMax {
+						quota: 50_000,
+						period: 100_000
+					})
+				}),
+				wall_time: Some(60_000),
+				idle_time: None
+			}),
+			uid: None,
+			gid: None,
+			gids: None,
+			persist: false,
+			lines: None,
+			env: None,
+			container: None
+		}));
+		assert_roundtrip(Request::Exec(ExecRequest {
+			program: "program".to_string(),
+			args: vec![],
+			dir: None,
+			envvars: vec![],
+			stdin: ExecStdin::Ignore,
+			stdout: ExecStdout::Ignore,
+			stderr: ExecStderr::Ignore,
+			stream_fin: false,
+			limits: None,
+			uid: Some(5),
+			gid: Some(7),
+			gids: Some(vec![42, 43]),
+			persist: true,
+			lines: None,
+			env: None,
+			container: None
+		}));
+
+		assert_roundtrip(Request::Exec(ExecRequest {
+			program: "program".to_string(),
+			args: vec![],
+			dir: None,
+			envvars: vec![],
+			stdin: ExecStdin::Ignore,
+			stdout: ExecStdout::Stream,
+			stderr: ExecStderr::Stream,
+			stream_fin: true,
+			limits: None,
+			uid: None,
+			gid: None,
+			gids: None,
+			persist: false,
+			lines: None,
+			env: None,
+			container: Some(ExecContainer {
+				image: "/path/to/image.sif".to_string(),
+				binds: vec![
+					ContainerBind {
+						src: "/data".to_string(),
+						dst: None,
+						read_only: false
+					},
+					ContainerBind {
+						src: "/data/in".to_string(),
+						dst: Some("/in".to_string()),
+						read_only: true
+					}
+				],
+				envvars: vec![
+					("a".to_string(), "b".to_string())
+				],
+				flags: vec![ContainerFlag::Nv, ContainerFlag::CleanEnv],
+				workdir: Some("/in".to_string())
+			})
+		}));
+
+		assert_roundtrip(Request::ExecPipeline(ExecPipelineRequest {
+			stages: vec![],
+			dir: None,
+			stdin: ExecStdin::Stream,
+			stdout: ExecStdout::Stream,
+			stderr: ExecStderr::Stream,
+			stream_fin: true,
+			limits: None,
+			uid: None,
+			gid: None,
+			gids: None,
+			lines: None,
+			env: None
+		}));
+		assert_roundtrip(Request::ExecPipeline(ExecPipelineRequest {
+			stages: vec![
+				PipelineStage {
+					program: "grep".to_string(),
+					args: vec!["-v".to_string(), "foo".to_string()],
+					envvars: vec![]
+				},
+				PipelineStage {
+					program: "sort".to_string(),
+					args: vec![],
+					envvars: vec![
+						("LC_ALL".to_string(), "C".to_string())
+					]
+				}
+			],
+			dir: Some("/path/to/dir".to_string()),
+			stdin: ExecStdin::File {
+				path: "/path/to/in".to_string()
+			},
+			stdout: ExecStdout::Write {
+				path: "out".to_string()
+			},
+			stderr: ExecStderr::Merge,
+			stream_fin: false,
+			limits: Some(ExecLimits {
+				wall_time: Some(1000),
+				.. ExecLimits::default()
+			}),
+			uid: Some(5),
+			gid: Some(6),
+			gids: Some(vec![7, 8]),
+			lines: Some(ConsoleLines {
+				max_line_length: 4096,
+				max_partial: 1024
+			}),
+			env: Some(ExecEnv {
+				mode: EnvMode::Clear,
+				unset: vec![]
+			})
 		}));
 
 		assert_roundtrip(Request::Status {
@@ -987,11 +2753,22 @@ mod test {
 
 		assert_roundtrip(Request::WriteStdin {
 			pid: 37,
-			chunk: b"a line".to_vec()
+			chunk: b"a line".to_vec(),
+			ack: false
+		});
+		assert_roundtrip(Request::WriteStdin {
+			pid: 37,
+			chunk: b"a line".to_vec(),
+			ack: true
 		});
 
 		assert_roundtrip(Request::CloseStdin {
-			pid: 5
+			pid: 5,
+			ack: false
+		});
+		assert_roundtrip(Request::CloseStdin {
+			pid: 5,
+			ack: true
 		});
 
 		assert_roundtrip(Request::Kill {
@@ -1019,6 +2796,42 @@ mod test {
 		assert_roundtrip(Request::Gids {
 			uid: 7
 		});
+
+		assert_roundtrip(Request::Attach {
+			pid: 42
+		});
+
+		assert_roundtrip(Request::ListProcesses);
+
+		for signal in KillSignal::ALL {
+			assert_roundtrip(Request::Kill {
+				signal,
+				pid: 5,
+				process_group: true
+			});
+		}
+
+		assert_roundtrip(Request::KillGracefully {
+			pid: 5,
+			timeout: 2000
+		});
+
+		assert_roundtrip(Request::ResizePty {
+			pid: 5,
+			rows: 40,
+			cols: 120
+		});
+
+		assert_roundtrip(Request::Stats);
+
+		assert_roundtrip(Request::Wait {
+			pid: 5,
+			timeout: Some(1000)
+		});
+		assert_roundtrip(Request::Wait {
+			pid: 5,
+			timeout: None
+		});
 	}
 
 
@@ -1058,15 +2871,91 @@ mod test {
 			kind: ConsoleKind::Stderr,
 			chunk: b"oh noes!".to_vec(),
 		}));
+		assert_roundtrip(Response::ProcessEvent(ProcessEvent::ConsoleLine {
+			kind: ConsoleKind::Stderr,
+			timestamp: 1234567,
+			line: b"oh noes!".to_vec(),
+			partial: true,
+			truncated: false
+		}));
+		assert_roundtrip(Response::ProcessEvent(ProcessEvent::ConsoleLine {
+			kind: ConsoleKind::Stdout,
+			timestamp: 0,
+			line: vec![],
+			partial: false,
+			truncated: true
+		}));
+		assert_roundtrip(Response::ProcessEvent(ProcessEvent::Dropped {
+			kind: ConsoleKind::Stderr,
+			bytes: 65536
+		}));
+		assert_roundtrip(Response::ProcessEvent(ProcessEvent::Detached));
+		assert_roundtrip(Response::ProcessEvent(ProcessEvent::Fin {
+			exit_code: None,
+			limit: None,
+			stages: vec![]
+		}));
+		assert_roundtrip(Response::ProcessEvent(ProcessEvent::Fin {
+			exit_code: Some(-9),
+			limit: None,
+			stages: vec![]
+		}));
 		assert_roundtrip(Response::ProcessEvent(ProcessEvent::Fin {
-			exit_code: None
+			exit_code: None,
+			limit: Some(LimitKind::CpuTime),
+			stages: vec![]
 		}));
 		assert_roundtrip(Response::ProcessEvent(ProcessEvent::Fin {
-			exit_code: Some(-9)
+			exit_code: None,
+			limit: Some(LimitKind::Memory),
+			stages: vec![]
+		}));
+		assert_roundtrip(Response::ProcessEvent(ProcessEvent::Fin {
+			exit_code: None,
+			limit: Some(LimitKind::WallTime),
+			stages: vec![]
+		}));
+		assert_roundtrip(Response::ProcessEvent(ProcessEvent::Fin {
+			exit_code: None,
+			limit: Some(LimitKind::IdleTime),
+			stages: vec![]
+		}));
+		assert_roundtrip(Response::ProcessEvent(ProcessEvent::Fin {
+			exit_code: Some(0),
+			limit: None,
+			stages: vec![Some(ProcessExit::Signal(13)), None, Some(ProcessExit::Code(0))]
 		}));
 
-		assert_roundtrip(Response::Status(true));
-		assert_roundtrip(Response::Status(false));
+		assert_roundtrip(Response::Status(ProcessStatus::Unknown));
+		assert_roundtrip(Response::Status(ProcessStatus::Running {
+			start_time: 1700000000000,
+			wall_time: 42
+		}));
+		assert_roundtrip(Response::Status(ProcessStatus::Exited {
+			start_time: 1700000000000,
+			wall_time: 42,
+			exit: Some(ProcessExit::Code(5)),
+			usage: Some(ResourceUsage {
+				user_time: 1,
+				system_time: 2,
+				max_rss: 3
+			}),
+			limit: None
+		}));
+		assert_roundtrip(Response::Status(ProcessStatus::Exited {
+			start_time: 1700000000000,
+			wall_time: 42,
+			exit: Some(ProcessExit::Signal(9)),
+			usage: None,
+			limit: Some(LimitKind::CpuTime)
+		}));
+		assert_roundtrip(Response::Status(ProcessStatus::Exited {
+			start_time: 0,
+			wall_time: 0,
+			exit: None,
+			usage: None,
+			limit: None
+		}));
 
 		assert_roundtrip(Response::Username(Some("bob".to_string())));
 		assert_roundtrip(Response::Username(None));
@@ -1082,5 +2971,86 @@ mod test {
 
 		assert_roundtrip(Response::Gids(Some(vec![1, 2, 3])));
 		assert_roundtrip(Response::Gids(None));
+
+		assert_roundtrip(Response::Attach(AttachResponse::Success));
+		assert_roundtrip(Response::Attach(AttachResponse::Failure {
+			reason: "nope".to_string()
+		}));
+
+		assert_roundtrip(Response::ListProcesses(vec![]));
+		assert_roundtrip(Response::ListProcesses(vec![
+			ProcessInfo {
+				pid: 5,
+				program: "program".to_string(),
+				args: vec!["arg1".to_string(), "arg2".to_string()],
+				dir: "/path/to/dir".to_string(),
+				start_time: 1700000000000,
+				running: true,
+				stdio: Some(ProcessStdio {
+					stdin: ExecStdin::Stream,
+					stdout: ExecStdout::Write {
+						path: "/path/to/out".to_string()
+					},
+					stderr: ExecStderr::Merge
+				}),
+				connection: Some(42)
+			},
+			ProcessInfo {
+				pid: 7,
+				program: "program".to_string(),
+				args: vec![],
+				dir: "/".to_string(),
+				start_time: 0,
+				running: false,
+				stdio: None,
+				connection: None
+			}
+		]));
+
+		assert_roundtrip(Response::Kill(KillResponse::Success));
+		assert_roundtrip(Response::Kill(KillResponse::Failure(KillFailure {
+			errno: Some(3),
+			reason: "No such process".to_string()
+		})));
+		assert_roundtrip(Response::Kill(KillResponse::Failure(KillFailure {
+			errno: None,
+			reason: "Process not found".to_string()
+		})));
+
+		assert_roundtrip(Response::KillGracefully(KillGracefullyResponse::Terminated));
+		assert_roundtrip(Response::KillGracefully(KillGracefullyResponse::Killed));
+
+		assert_roundtrip(Response::Stats(HostStats {
+			uptime: 123456,
+			version: "0.1.0".to_string(),
+			connections: 2,
+			live_processes: 3,
+			total_processes: 42,
+			bytes_streamed: 1 << 40,
+			decode_errors: 1,
+			exec_failures: 5
+		}));
+		assert_roundtrip(Response::KillGracefully(KillGracefullyResponse::Failure(KillFailure {
+			errno: Some(1),
+			reason: "Operation not permitted".to_string()
+		})));
+
+		assert_roundtrip(Response::Wait(WaitResponse::Exited {
+			exit: Some(ProcessExit::Signal(15))
+		}));
+		assert_roundtrip(Response::Wait(WaitResponse::Exited {
+			exit: None
+		}));
+		assert_roundtrip(Response::Wait(WaitResponse::Timeout));
+		assert_roundtrip(Response::Wait(WaitResponse::Unknown));
+
+		assert_roundtrip(Response::Stdin(StdinResponse::Written {
+			bytes: 42,
+			credit: 1024
+		}));
+		assert_roundtrip(Response::Stdin(StdinResponse::Closed));
+		assert_roundtrip(Response::Stdin(StdinResponse::Failure {
+			reason: "Process stdin already closed".to_string()
+		}));
 	}
 }
@@ -0,0 +1,176 @@
+use std::ffi::CStr;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::io::unix::AsyncFd;
+
+use crate::proto::{ExecStderr, ExecStdin};
+
+
+/// default terminal size for new pseudo-terminals, until a client resizes it
+pub const DEFAULT_ROWS: u16 = 24;
+pub const DEFAULT_COLS: u16 = 80;
+
+
+/// The master side of a pseudo-terminal.
+/// Reading gets the terminal output, writing sends terminal input.
+/// Clones share the same terminal.
+#[derive(Clone)]
+pub struct PtyMaster {
+	fd: Rc<AsyncFd<OwnedFd>>
+}
+
+impl PtyMaster {
+
+	/// Opens a new pseudo-terminal, returning the master side, and the slave side for the child process
+	pub fn open() -> io::Result<(Self, OwnedFd)> {
+
+		let master = match unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC | libc::O_NONBLOCK) } {
+			-1 => return Err(io::Error::last_os_error()),
+			fd => unsafe { OwnedFd::from_raw_fd(fd) }
+		};
+		check(unsafe { libc::grantpt(master.as_raw_fd()) })?;
+		check(unsafe { libc::unlockpt(master.as_raw_fd()) })?;
+
+		// open the slave side
+		let mut name = [0 as libc::c_char; 256];
+		match unsafe { libc::ptsname_r(master.as_raw_fd(), name.as_mut_ptr(), name.len()) } {
+			0 => (),
+			e => return Err(io::Error::from_raw_os_error(e))
+		}
+		let name = unsafe { CStr::from_ptr(name.as_ptr()) };
+		let slave = match unsafe { libc::open(name.as_ptr(), libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC) } {
+			-1 => return Err(io::Error::last_os_error()),
+			fd => unsafe { OwnedFd::from_raw_fd(fd) }
+		};
+
+		let master = Self {
+			fd: Rc::new(AsyncFd::new(master)?)
+		};
+		master.resize(DEFAULT_ROWS, DEFAULT_COLS)?;
+
+		Ok((master, slave))
+	}
+
+	pub fn resize(&self, rows: u16, cols: u16) -> io::Result<()> {
+		let size = libc::winsize {
+			ws_row: rows,
+			ws_col: cols,
+			ws_xpixel: 0,
+			ws_ypixel: 0
+		};
+		check(unsafe { libc::ioctl(self.fd.as_raw_fd(), libc::TIOCSWINSZ, &size) })
+	}
+}
+
+impl AsFd for PtyMaster {
+	fn as_fd(&self) -> BorrowedFd<'_> {
+		self.fd.get_ref().as_fd()
+	}
+}
+
+impl AsyncRead for PtyMaster {
+
+	fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+		loop {
+			let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+			let unfilled = buf.initialize_unfilled();
+			let result = guard.try_io(|fd| {
+				match unsafe { libc::read(fd.as_raw_fd(), unfilled.as_mut_ptr() as *mut libc::c_void, unfilled.len()) } {
+					-1 => Err(io::Error::last_os_error()),
+					n => Ok(n as usize)
+				}
+			});
+			match result {
+				Ok(Ok(n)) => {
+					buf.advance(n);
+					return Poll::Ready(Ok(()));
+				}
+				// NOTE: Linux returns EIO once every process has closed the slave side, which is really just EOF
+				Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => return Poll::Ready(Ok(())),
+				Ok(Err(e)) => return Poll::Ready(Err(e)),
+				Err(_would_block) => continue
+			}
+		}
+	}
+}
+
+impl AsyncWrite for PtyMaster {
+
+	fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+		loop {
+			let mut guard = ready!(self.fd.poll_write_ready(cx))?;
+			let result = guard.try_io(|fd| {
+				match unsafe { libc::write(fd.as_raw_fd(), buf.as_ptr() as *const libc::c_void, buf.len()) } {
+					-1 => Err(io::Error::last_os_error()),
+					n => Ok(n as usize)
+				}
+			});
+			match result {
+				Ok(result) => return Poll::Ready(result),
+				Err(_would_block) => continue
+			}
+		}
+	}
+
+	fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+
+	fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+		Poll::Ready(Ok(()))
+	}
+}
+
+
+/// A new pseudo-terminal for an Exec request,
+/// with copies of the slave side for each of the process' stdio streams that should use it
+pub struct PtyStdio {
+	pub master: PtyMaster,
+	pub stdin: Option<OwnedFd>,
+	pub stdout: Option<OwnedFd>,
+	pub stderr: Option<OwnedFd>
+}
+
+impl PtyStdio {
+
+	pub fn open(stdin: &ExecStdin, stderr: &ExecStderr) -> io::Result<Self> {
+		let (master, slave) = PtyMaster::open()?;
+		Ok(Self {
+			master,
+			stdin: match stdin {
+				ExecStdin::Stream => Some(slave.try_clone()?),
+				_ => None
+			},
+			stderr: match stderr {
+				ExecStderr::Merge => Some(slave.try_clone()?),
+				_ => None
+			},
+			stdout: Some(slave)
+		})
+	}
+}
+
+
+/// Starts a new session for the current process, with the terminal as its controlling terminal.
+/// WARNING: This gets called in the child process after fork() but before exec(),
+///          so only async-signal-safe functions are allowed here. ie, no allocations!
+pub fn make_controlling_terminal(slave: RawFd) -> io::Result<()> {
+	// NOTE: the new session also gets a new process group, with the same id as the process
+	if unsafe { libc::setsid() } == -1 {
+		return Err(io::Error::last_os_error());
+	}
+	check(unsafe { libc::ioctl(slave, libc::TIOCSCTTY, 0) })
+}
+
+
+fn check(result: libc::c_int) -> io::Result<()> {
+	match result {
+		0 => Ok(()),
+		_ => Err(io::Error::last_os_error())
+	}
+}
@@ -0,0 +1,27 @@
+use std::str::FromStr;
+
+
+/// What to do with the processes that are still running when the host processor shuts down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShutdownPolicy {
+
+	/// leave the processes running, and tell any attached clients that no more events are coming
+	#[default]
+	Detach,
+
+	/// send SIGTERM to every process group, then SIGKILL to any that don't exit in time,
+	/// and send the final events to any attached clients
+	Terminate
+}
+
+impl FromStr for ShutdownPolicy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"detach" => Ok(Self::Detach),
+			"terminate" => Ok(Self::Terminate),
+			_ => Err(format!("Unrecognized shutdown policy: {}, expected one of: detach, terminate", s))
+		}
+	}
+}
@@ -0,0 +1,46 @@
+use std::io;
+
+use crate::proto::{KillFailure, KillSignal};
+
+
+pub fn number(signal: KillSignal) -> libc::c_int {
+	match signal {
+		KillSignal::Interrupt => libc::SIGINT,
+		KillSignal::Kill => libc::SIGKILL,
+		KillSignal::Terminate => libc::SIGTERM,
+		KillSignal::Hangup => libc::SIGHUP,
+		KillSignal::User1 => libc::SIGUSR1,
+		KillSignal::User2 => libc::SIGUSR2,
+		KillSignal::Stop => libc::SIGSTOP,
+		KillSignal::Continue => libc::SIGCONT,
+		KillSignal::Quit => libc::SIGQUIT
+	}
+}
+
+
+/// Sends a signal to a process, or to its whole process group
+pub fn send(pid: u32, signal: KillSignal, process_group: bool) -> io::Result<()> {
+
+	let pid = pid as libc::pid_t;
+	let signal = number(signal);
+
+	let result = if process_group {
+		unsafe { libc::killpg(pid, signal) }
+	} else {
+		unsafe { libc::kill(pid, signal) }
+	};
+	match result {
+		0 => Ok(()),
+		_ => Err(io::Error::last_os_error())
+	}
+}
+
+
+impl From<io::Error> for KillFailure {
+	fn from(e: io::Error) -> Self {
+		Self {
+			errno: e.raw_os_error(),
+			reason: e.to_string()
+		}
+	}
+}
@@ -0,0 +1,123 @@
+use std::cell::Cell;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
+
+use crate::proto::HostStats;
+
+
+/// Counters about the host processor itself, for the Stats request and the metrics file
+pub struct Stats {
+	started: Instant,
+	connections: Cell<u32>,
+	total_processes: Cell<u64>,
+	bytes_streamed: Cell<u64>,
+	decode_errors: Cell<u64>,
+	exec_failures: Cell<u64>
+}
+
+impl Default for Stats {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Stats {
+
+	pub fn new() -> Self {
+		Self {
+			started: Instant::now(),
+			connections: Cell::new(0),
+			total_processes: Cell::new(0),
+			bytes_streamed: Cell::new(0),
+			decode_errors: Cell::new(0),
+			exec_failures: Cell::new(0)
+		}
+	}
+
+	/// Counts an open connection, until the returned guard is dropped
+	pub fn connection(self: &Rc<Self>) -> ConnectionGuard {
+		self.connections.set(self.connections.get() + 1);
+		ConnectionGuard {
+			stats: self.clone()
+		}
+	}
+
+	pub fn launched(&self) {
+		self.total_processes.set(self.total_processes.get() + 1);
+	}
+
+	pub fn streamed(&self, bytes: usize) {
+		self.bytes_streamed.set(self.bytes_streamed.get() + bytes as u64);
+	}
+
+	pub fn decode_error(&self) {
+		self.decode_errors.set(self.decode_errors.get() + 1);
+	}
+
+	pub fn exec_failure(&self) {
+		self.exec_failures.set(self.exec_failures.get() + 1);
+	}
+
+	/// `live_processes` comes from the process collection, since processes can exit without us counting them
+	pub fn snapshot(&self, live_processes: usize) -> HostStats {
+		HostStats {
+			uptime: self.started.elapsed().as_millis() as u64,
+			version: env!("CARGO_PKG_VERSION").to_string(),
+			connections: self.connections.get(),
+			live_processes: live_processes as u32,
+			total_processes: self.total_processes.get(),
+			bytes_streamed: self.bytes_streamed.get(),
+			decode_errors: self.decode_errors.get(),
+			exec_failures: self.exec_failures.get()
+		}
+	}
+}
+
+
+pub struct ConnectionGuard {
+	stats: Rc<Stats>
+}
+
+impl Drop for ConnectionGuard {
+	fn drop(&mut self) {
+		let connections = &self.stats.connections;
+		connections.set(connections.get() - 1);
+	}
+}
+
+
+/// Formats the stats in the Prometheus text exposition format,
+/// see: https://prometheus.io/docs/instrumenting/exposition_formats/
+pub fn prometheus(stats: &HostStats) -> String {
+
+	let mut out = String::new();
+	let mut metric = |name: &str, kind: &str, help: &str, labels: &str, value: f64| {
+		// NOTE: writing to a String can't fail
+		writeln!(out, "# HELP host_processor_{} {}", name, help).ok();
+		writeln!(out, "# TYPE host_processor_{} {}", name, kind).ok();
+		writeln!(out, "host_processor_{}{} {}", name, labels, value).ok();
+	};
+
+	metric("info", "gauge", "Information about the host processor", &format!("{{version=\"{}\"}}", stats.version), 1.0);
+	metric("uptime_seconds", "gauge", "Time since the host processor started", "", stats.uptime as f64/1000.0);
+	metric("connections", "gauge", "Connections that are open right now", "", stats.connections as f64);
+	metric("processes_running", "gauge", "Processes launched by Exec that are still running", "", stats.live_processes as f64);
+	metric("processes_launched_total", "counter", "Processes launched by Exec", "", stats.total_processes as f64);
+	metric("streamed_bytes_total", "counter", "Console output read from processes to stream to clients", "", stats.bytes_streamed as f64);
+	metric("decode_errors_total", "counter", "Requests that couldn't be decoded", "", stats.decode_errors as f64);
+	metric("exec_failures_total", "counter", "Exec requests that didn't launch a process", "", stats.exec_failures as f64);
+
+	out
+}
+
+
+/// Writes the metrics file, so a scraper never sees a partially-written file
+pub fn write_prometheus(path: &Path, stats: &HostStats) -> io::Result<()> {
+	let tmp_path = path.with_extension("tmp");
+	fs::write(&tmp_path, prometheus(stats))?;
+	fs::rename(&tmp_path, path)
+}
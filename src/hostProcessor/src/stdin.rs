@@ -0,0 +1,57 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+
+/// Limits the bytes of acknowledged stdin writes a connection can have waiting to be written,
+/// so a client can stream a large input without the host processor holding all of it in memory.
+/// Each ack tells the client how much credit is left, and the client stops sending when it runs out.
+pub struct StdinWindow {
+	size: usize,
+	used: Cell<usize>
+}
+
+impl StdinWindow {
+
+	pub fn new(size: usize) -> Rc<Self> {
+		Rc::new(Self {
+			size,
+			used: Cell::new(0)
+		})
+	}
+
+	/// bytes the client can still send before it has to wait for more acks
+	pub fn credit(&self) -> usize {
+		self.size - self.used.get()
+	}
+
+	/// Takes room in the window for a chunk, until the returned guard is dropped
+	pub fn reserve(self: &Rc<Self>, bytes: usize) -> Result<StdinReservation,String> {
+
+		if bytes > self.size {
+			return Err(format!("Chunk of {} bytes is larger than the stdin window of {} bytes", bytes, self.size));
+		}
+		let credit = self.credit();
+		if bytes > credit {
+			return Err(format!("Chunk of {} bytes is over the remaining stdin credit of {} bytes", bytes, credit));
+		}
+
+		self.used.set(self.used.get() + bytes);
+		Ok(StdinReservation {
+			window: self.clone(),
+			bytes
+		})
+	}
+}
+
+
+pub struct StdinReservation {
+	window: Rc<StdinWindow>,
+	bytes: usize
+}
+
+impl Drop for StdinReservation {
+	fn drop(&mut self) {
+		let used = &self.window.used;
+		used.set(used.get() - self.bytes);
+	}
+}
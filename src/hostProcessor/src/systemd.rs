@@ -0,0 +1,135 @@
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixListener};
+
+use anyhow::{bail, Context, Result};
+use tracing::{debug, warn};
+
+
+/// the first file descriptor passed by systemd, see: https://www.freedesktop.org/software/systemd/man/latest/sd_listen_fds.html
+const LISTEN_FDS_START: RawFd = 3;
+
+
+/// Takes the listening socket passed by systemd socket activation, if any.
+/// The LISTEN_* environment variables are removed, so the processes we launch don't think they're socket-activated too.
+/// NOTE: call this before starting any threads, since it changes the environment
+pub fn listen_fds() -> Result<Option<UnixListener>> {
+
+	let pid = env::var("LISTEN_PID").ok();
+	let fds = env::var("LISTEN_FDS").ok();
+	env::remove_var("LISTEN_PID");
+	env::remove_var("LISTEN_FDS");
+	env::remove_var("LISTEN_FDNAMES");
+
+	// the sockets are only for us if the pid matches
+	let (Some(pid), Some(fds)) = (pid, fds)
+		else { return Ok(None); };
+	if pid.parse::<u32>().ok() != Some(std::process::id()) {
+		debug!("Ignoring LISTEN_FDS for another process: LISTEN_PID={}", pid);
+		return Ok(None);
+	}
+	let fds = fds.parse::<RawFd>()
+		.context(format!("Invalid LISTEN_FDS: {}", fds))?;
+	match fds {
+		0 => return Ok(None),
+		1 => (),
+		_ => warn!("systemd passed {} sockets, using only the first one", fds)
+	}
+
+	let fd = LISTEN_FDS_START;
+	if !is_unix_stream_listener(fd).context("Failed to check socket from systemd")? {
+		bail!("The socket from systemd isn't a listening unix stream socket");
+	}
+
+	// don't leak the socket to the processes we launch
+	check(unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) })
+		.context("Failed to set close-on-exec for socket from systemd")?;
+
+	let listener = unsafe { UnixListener::from_raw_fd(fd) };
+	listener.set_nonblocking(true)
+		.context("Failed to make socket from systemd non-blocking")?;
+	Ok(Some(listener))
+}
+
+
+fn is_unix_stream_listener(fd: RawFd) -> io::Result<bool> {
+	Ok(sockopt(fd, libc::SO_DOMAIN)? == libc::AF_UNIX
+		&& sockopt(fd, libc::SO_TYPE)? == libc::SOCK_STREAM
+		&& sockopt(fd, libc::SO_ACCEPTCONN)? != 0)
+}
+
+
+fn sockopt(fd: RawFd, opt: libc::c_int) -> io::Result<libc::c_int> {
+	let mut value: libc::c_int = 0;
+	let mut len = size_of::<libc::c_int>() as libc::socklen_t;
+	check(unsafe { libc::getsockopt(fd, libc::SOL_SOCKET, opt, &mut value as *mut _ as *mut libc::c_void, &mut len) })?;
+	Ok(value)
+}
+
+
+fn check(result: libc::c_int) -> io::Result<()> {
+	match result {
+		-1 => Err(io::Error::last_os_error()),
+		_ => Ok(())
+	}
+}
+
+
+/// Sends status updates to systemd, if it's listening,
+/// see: https://www.freedesktop.org/software/systemd/man/latest/sd_notify.html
+pub struct Notifier {
+	path: Option<OsString>
+}
+
+impl Notifier {
+
+	/// Reads the notification socket from NOTIFY_SOCKET, if any.
+	/// The variable is removed, so the processes we launch don't send notifications on our behalf.
+	/// NOTE: call this before starting any threads, since it changes the environment
+	pub fn from_env() -> Self {
+		let path = env::var_os("NOTIFY_SOCKET");
+		env::remove_var("NOTIFY_SOCKET");
+		Self {
+			path
+		}
+	}
+
+	/// Tells systemd we're ready to accept connections
+	pub fn ready(&self) {
+		self.notify("READY=1");
+	}
+
+	/// Tells systemd we're shutting down
+	pub fn stopping(&self) {
+		self.notify("STOPPING=1");
+	}
+
+	fn notify(&self, state: &str) {
+
+		let Some(path) = &self.path
+			else { return; };
+
+		let result = send(path, state);
+		match result {
+			Ok(()) => debug!("Notified systemd: {}", state),
+			Err(e) => warn!("Failed to notify systemd: {}: {}", state, e)
+		}
+	}
+}
+
+
+fn send(path: &OsString, state: &str) -> io::Result<()> {
+
+	// paths starting with @ are in the abstract namespace
+	let addr = match path.as_encoded_bytes().strip_prefix(b"@") {
+		Some(name) => SocketAddr::from_abstract_name(name)?,
+		None => SocketAddr::from_pathname(path)?
+	};
+
+	let socket = UnixDatagram::unbound()?;
+	socket.send_to_addr(state.as_bytes(), &addr)?;
+	Ok(())
+}
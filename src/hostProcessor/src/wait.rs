@@ -0,0 +1,88 @@
+use std::{io, mem};
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+
+use anyhow::{bail, Context, Result};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::proto::{ProcessExit, ResourceUsage};
+
+
+/// How a child process exited, and what resources it used
+pub struct Exit {
+	pub status: ExitStatus,
+	pub usage: ResourceUsage
+}
+
+impl Exit {
+
+	pub fn exit(&self) -> Option<ProcessExit> {
+		if let Some(code) = self.status.code() {
+			Some(ProcessExit::Code(code))
+		} else {
+			self.status.signal()
+				.map(ProcessExit::Signal)
+		}
+	}
+}
+
+
+/// Waits for a child process to exit and reaps it, like tokio's Child::wait(),
+/// except this also collects the resource usage of the process using wait4().
+/// NOTE: Don't call Child::wait() on the same process, since only one waiter can reap it.
+///       Spawn the process with std's Command too, since dropping a tokio Child that hasn't been waited on
+///       makes tokio try to reap the pid later, and by then it could belong to a different process.
+pub async fn wait(pid: u32) -> Result<Exit> {
+
+	// NOTE: start listening for SIGCHLD before checking the process,
+	//       so we can't miss a signal that arrives in between
+	let mut sigchld = signal(SignalKind::child())
+		.context("Failed to listen for SIGCHLD")?;
+
+	loop {
+		if let Some(exit) = try_wait(pid).context("Failed to wait for process")? {
+			return Ok(exit);
+		}
+		if sigchld.recv().await.is_none() {
+			bail!("SIGCHLD listener closed");
+		}
+	}
+}
+
+
+fn try_wait(pid: u32) -> io::Result<Option<Exit>> {
+
+	let mut status: libc::c_int = 0;
+	let mut rusage = unsafe { mem::zeroed::<libc::rusage>() };
+
+	loop {
+		match unsafe { libc::wait4(pid as libc::pid_t, &mut status, libc::WNOHANG, &mut rusage) } {
+
+			// still running
+			0 => return Ok(None),
+
+			-1 => {
+				let e = io::Error::last_os_error();
+				if e.kind() == io::ErrorKind::Interrupted {
+					continue;
+				}
+				return Err(e);
+			}
+
+			_ => return Ok(Some(Exit {
+				status: ExitStatus::from_raw(status),
+				usage: ResourceUsage {
+					user_time: micros(&rusage.ru_utime),
+					system_time: micros(&rusage.ru_stime),
+					// NOTE: Linux reports max RSS in KiB
+					max_rss: rusage.ru_maxrss as u64
+				}
+			}))
+		}
+	}
+}
+
+
+fn micros(time: &libc::timeval) -> u64 {
+	(time.tv_sec as u64)*1_000_000 + (time.tv_usec as u64)
+}
@@ -1,19 +1,25 @@
 
 use std::net::Shutdown;
-use std::os::unix::net::UnixStream;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixDatagram, UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitStatus};
 use std::{fs, thread};
-use std::time::Duration;
+use std::io::{IoSlice, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::time::{Duration, Instant};
 
+use flate2::read::GzDecoder;
 use galvanic_assert::{assert_that, matchers::*};
 use nix::sys::signal::{self, Signal};
+use nix::sys::socket::{self, ControlMessage, MsgFlags};
 use nix::unistd::Pid;
-use tracing::debug;
+use tracing::{debug, info};
 
 use host_processor::framing::{ReadFramed, WriteFramed};
 use host_processor::logging;
-use host_processor::proto::{ExecRequest, ExecStderr, ExecStdin, ExecStdout, Request, RequestEnvelope, Response, ResponseEnvelope};
+use host_processor::proto::{AttachResponse, ConsoleKind, ConsoleLines, ContainerBind, ContainerFlag, EnvMode, ExecContainer, ExecEnv, ExecLimits, ExecPipelineRequest, ExecRequest, ExecResponse, ExecStderr, ExecStdin, ExecStdout, HostStats, KillFailure, KillGracefullyResponse, KillResponse, KillSignal, LimitKind, LogFile, LogRotation, PipelineStage, ProcessEvent, ProcessExit, ProcessStatus, ProcessStdio, Request, RequestEnvelope, Response, ResponseEnvelope, StdinResponse, WaitResponse};
 
 
 // NOTE: these tests need `cargo test ... -- --test-threads=1` for the log to make sense
@@ -57,6 +63,509 @@ fn ping_pong() {
 }
 
 
+#[test]
+fn peer_allowed() {
+	let _logging = logging::init_test();
+
+	let uid = users::get_current_uid().to_string();
+	let host_processor = HostProcessor::start_with_args(&["--allow-peer-uid", &uid]);
+	let mut socket = host_processor.connect();
+
+	let (response, _request_id) = request(&mut socket, Request::Ping);
+	assert_that!(&response, eq(Response::Pong));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn peer_allowed_gid() {
+	let _logging = logging::init_test();
+
+	let gid = users::get_current_gid().to_string();
+	let host_processor = HostProcessor::start_with_args(&["--allow-peer-uid", "12345", "--allow-peer-gid", &gid]);
+	let mut socket = host_processor.connect();
+
+	let (response, _request_id) = request(&mut socket, Request::Ping);
+	assert_that!(&response, eq(Response::Pong));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn peer_refused() {
+	let _logging = logging::init_test();
+
+	// allow only some other user
+	let host_processor = HostProcessor::start_with_args(&["--allow-peer-uid", "12345"]);
+	let mut socket = host_processor.connect();
+
+	// the host processor should hang up on us
+	assert_that!(&socket.read_framed().is_err(), eq(true));
+
+	drop(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn peer_refused_log() {
+	let _logging = logging::init_test();
+
+	// allow only some other user, but only log the others
+	let host_processor = HostProcessor::start_with_args(&["--allow-peer-uid", "12345", "--peer-policy", "log"]);
+	let mut socket = host_processor.connect();
+
+	let (response, _request_id) = request(&mut socket, Request::Ping);
+	assert_that!(&response, eq(Response::Pong));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn config_socket() {
+	let _logging = logging::init_test();
+
+	let socket_name = format!("host-processor-test-{}", rand::random::<u32>());
+	let host_processor = HostProcessor::start_with_socket(&socket_name, &["--socket-mode", "600"]);
+	let mut socket = host_processor.connect();
+
+	let mode = fs::metadata(host_processor.socket_path())
+		.unwrap()
+		.permissions()
+		.mode();
+	assert_that!(&(mode & 0o777), eq(0o600));
+
+	let (response, _request_id) = request(&mut socket, Request::Ping);
+	assert_that!(&response, eq(Response::Pong));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn config_file() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_config(r#"
+		[exec]
+		default_dir = "/tmp"
+		env_clear = true
+		env_keep = ["PATH"]
+
+		[limits]
+		open_files = 16
+	"#);
+	let mut socket = host_processor.connect();
+
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "pwd; echo ${PATH:+path} ${HOME:-nohome} $FOO; ulimit -n".to_string()],
+		dir: None,
+		envvars: vec![("FOO".to_string(), "foo".to_string())],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("/tmp\npath nohome foo\n16\n".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn config_file_invalid() {
+	let _logging = logging::init_test();
+
+	fs::create_dir_all(SOCKET_DIR)
+		.unwrap();
+	let path = PathBuf::from(SOCKET_DIR)
+		.join(format!("config-{}.toml", rand::random::<u32>()));
+	fs::write(&path, "[exec]\nnope = 5\n")
+		.unwrap();
+
+	let exit = Command::new(HostProcessor::bin_path())
+		.args(["--config", path.to_str().unwrap()])
+		.current_dir(SOCKET_DIR)
+		.status()
+		.unwrap();
+	assert_that!(&exit.success(), eq(false));
+
+	fs::remove_file(&path)
+		.unwrap();
+}
+
+
+#[test]
+fn config_allowed_programs() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_args(&["--allow-program", "sh"]);
+	let mut socket = host_processor.connect();
+
+	// ls isn't allowed
+	let reason = exec::launch_failure(&mut socket, ExecRequest {
+		program: "ls".to_string(),
+		args: vec![],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	assert_that!(&reason.contains("not allowed"), eq(true));
+
+	// but sh is
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "exit 5".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	assert_that!(&exec::fin(&mut socket, request_id), eq(Some(5)));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn policy_program_canonical() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_config(r#"
+		[exec]
+		allowed_programs = ["/bin/sh"]
+	"#);
+	let mut socket = host_processor.connect();
+
+	// make some symlinks to try to sneak around the policy
+	let dir = PathBuf::from(SOCKET_DIR)
+		.join(format!("policy-{}", rand::random::<u32>()));
+	fs::create_dir_all(&dir)
+		.unwrap();
+	std::os::unix::fs::symlink("/bin/sh", dir.join("my-sh"))
+		.unwrap();
+	std::os::unix::fs::symlink("/usr/bin/env", dir.join("not-sh"))
+		.unwrap();
+
+	let exec_request = |program: &str| ExecRequest {
+		program: program.to_string(),
+		args: vec!["-c".to_string(), "exit 5".to_string()],
+		dir: Some(dir.to_string_lossy().to_string()),
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	};
+
+	// sh from the PATH, and a symlink to it, should both be allowed
+	for program in ["sh", "./my-sh"] {
+		let (_pid, request_id) = exec::launch(&mut socket, exec_request(program));
+		assert_that!(&exec::fin(&mut socket, request_id), eq(Some(5)));
+	}
+
+	// but not other programs, no matter what they're called
+	for program in ["ls", "./not-sh", "/usr/bin/env"] {
+		let reason = exec::launch_failure(&mut socket, exec_request(program));
+		assert_that!(&reason.starts_with("Program not allowed"), eq(true));
+	}
+
+	// or programs that don't exist
+	let reason = exec::launch_failure(&mut socket, exec_request("not-a-program"));
+	assert_that!(&reason.starts_with("Program not found"), eq(true));
+
+	fs::remove_dir_all(&dir)
+		.unwrap();
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn policy_program_arg0() {
+	let _logging = logging::init_test();
+
+	// make several names for one program, like a multi-call binary would have
+	let dir = PathBuf::from(SOCKET_DIR)
+		.join(format!("policy-{}", rand::random::<u32>()));
+	fs::create_dir_all(&dir)
+		.unwrap();
+	for name in ["ls", "cat", "rm"] {
+		std::os::unix::fs::symlink("/bin/sh", dir.join(name))
+			.unwrap();
+	}
+	let path = |name: &str| dir.join(name)
+		.to_string_lossy()
+		.to_string();
+
+	let host_processor = HostProcessor::start_with_config(&format!(r#"
+		[exec]
+		allowed_programs = ["{}", "{}"]
+	"#, path("ls"), path("cat")));
+	let mut socket = host_processor.connect();
+
+	// sh reading a script from stdin says what's in its argv[0]
+	let mut arg0 = |program: &str| {
+		let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+			program: program.to_string(),
+			args: vec![],
+			dir: None,
+			envvars: vec![],
+			stdin: ExecStdin::Bytes(b"echo $0".to_vec()),
+			stdout: ExecStdout::Stream,
+			stderr: ExecStderr::Ignore,
+			stream_fin: true,
+			limits: None,
+			uid: None,
+			gid: None,
+			gids: None,
+			persist: false,
+			lines: None,
+			env: None,
+			container: None
+		});
+		let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+		assert_that!(&exit_code, eq(Some(0)));
+		String::from_utf8_lossy(stdout.as_ref()).trim().to_string()
+	};
+
+	// the allowed names run as themselves
+	assert_that!(&arg0(&path("ls")), eq(path("ls")));
+	assert_that!(&arg0(&path("cat")), eq(path("cat")));
+
+	// but other names for the same program only run as an allowed name
+	assert_that!(&arg0(&path("rm")), eq(path("ls")));
+
+	fs::remove_dir_all(&dir)
+		.unwrap();
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn policy_args() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_config(r#"
+		[[exec.rules]]
+		program = "echo"
+		args = ["hello", "w*d", "-?"]
+	"#);
+	let mut socket = host_processor.connect();
+
+	let exec_request = |args: &[&str]| ExecRequest {
+		program: "echo".to_string(),
+		args: args.iter().map(|arg| arg.to_string()).collect(),
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	};
+
+	// arguments that match the patterns should be allowed
+	let (_pid, request_id) = exec::launch(&mut socket, exec_request(&["-n", "hello", "world"]));
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("hello world".to_string()));
+
+	// but not anything else
+	for args in [&["hello", "there"][..], &["-ne", "hello"], &["word", "words"]] {
+		let reason = exec::launch_failure(&mut socket, exec_request(args));
+		assert_that!(&reason.starts_with("Argument not allowed"), eq(true));
+	}
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn audit_log() {
+	let _logging = logging::init_test();
+
+	let path = PathBuf::from(SOCKET_DIR)
+		.join(format!("audit-{}.log", rand::random::<u32>()));
+	let host_processor = HostProcessor::start_with_args(&["--audit-log", path.to_str().unwrap()]);
+	let mut socket = host_processor.connect();
+
+	// send a few requests
+	let (response, _request_id) = request(&mut socket, Request::Ping);
+	assert_that!(&response, eq(Response::Pong));
+
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "exit 3".to_string()],
+		dir: None,
+		envvars: vec![("SECRET".to_string(), "hunter2".to_string())],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	assert_that!(&exec::fin(&mut socket, request_id), eq(Some(3)));
+
+	let (response, _request_id) = request(&mut socket, Request::Kill {
+		signal: KillSignal::Interrupt,
+		pid: 5,
+		process_group: false
+	});
+	assert_that!(&matches!(response, Response::Kill(KillResponse::Failure(..))), eq(true));
+
+	// and one that makes no sense at all
+	socket.write_framed([0, 0, 0, 42, 0, 0, 0, 99])
+		.unwrap();
+	let response = ResponseEnvelope::decode(socket.read_framed().unwrap())
+		.unwrap();
+	assert_that!(&matches!(response.response, Response::Error { .. }), eq(true));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+
+	// read the audit log
+	let log = fs::read_to_string(&path)
+		.unwrap();
+	fs::remove_file(&path)
+		.unwrap();
+	assert_that!(&log.contains("hunter2"), eq(false));
+	let entries = log.lines()
+		.map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+		.collect::<Vec<_>>();
+	assert_that!(&entries.len(), eq(5));
+	for entry in &entries {
+		assert_that!(&entry["peer"]["uid"].as_u64(), eq(Some(users::get_current_uid() as u64)));
+		assert_that!(&entry["time"].is_string(), eq(true));
+	}
+	let find = |request: &str, status: &str| entries.iter()
+		.find(|entry| entry["request"] == request && entry["outcome"]["status"] == status)
+		.unwrap_or_else(|| panic!("no audit log entry for {} {}", request, status));
+
+	find("Ping", "done");
+
+	let entry = find("Exec", "started");
+	assert_that!(&entry["program"].as_str(), eq(Some("sh")));
+	assert_that!(&entry["args"], eq(serde_json::json!(["-c", "exit 3"])));
+	assert_that!(&entry["outcome"]["pid"].as_u64(), eq(Some(pid as u64)));
+
+	let entry = find("Exec", "exited");
+	assert_that!(&entry["outcome"]["pid"].as_u64(), eq(Some(pid as u64)));
+	assert_that!(&entry["outcome"]["exit_code"].as_i64(), eq(Some(3)));
+
+	let entry = find("Kill", "error");
+	assert_that!(&entry["pid"].as_u64(), eq(Some(5)));
+	assert_that!(&entry["signal"].as_str(), eq(Some("SIGINT")));
+
+	let entry = find("Invalid", "error");
+	assert_that!(&entry["request_id"].as_u64(), eq(Some(42)));
+}
+
+
+#[test]
+fn audit_log_rotate() {
+	let _logging = logging::init_test();
+
+	let path = PathBuf::from(SOCKET_DIR)
+		.join(format!("audit-{}.log", rand::random::<u32>()));
+	let host_processor = HostProcessor::start_with_args(&[
+		"--audit-log", path.to_str().unwrap(),
+		"--audit-max-size", "1000",
+		"--audit-generations", "2"
+	]);
+	let mut socket = host_processor.connect();
+
+	// each entry is a couple hundred bytes, so this should fill a few files
+	for _ in 0 .. 30 {
+		let (response, _request_id) = request(&mut socket, Request::Ping);
+		assert_that!(&response, eq(Response::Pong));
+	}
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+
+	let generation = |i: u32| PathBuf::from(format!("{}.{}.gz", path.to_string_lossy(), i));
+	assert_that!(&path.exists(), eq(true));
+	assert_that!(&generation(1).exists(), eq(true));
+	assert_that!(&generation(2).exists(), eq(true));
+	assert_that!(&generation(3).exists(), eq(false));
+	assert_that!(&(fs::metadata(&path).unwrap().len() <= 1000), eq(true));
+
+	for path in [path.clone(), generation(1), generation(2)] {
+		fs::remove_file(path)
+			.unwrap();
+	}
+}
+
+
 #[test]
 fn exec() {
 	let _logging = logging::init_test();
@@ -73,7 +582,15 @@ fn exec() {
 		stdin: ExecStdin::Ignore,
 		stdout: ExecStdout::Ignore,
 		stderr: ExecStderr::Ignore,
-		stream_fin: true
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
 	});
 
 	// wait for the fin response
@@ -86,29 +603,2844 @@ fn exec() {
 
 
 #[test]
-fn exec_stdout_stream() {
+fn exec_stdout_stream() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "ls".to_string(),
+		args: vec!["-al".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// get the stdout
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&stdout.len(), gt(0));
+	assert_that!(&stderr.len(), eq(0));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stdout_write() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let out_path = PathBuf::from(SOCKET_DIR).join("out");
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "echo".to_string(),
+		args: vec!["hello".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Write {
+			path: out_path.to_string_lossy().to_string()
+		},
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// get the stdout
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&stdout.len(), eq(0));
+	assert_that!(&stderr.len(), eq(0));
+	let out = fs::read_to_string(&out_path)
+		.unwrap();
+	assert_that!(&out.as_str(), eq("hello\n"));
+
+	fs::remove_file(&out_path)
+		.ok();
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stdout_log_file_rotate() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let out_dir = PathBuf::from(SOCKET_DIR).join("log-rotate");
+	fs::remove_dir_all(&out_dir).ok();
+	fs::create_dir_all(&out_dir)
+		.unwrap();
+	let out_path = out_dir.join("out");
+
+	// send the exec request
+	// NOTE: each line is 6 bytes, so each file should get two lines
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "for i in 1 2 3 4 5 6 7 8; do echo line$i; done".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::LogFile(LogFile {
+			path: out_path.to_string_lossy().to_string(),
+			append: false,
+			rotation: Some(LogRotation {
+				max_size: 12,
+				generations: 2,
+				gzip: false
+			}),
+			timestamps: false
+		}),
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	let exit_code = exec::fin(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+
+	// the oldest lines should have been rotated away
+	assert_that!(&fs::read_to_string(&out_path).unwrap(), eq("line7\nline8\n".to_string()));
+	assert_that!(&fs::read_to_string(out_dir.join("out.1")).unwrap(), eq("line5\nline6\n".to_string()));
+	assert_that!(&fs::read_to_string(out_dir.join("out.2")).unwrap(), eq("line3\nline4\n".to_string()));
+	assert_that!(&out_dir.join("out.3").exists(), eq(false));
+
+	fs::remove_dir_all(&out_dir)
+		.ok();
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stdout_log_file_gzip() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let out_dir = PathBuf::from(SOCKET_DIR).join("log-gzip");
+	fs::remove_dir_all(&out_dir).ok();
+	fs::create_dir_all(&out_dir)
+		.unwrap();
+	let out_path = out_dir.join("out");
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "for i in 1 2 3; do echo line$i; done".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::LogFile(LogFile {
+			path: out_path.to_string_lossy().to_string(),
+			append: false,
+			rotation: Some(LogRotation {
+				max_size: 12,
+				generations: 3,
+				gzip: true
+			}),
+			timestamps: false
+		}),
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	let exit_code = exec::fin(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+
+	// the rotated file should be compressed
+	assert_that!(&fs::read_to_string(&out_path).unwrap(), eq("line3\n".to_string()));
+	assert_that!(&out_dir.join("out.1").exists(), eq(false));
+	let mut rotated = String::new();
+	GzDecoder::new(fs::File::open(out_dir.join("out.1.gz")).unwrap())
+		.read_to_string(&mut rotated)
+		.unwrap();
+	assert_that!(&rotated.as_str(), eq("line1\nline2\n"));
+
+	fs::remove_dir_all(&out_dir)
+		.ok();
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stdout_log_file_append_timestamps() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let out_dir = PathBuf::from(SOCKET_DIR).join("log-append");
+	fs::remove_dir_all(&out_dir).ok();
+	fs::create_dir_all(&out_dir)
+		.unwrap();
+	let out_path = out_dir.join("out");
+	fs::write(&out_path, "old\n")
+		.unwrap();
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "echo".to_string(),
+		args: vec!["hello".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::LogFile(LogFile {
+			path: out_path.to_string_lossy().to_string(),
+			append: true,
+			rotation: None,
+			timestamps: true
+		}),
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	let exit_code = exec::fin(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+
+	// the old contents should still be there, and the new line should have a timestamp
+	let out = fs::read_to_string(&out_path)
+		.unwrap();
+	let lines = out.lines()
+		.collect::<Vec<_>>();
+	assert_that!(&lines.len(), eq(2));
+	assert_that!(&lines[0], eq("old"));
+	assert_that!(&lines[1].ends_with(" hello"), eq(true));
+	assert_that!(&lines[1].len(), gt("hello".len() + 20));
+
+	fs::remove_dir_all(&out_dir)
+		.ok();
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stderr_stream() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "ls".to_string(),
+		args: vec!["/nope/probably/not/a/thing/right?".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Stream,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// get the stdout
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(2)));
+	assert_that!(&stdout.len(), eq(0));
+	assert_that!(&stderr.len(), gt(0));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stderr_write() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let err_path = PathBuf::from(SOCKET_DIR).join("err");
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "ls".to_string(),
+		args: vec!["/nope/probably/not/a/thing/right?".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Write {
+			path: err_path.to_string_lossy().to_string()
+		},
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// get the stderr
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(2)));
+	assert_that!(&stdout.len(), eq(0));
+	assert_that!(&stderr.len(), eq(0));
+	let err = fs::read_to_string(&err_path)
+		.unwrap();
+	assert_that!(&err.len(), gt(0));
+
+	fs::remove_file(&err_path)
+		.ok();
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stderr_merge() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let out_path = PathBuf::from(SOCKET_DIR).join("out");
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "ls".to_string(),
+		args: vec!["/nope/probably/not/a/thing/right?".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Write {
+			path: out_path.to_string_lossy().to_string()
+		},
+		stderr: ExecStderr::Merge,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// get the output
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(2)));
+	assert_that!(&stdout.len(), eq(0));
+	assert_that!(&stderr.len(), eq(0));
+	let out = fs::read_to_string(&out_path)
+		.unwrap();
+	assert_that!(&out.len(), gt(0));
+
+	fs::remove_file(&out_path)
+		.ok();
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stdin() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "cat".to_string(),
+		args: vec!["-".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Stream,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Stream,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// send chunks to stdin
+	exec::write_stdin(&mut socket, pid, b"hi");
+	exec::close_stdin(&mut socket, pid);
+
+	// get the stdout
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("hi".to_string()));
+	assert_that!(&stderr.len(), eq(0));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stdin_blocked() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+	socket.set_read_timeout(Some(Duration::from_secs(5)))
+		.unwrap();
+
+	// launch a process that never reads its stdin
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sleep".to_string(),
+		args: vec!["30".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Stream,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// write more than the pipe can hold, so the write gets stuck
+	exec::write_stdin(&mut socket, pid, vec![b'x'; 256*1024]);
+
+	// other requests should still work
+	let status = exec::status(&mut socket, pid);
+	assert_that!(&matches!(status, ProcessStatus::Running { .. }), eq(true));
+	exec::signal(&mut socket, pid, KillSignal::Kill);
+	let response = exec::wait(&mut socket, pid, Some(5000));
+	assert_that!(&response, eq(WaitResponse::Exited {
+		exit: Some(ProcessExit::Signal(libc::SIGKILL))
+	}));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stdin_acked() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// don't stream any output, so the only responses are the acks
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "read line && [ \"$line\" = hello ] && cat > /dev/null".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Stream,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	let response = exec::write_stdin_acked(&mut socket, pid, b"hello\n");
+	assert_that!(&response, eq(StdinResponse::Written {
+		bytes: 6,
+		credit: 1024*1024
+	}));
+
+	let response = exec::close_stdin_acked(&mut socket, pid);
+	assert_that!(&response, eq(StdinResponse::Closed));
+
+	// errors come back to the client too
+	let response = exec::write_stdin_acked(&mut socket, pid, b"more\n");
+	assert_that!(&response, eq(StdinResponse::Failure {
+		reason: "Process stdin already closed".to_string()
+	}));
+	let response = exec::close_stdin_acked(&mut socket, pid);
+	assert_that!(&response, eq(StdinResponse::Failure {
+		reason: "Process stdin already closed".to_string()
+	}));
+
+	let response = exec::write_stdin_acked(&mut socket, 5, b"hello\n");
+	assert_that!(&response, eq(StdinResponse::Failure {
+		reason: "Process not found".to_string()
+	}));
+
+	// the process got the whole line, and then the end of stdin
+	let response = exec::wait(&mut socket, pid, Some(5000));
+	assert_that!(&response, eq(WaitResponse::Exited {
+		exit: Some(ProcessExit::Code(0))
+	}));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stdin_window() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_args(&["--stdin-window", "8"]);
+	let mut socket = host_processor.connect();
+
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "cat".to_string(),
+		args: vec!["-".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Stream,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// chunks that fit in the window get written, and the credit comes back after each write
+	for _ in 0 .. 3 {
+		let response = exec::write_stdin_acked(&mut socket, pid, b"12345678");
+		assert_that!(&response, eq(StdinResponse::Written {
+			bytes: 8,
+			credit: 8
+		}));
+	}
+
+	// but chunks that never fit are refused
+	let response = exec::write_stdin_acked(&mut socket, pid, b"123456789");
+	assert_that!(&response, eq(StdinResponse::Failure {
+		reason: "Chunk of 9 bytes is larger than the stdin window of 8 bytes".to_string()
+	}));
+
+	let response = exec::close_stdin_acked(&mut socket, pid);
+	assert_that!(&response, eq(StdinResponse::Closed));
+
+	let response = exec::wait(&mut socket, pid, Some(5000));
+	assert_that!(&response, eq(WaitResponse::Exited {
+		exit: Some(ProcessExit::Code(0))
+	}));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_fd_stdout() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let out_path = PathBuf::from(SOCKET_DIR).join("out-fd");
+	let out_file = fs::File::create(&out_path)
+		.unwrap();
+
+	// send the exec request, with the file for stdout
+	let (_pid, request_id) = exec::launch_with_fds(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "echo hello; echo oops >&2".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Fd,
+		stderr: ExecStderr::Merge,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	}, &[out_file.as_raw_fd()]);
+	drop(out_file);
+
+	// the output should go to the file, not the socket
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&stdout.len(), eq(0));
+	assert_that!(&stderr.len(), eq(0));
+	let out = fs::read_to_string(&out_path)
+		.unwrap();
+	assert_that!(&out, eq("hello\noops\n".to_string()));
+
+	fs::remove_file(&out_path)
+		.ok();
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_fd_stdin() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let (mut stdin_ours, stdin_theirs) = UnixStream::pair()
+		.unwrap();
+	let (mut stdout_ours, stdout_theirs) = UnixStream::pair()
+		.unwrap();
+
+	// send the exec request, with the sockets for stdin and stdout
+	let (_pid, request_id) = exec::launch_with_fds(&mut socket, ExecRequest {
+		program: "cat".to_string(),
+		args: vec!["-".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Fd,
+		stdout: ExecStdout::Fd,
+		stderr: ExecStderr::Stream,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	}, &[stdin_theirs.as_raw_fd(), stdout_theirs.as_raw_fd()]);
+	drop(stdin_theirs);
+	drop(stdout_theirs);
+
+	// talk to the process directly
+	stdin_ours.write_all(b"hi")
+		.unwrap();
+	drop(stdin_ours);
+	let mut out = String::new();
+	stdout_ours.read_to_string(&mut out)
+		.unwrap();
+	assert_that!(&out, eq("hi".to_string()));
+
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&stdout.len(), eq(0));
+	assert_that!(&stderr.len(), eq(0));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_fd_missing() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request without any file descriptors
+	let reason = exec::launch_failure(&mut socket, ExecRequest {
+		program: "echo".to_string(),
+		args: vec![],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Fd,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	assert_that!(&reason.contains("stdout"), eq(true));
+
+	// the connection should still work
+	let (response, _) = request(&mut socket, Request::Ping);
+	assert_that!(&response, eq(Response::Pong));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stdin_file() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let in_path = PathBuf::from(SOCKET_DIR).join("in");
+	fs::write(&in_path, "from a file")
+		.unwrap();
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "cat".to_string(),
+		args: vec!["-".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::File {
+			path: in_path.to_string_lossy().to_string()
+		},
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Stream,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("from a file".to_string()));
+	assert_that!(&stderr.len(), eq(0));
+
+	// a missing file should fail the request
+	let reason = exec::launch_failure(&mut socket, ExecRequest {
+		program: "cat".to_string(),
+		args: vec!["-".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::File {
+			path: "/nope/probably/not/a/thing/right?".to_string()
+		},
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Stream,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	assert_that!(&reason.contains("stdin"), eq(true));
+
+	fs::remove_file(&in_path)
+		.ok();
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_stdin_bytes() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request, stdin should get closed after the bytes without asking
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "cat".to_string(),
+		args: vec!["-".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Bytes(b"inline".to_vec()),
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Stream,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("inline".to_string()));
+	assert_that!(&stderr.len(), eq(0));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_pty() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "test -t 1 && test -t 2 && echo tty; stty size < /dev/tty".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Pty,
+		stderr: ExecStderr::Merge,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// get the terminal output
+	// NOTE: the terminal translates newlines to CRLF
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("tty\r\n24 80\r\n".to_string()));
+	assert_that!(&stderr.len(), eq(0));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_pty_resize() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "read line; stty size".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Stream,
+		stdout: ExecStdout::Pty,
+		stderr: ExecStderr::Merge,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// resize the terminal, then let the process continue
+	exec::resize_pty(&mut socket, pid, 30, 100);
+	exec::write_stdin(&mut socket, pid, b"go\n");
+
+	// get the terminal output, which includes the echoed input
+	let (stdout, _stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("go\r\n30 100\r\n".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_console_lines() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "printf 'one\\ntw'; sleep 0.1; printf 'o\\n'; printf 'err\\n' >&2; printf 'toolongline\\nend'".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Stream,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: Some(ConsoleLines {
+			max_line_length: 6,
+			max_partial: 1024
+		}),
+		env: None,
+		container: None
+	});
+
+	let (lines, exit_code) = exec::lines(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+
+	// NOTE: the order between stdout and stderr isn't deterministic, so check them separately
+	let stdout = lines.iter()
+		.filter(|line| line.kind == ConsoleKind::Stdout)
+		.collect::<Vec<_>>();
+	assert_that!(&stdout.iter().map(|line| line.line.as_str()).collect::<Vec<_>>(), eq(vec!["one", "two", "toolon", "end"]));
+	assert_that!(&stdout.iter().map(|line| line.truncated).collect::<Vec<_>>(), eq(vec![false, false, true, false]));
+	assert_that!(&stdout.iter().map(|line| line.partial).collect::<Vec<_>>(), eq(vec![false, false, false, true]));
+	assert_that!(&stdout.windows(2).all(|w| w[0].timestamp <= w[1].timestamp), eq(true));
+	assert_that!(&stdout[1].timestamp, gt(stdout[0].timestamp + 100_000));
+
+	let stderr = lines.iter()
+		.filter(|line| line.kind == ConsoleKind::Stderr)
+		.map(|line| line.line.as_str())
+		.collect::<Vec<_>>();
+	assert_that!(&stderr, eq(vec!["err"]));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_console_lines_partial() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "printf 'abcdef'; sleep 0.2; printf 'gh\\n'".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Stream,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: Some(ConsoleLines {
+			max_line_length: 1024,
+			max_partial: 4
+		}),
+		env: None,
+		container: None
+	});
+
+	// the start of the line should arrive before the end
+	let (lines, exit_code) = exec::lines(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&lines.iter().map(|line| line.line.as_str()).collect::<Vec<_>>(), eq(vec!["abcdef", "gh"]));
+	assert_that!(&lines.iter().map(|line| line.partial).collect::<Vec<_>>(), eq(vec![true, false]));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+const FLOOD_BYTES: usize = 20*1024*1024;
+
+
+#[test]
+fn exec_flood_block() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_args(&["--output-budget", "65536"]);
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "head".to_string(),
+		args: vec!["-c".to_string(), FLOOD_BYTES.to_string(), "/dev/zero".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// all of the output should arrive, even though it's much bigger than the budget
+	let (stdout, _stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&stdout.len(), eq(FLOOD_BYTES));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_flood_drop() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_args(&["--output-budget", "65536", "--overflow", "drop"]);
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "head".to_string(),
+		args: vec!["-c".to_string(), FLOOD_BYTES.to_string(), "/dev/zero".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// don't read anything for a while, the process should finish anyway
+	thread::sleep(Duration::from_secs(2));
+	assert_that!(&is_alive(pid), eq(false));
+
+	// some of the output should have been dropped, but all of it should be accounted for
+	let mut received = 0usize;
+	let mut dropped = 0usize;
+	let exit_code = loop {
+		let response = socket.read_framed()
+			.unwrap();
+		let response = ResponseEnvelope::decode(response)
+			.unwrap();
+		assert_that!(&response.id, eq(request_id));
+		match response.response {
+			Response::ProcessEvent(ProcessEvent::Console { chunk, .. }) => received += chunk.len(),
+			Response::ProcessEvent(ProcessEvent::Dropped { kind, bytes }) => {
+				assert_that!(&kind, eq(ConsoleKind::Stdout));
+				dropped += bytes as usize;
+			}
+			Response::ProcessEvent(ProcessEvent::Fin { exit_code, .. }) => break exit_code,
+			r => panic!("unexpected response: {:?}", r)
+		}
+	};
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&dropped, gt(0));
+	assert_that!(&(received + dropped), eq(FLOOD_BYTES));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_flood_drop_quiet() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_args(&["--output-budget", "65536", "--overflow", "drop"]);
+	let mut socket = host_processor.connect();
+	socket.set_read_timeout(Some(Duration::from_secs(5)))
+		.unwrap();
+
+	// flood the output, and then go quiet without exiting
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), format!("head -c {} /dev/zero; sleep 30", FLOOD_BYTES)],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	thread::sleep(Duration::from_secs(2));
+
+	// the dropped output should be accounted for once the queue catches up, without waiting for more events
+	let mut received = 0usize;
+	let mut dropped = 0usize;
+	while received + dropped < FLOOD_BYTES {
+		let response = receive(&mut socket, request_id);
+		match response {
+			Response::ProcessEvent(ProcessEvent::Console { chunk, .. }) => received += chunk.len(),
+			Response::ProcessEvent(ProcessEvent::Dropped { bytes, .. }) => dropped += bytes as usize,
+			r => panic!("unexpected response: {:?}", r)
+		}
+	}
+	assert_that!(&dropped, gt(0));
+	assert_that!(&(received + dropped), eq(FLOOD_BYTES));
+
+	exec::signal(&mut socket, pid, KillSignal::Kill);
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_flood_spill() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_args(&["--output-budget", "65536", "--overflow", "spill"]);
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "head".to_string(),
+		args: vec!["-c".to_string(), FLOOD_BYTES.to_string(), "/dev/zero".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// don't read anything for a while, the process should finish anyway
+	thread::sleep(Duration::from_secs(2));
+	assert_that!(&is_alive(pid), eq(false));
+
+	// but none of the output should be lost
+	let (stdout, _stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&stdout.len(), eq(FLOOD_BYTES));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_flood_fair() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// launch a chatty process and a quiet one on the same connection
+	let flood_id = send_with_id(&mut socket, 1, Request::Exec(ExecRequest {
+		program: "head".to_string(),
+		args: vec!["-c".to_string(), (5*FLOOD_BYTES).to_string(), "/dev/zero".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	}));
+	thread::sleep(Duration::from_millis(100));
+	let quiet_id = send_with_id(&mut socket, 2, Request::Exec(ExecRequest {
+		program: "echo".to_string(),
+		args: vec!["quiet".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	}));
+
+	// the quiet process shouldn't have to wait for the chatty one
+	let mut flood_bytes = 0usize;
+	let mut quiet_fin = None;
+	loop {
+		let response = socket.read_framed()
+			.unwrap();
+		let response = ResponseEnvelope::decode(response)
+			.unwrap();
+		match (response.id, response.response) {
+			(_, Response::Exec(ExecResponse::Success { .. })) => (),
+			(id, Response::ProcessEvent(ProcessEvent::Console { chunk, .. })) if id == flood_id => flood_bytes += chunk.len(),
+			(id, Response::ProcessEvent(ProcessEvent::Console { chunk, .. })) if id == quiet_id =>
+				assert_that!(&String::from_utf8_lossy(chunk.as_ref()).to_string(), eq("quiet\n".to_string())),
+			(id, Response::ProcessEvent(ProcessEvent::Fin { .. })) if id == quiet_id => quiet_fin = Some(flood_bytes),
+			(id, Response::ProcessEvent(ProcessEvent::Fin { .. })) if id == flood_id => break,
+			r => panic!("unexpected response: {:?}", r)
+		}
+	}
+	assert_that!(&flood_bytes, eq(5*FLOOD_BYTES));
+	let Some(quiet_fin) = quiet_fin
+		else { panic!("quiet process didn't finish before the chatty one"); };
+	assert_that!(&quiet_fin, lt(flood_bytes));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+fn is_alive(pid: u32) -> bool {
+	PathBuf::from(format!("/proc/{}", pid))
+		.exists()
+}
+
+
+#[test]
+fn exec_dir() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "pwd".to_string(),
+		args: vec![],
+		dir: Some("/tmp".to_string()),
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// get the stdout
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("/tmp\n".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_envvars() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "echo $TEST1 $TEST2".to_string()],
+		dir: Some("/tmp".to_string()),
+		envvars: vec![
+			("TEST1".to_string(), "foo".to_string()),
+			("TEST2".to_string(), "bar".to_string())
+		],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// get the stdout
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("foo bar\n".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_env_unset() {
+	let _logging = logging::init_test();
+
+	let mut cmd = HostProcessor::command(&[]);
+	cmd.env("SECRET", "hunter2")
+		.env("OTHER", "other");
+	let host_processor = HostProcessor::start_with_command(cmd);
+	let mut socket = host_processor.connect();
+
+	// inherit everything but the secret
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "echo ${SECRET:-nosecret} ${OTHER:-noother} ${PATH:+path} $FOO".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: Some(ExecEnv {
+			mode: EnvMode::Inherit,
+			unset: vec!["SECRET".to_string()]
+		}),
+		container: None
+	});
+
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("nosecret other path\n".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_env_clear() {
+	let _logging = logging::init_test();
+
+	let mut cmd = HostProcessor::command(&[]);
+	cmd.env("SECRET", "hunter2")
+		.env("OTHER", "other");
+	let host_processor = HostProcessor::start_with_command(cmd);
+	let mut socket = host_processor.connect();
+
+	// inherit nothing, but still get the request's variables
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "env".to_string(),
+		args: vec![],
+		dir: None,
+		envvars: vec![("FOO".to_string(), "foo".to_string())],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: Some(ExecEnv {
+			mode: EnvMode::Clear,
+			unset: vec![]
+		}),
+		container: None
+	});
+
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("FOO=foo\n".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_env_allow() {
+	let _logging = logging::init_test();
+
+	let mut cmd = HostProcessor::command(&[]);
+	cmd.env("SECRET", "hunter2")
+		.env("OTHER", "other");
+	let host_processor = HostProcessor::start_with_command(cmd);
+	let mut socket = host_processor.connect();
+
+	// inherit only some variables, and unset one of those anyway
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "echo ${SECRET:-nosecret} ${OTHER:-noother} ${PATH:+path} $FOO".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: Some(ExecEnv {
+			mode: EnvMode::Allow(vec!["PATH".to_string(), "SECRET".to_string(), "OTHER".to_string()]),
+			unset: vec!["SECRET".to_string()]
+		}),
+		container: None
+	});
+
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("nosecret other path\n".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+/// Makes a folder with a stub apptainer that prints its arguments and the variables it would pass into the container
+fn stub_apptainer(dir: &Path) -> PathBuf {
+	let bin_dir = dir.join("bin");
+	fs::create_dir_all(&bin_dir)
+		.unwrap();
+	let path = bin_dir.join("apptainer");
+	fs::write(&path, "#!/bin/sh\nprintf '%s\\n' \"$@\"\nenv | grep ^APPTAINERENV_ | sort\n")
+		.unwrap();
+	fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+		.unwrap();
+	bin_dir
+}
+
+
+#[test]
+fn exec_container() {
+	let _logging = logging::init_test();
+
+	let dir = PathBuf::from(SOCKET_DIR)
+		.join(format!("container-{}", rand::random::<u32>()));
+	let data_dir = dir.join("data");
+	fs::create_dir_all(data_dir.join("in"))
+		.unwrap();
+	let data_dir = fs::canonicalize(data_dir)
+		.unwrap();
+	let bin_dir = stub_apptainer(&dir);
+
+	let mut cmd = HostProcessor::command(&["--allow-bind", &data_dir.to_string_lossy()]);
+	cmd.env("PATH", format!("{}:{}", bin_dir.to_string_lossy(), std::env::var("PATH").unwrap()));
+	let host_processor = HostProcessor::start_with_command(cmd);
+	let mut socket = host_processor.connect();
+
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "echo".to_string(),
+		args: vec!["hi".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Stream,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: Some(ExecContainer {
+			image: "/images/pyp.sif".to_string(),
+			binds: vec![
+				ContainerBind {
+					src: data_dir.to_string_lossy().to_string(),
+					dst: None,
+					read_only: false
+				},
+				ContainerBind {
+					src: data_dir.join("in").to_string_lossy().to_string(),
+					dst: Some("/in".to_string()),
+					read_only: true
+				}
+			],
+			envvars: vec![
+				("FOO".to_string(), "a,b".to_string())
+			],
+			flags: vec![ContainerFlag::Nv],
+			workdir: Some("/in".to_string())
+		})
+	});
+
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	let data_dir = data_dir.to_string_lossy();
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq([
+		"exec",
+		"--nv",
+		"--bind", &format!("{}:{}", data_dir, data_dir),
+		"--bind", &format!("{}/in:/in:ro", data_dir),
+		"--pwd", "/in",
+		"/images/pyp.sif",
+		"echo", "hi",
+		"APPTAINERENV_FOO=a,b",
+		""
+	].join("\n")));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_container_denied() {
+	let _logging = logging::init_test();
+
+	let dir = PathBuf::from(SOCKET_DIR)
+		.join(format!("container-{}", rand::random::<u32>()));
+	let data_dir = dir.join("data");
+	fs::create_dir_all(&data_dir)
+		.unwrap();
+
+	// make a symlink to try to sneak around the allow-list
+	std::os::unix::fs::symlink("/etc", data_dir.join("etc"))
+		.unwrap();
+
+	let host_processor = HostProcessor::start_with_args(&["--allow-bind", &data_dir.to_string_lossy()]);
+	let mut socket = host_processor.connect();
+
+	let exec_request = |container: ExecContainer| ExecRequest {
+		program: "echo".to_string(),
+		args: vec![],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: Some(container)
+	};
+	let container = |src: &str| ExecContainer {
+		image: "/images/pyp.sif".to_string(),
+		binds: vec![ContainerBind {
+			src: src.to_string(),
+			dst: None,
+			read_only: false
+		}],
+		envvars: vec![],
+		flags: vec![],
+		workdir: None
+	};
+
+	let reason = exec::launch_failure(&mut socket, exec_request(container("/etc")));
+	assert_that!(&reason, eq("Bind not allowed: /etc".to_string()));
+
+	let reason = exec::launch_failure(&mut socket, exec_request(container(&data_dir.join("etc").to_string_lossy())));
+	assert_that!(&reason, eq("Bind not allowed: /etc".to_string()));
+
+	// binds can't sneak in more binds or options
+	let src = format!("{}:/x,/etc", data_dir.to_string_lossy());
+	let reason = exec::launch_failure(&mut socket, exec_request(container(&src)));
+	assert_that!(&reason, eq(format!("Invalid bind source: {}", src)));
+
+	let reason = exec::launch_failure(&mut socket, exec_request(ExecContainer {
+		image: "--bind=/etc".to_string(),
+		.. container(&data_dir.to_string_lossy())
+	}));
+	assert_that!(&reason, eq("Invalid container image: --bind=/etc".to_string()));
+
+	let reason = exec::launch_failure(&mut socket, exec_request(ExecContainer {
+		envvars: vec![("A=B".to_string(), "C".to_string())],
+		.. container(&data_dir.to_string_lossy())
+	}));
+	assert_that!(&reason, eq("Invalid container environment variable name: A=B".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+fn pipeline_stage(program: &str, args: &[&str]) -> PipelineStage {
+	PipelineStage {
+		program: program.to_string(),
+		args: args.iter()
+			.map(|arg| arg.to_string())
+			.collect(),
+		envvars: vec![]
+	}
+}
+
+
+fn pipeline_request(stages: Vec<PipelineStage>) -> ExecPipelineRequest {
+	ExecPipelineRequest {
+		stages,
+		dir: None,
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Stream,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		lines: None,
+		env: None
+	}
+}
+
+
+#[test]
+fn exec_pipeline() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let (_pid, request_id) = exec::launch_pipeline(&mut socket, pipeline_request(vec![
+		pipeline_stage("sh", &["-c", "printf 'b\\na\\n'; echo one >&2"]),
+		pipeline_stage("sort", &[]),
+		pipeline_stage("sh", &["-c", "tr a-z A-Z; echo two >&2"])
+	]));
+
+	// every stage writes to the same stderr, but only the last stage writes to stdout
+	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("A\nB\n".to_string()));
+	let stderr = String::from_utf8_lossy(stderr.as_ref()).to_string();
+	let mut stderr_lines = stderr.lines()
+		.collect::<Vec<_>>();
+	stderr_lines.sort();
+	assert_that!(&stderr_lines, eq(vec!["one", "two"]));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_pipeline_fd_stdout() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let out_path = PathBuf::from(SOCKET_DIR).join(format!("out-pipeline-{}", rand::random::<u32>()));
+	let out_file = fs::File::create(&out_path)
+		.unwrap();
+
+	// only the last stage writes to the file
+	let (_pid, request_id) = exec::launch_pipeline_with_fds(&mut socket, ExecPipelineRequest {
+		stdout: ExecStdout::Fd,
+		.. pipeline_request(vec![
+			pipeline_stage("printf", &["b\\na\\n"]),
+			pipeline_stage("sort", &[]),
+			pipeline_stage("tr", &["a-z", "A-Z"])
+		])
+	}, &[out_file.as_raw_fd()]);
+	drop(out_file);
+
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&stdout.len(), eq(0));
+	let out = fs::read_to_string(&out_path)
+		.unwrap();
+	assert_that!(&out, eq("A\nB\n".to_string()));
+
+	fs::remove_file(&out_path)
+		.ok();
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_pipeline_stdin() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let (pid, request_id) = exec::launch_pipeline(&mut socket, ExecPipelineRequest {
+		stdin: ExecStdin::Stream,
+		.. pipeline_request(vec![
+			pipeline_stage("cat", &[]),
+			pipeline_stage("wc", &["-l"])
+		])
+	});
+
+	// stdin goes to the first stage
+	exec::write_stdin(&mut socket, pid, "a\nb\nc\n");
+	exec::close_stdin(&mut socket, pid);
+
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).trim().to_string(), eq("3".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_pipeline_stages() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let (_pid, request_id) = exec::launch_pipeline(&mut socket, ExecPipelineRequest {
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		.. pipeline_request(vec![
+			pipeline_stage("sh", &["-c", "exit 3"]),
+			pipeline_stage("cat", &[]),
+			pipeline_stage("sh", &["-c", "cat; exit 5"])
+		])
+	});
+
+	// the pipeline exits like its last stage, but the fin has all of them
+	let (exit_code, stages) = exec::fin_stages(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(5)));
+	assert_that!(&stages, eq(vec![
+		Some(ProcessExit::Code(3)),
+		Some(ProcessExit::Code(0)),
+		Some(ProcessExit::Code(5))
+	]));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_pipeline_process_group() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let (pid, request_id) = exec::launch_pipeline(&mut socket, ExecPipelineRequest {
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		.. pipeline_request(vec![
+			pipeline_stage("sleep", &["30"]),
+			pipeline_stage("sleep", &["30"])
+		])
+	});
+
+	// killing the process group stops every stage
+	let (response, _request_id) = request(&mut socket, Request::Kill {
+		signal: KillSignal::Kill,
+		pid,
+		process_group: true
+	});
+	assert_that!(&response, eq(Response::Kill(KillResponse::Success)));
+
+	let (exit_code, stages) = exec::fin_stages(&mut socket, request_id);
+	assert_that!(&exit_code, eq(None));
+	assert_that!(&stages, eq(vec![
+		Some(ProcessExit::Signal(libc::SIGKILL)),
+		Some(ProcessExit::Signal(libc::SIGKILL))
+	]));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_pipeline_invalid() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let reason = exec::launch_pipeline_failure(&mut socket, pipeline_request(vec![]));
+	assert_that!(&reason.as_str(), eq("Pipeline has no stages"));
+
+	let reason = exec::launch_pipeline_failure(&mut socket, ExecPipelineRequest {
+		stdout: ExecStdout::Pty,
+		.. pipeline_request(vec![pipeline_stage("echo", &[])])
+	});
+	assert_that!(&reason.as_str(), eq("Pipelines can't use a pseudo-terminal"));
+
+	// a stage that can't start stops the whole pipeline
+	let reason = exec::launch_pipeline_failure(&mut socket, pipeline_request(vec![
+		pipeline_stage("sleep", &["30"]),
+		pipeline_stage("/nonexistent/program", &[])
+	]));
+	assert_that!(&reason.starts_with("Failed to start process"), eq(true));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+#[test]
+fn exec_status() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let status = exec::status(&mut socket, 5);
+	assert_that!(&status, eq(ProcessStatus::Unknown));
+
+	// start a process that will keep running until we tell it to stop (by closing stdin)
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "cat".to_string(),
+		args: vec!["-".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Stream,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	let status = exec::status(&mut socket, pid);
+	assert_that!(&status.is_running(), eq(true));
+
+	// stop it
+	exec::close_stdin(&mut socket, pid);
+
+	// wait a bit for the process to exit
+	thread::sleep(Duration::from_millis(200));
+
+	let (exit, _usage, limit) = exec::exited(&mut socket, pid);
+	assert_that!(&exit, eq(Some(ProcessExit::Code(0))));
+	assert_that!(&limit, eq(None));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_wait() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+	let mut other_socket = host_processor.connect();
+
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "sleep 0.5; exit 3".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// wait from another connection, which should block until the process exits
+	let start = Instant::now();
+	let response = exec::wait(&mut other_socket, pid, None);
+	assert_that!(&response, eq(WaitResponse::Exited {
+		exit: Some(ProcessExit::Code(3))
+	}));
+	assert_that!(&start.elapsed(), gt(Duration::from_millis(300)));
+
+	// waiting again should answer right away
+	let start = Instant::now();
+	let response = exec::wait(&mut other_socket, pid, Some(5_000));
+	assert_that!(&response, eq(WaitResponse::Exited {
+		exit: Some(ProcessExit::Code(3))
+	}));
+	assert_that!(&start.elapsed(), lt(Duration::from_millis(1_000)));
+
+	host_processor.disconnect(other_socket);
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_wait_timeout() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let response = exec::wait(&mut socket, 5, None);
+	assert_that!(&response, eq(WaitResponse::Unknown));
+
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sleep".to_string(),
+		args: vec!["10".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	let response = exec::wait(&mut socket, pid, Some(200));
+	assert_that!(&response, eq(WaitResponse::Timeout));
+
+	// stop it, then the wait should see the signal
+	exec::kill(&mut socket, pid);
+	let response = exec::wait(&mut socket, pid, Some(5_000));
+	assert_that!(&response, eq(WaitResponse::Exited {
+		exit: Some(ProcessExit::Signal(libc::SIGINT))
+	}));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_kill() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let status = exec::status(&mut socket, 5);
+	assert_that!(&status, eq(ProcessStatus::Unknown));
+
+	// start a process that will keep running until we tell it to stop (by sending SIGTERM)
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "cat".to_string(),
+		args: vec!["-".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Stream,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	let status = exec::status(&mut socket, pid);
+	assert_that!(&status.is_running(), eq(true));
+
+	// stop it
+	exec::kill(&mut socket, pid);
+
+	// wait a bit for the process to exit
+	thread::sleep(Duration::from_millis(200));
+
+	let (exit, _usage, _limit) = exec::exited(&mut socket, pid);
+	assert_that!(&exit, eq(Some(ProcessExit::Signal(libc::SIGINT))));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_kill_signals() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// start a process that reports signals
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "trap 'echo usr1' USR1; trap 'echo hup; exit 5' HUP; echo ready; while true; do sleep 0.1; done".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	let (kind, chunk) = exec::console(&mut socket, request_id);
+	assert_that!(&kind, eq(ConsoleKind::Stdout));
+	assert_that!(&String::from_utf8_lossy(chunk.as_ref()).to_string(), eq("ready\n".to_string()));
+
+	exec::signal(&mut socket, pid, KillSignal::User1);
+	let (_kind, chunk) = exec::console(&mut socket, request_id);
+	assert_that!(&String::from_utf8_lossy(chunk.as_ref()).to_string(), eq("usr1\n".to_string()));
+
+	exec::signal(&mut socket, pid, KillSignal::Hangup);
+	let (stdout, _stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("hup\n".to_string()));
+	assert_that!(&exit_code, eq(Some(5)));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_kill_not_found() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let (response, _request_id) = request(&mut socket, Request::Kill {
+		signal: KillSignal::Terminate,
+		pid: 5,
+		process_group: false
+	});
+	assert_that!(&response, eq(Response::Kill(KillResponse::Failure(KillFailure {
+		errno: None,
+		reason: "Process not found".to_string()
+	}))));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_kill_gracefully() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// a process that exits on SIGTERM
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sleep".to_string(),
+		args: vec!["10".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	let (response, _request_id) = request(&mut socket, Request::KillGracefully {
+		pid,
+		timeout: 2000
+	});
+	assert_that!(&response, eq(Response::KillGracefully(KillGracefullyResponse::Terminated)));
+	let (exit, _usage, _limit) = exec::exited(&mut socket, pid);
+	assert_that!(&exit, eq(Some(ProcessExit::Signal(libc::SIGTERM))));
+
+	// a process that ignores SIGTERM
+	// NOTE: ignored signals are inherited by subprocesses too
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "trap '' TERM; sleep 10".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	thread::sleep(Duration::from_millis(200));
+	let (response, _request_id) = request(&mut socket, Request::KillGracefully {
+		pid,
+		timeout: 500
+	});
+	assert_that!(&response, eq(Response::KillGracefully(KillGracefullyResponse::Killed)));
+	let (exit, _usage, _limit) = exec::exited(&mut socket, pid);
+	assert_that!(&exit, eq(Some(ProcessExit::Signal(libc::SIGKILL))));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_status_usage() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// burn a little CPU time
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "i=0; while [ $i -lt 100000 ]; do i=$((i+1)); done; exit 3".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	let exit_code = exec::fin(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(3)));
+
+	let (response, _request_id) = request(&mut socket, Request::Status { pid });
+	let Response::Status(ProcessStatus::Exited { start_time, wall_time, exit, usage, limit }) = response
+		else { panic!("unexpected response: {:?}", response); };
+	assert_that!(&start_time, gt(0));
+	assert_that!(&wall_time, gt(0));
+	assert_that!(&exit, eq(Some(ProcessExit::Code(3))));
+	assert_that!(&limit, eq(None));
+	let usage = usage.expect("no usage");
+	assert_that!(&(usage.user_time + usage.system_time), gt(0));
+	assert_that!(&usage.max_rss, gt(0));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_status_retention() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_args(&["--retention", "0"]);
+	let mut socket = host_processor.connect();
+
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "true".to_string(),
+		args: vec![],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	exec::fin(&mut socket, request_id);
+
+	// with no retention, the process should be forgotten as soon as it exits
+	let status = exec::status(&mut socket, pid);
+	assert_that!(&status, eq(ProcessStatus::Unknown));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn list_processes() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	let (response, _request_id) = request(&mut socket, Request::ListProcesses);
+	assert_that!(&response, eq(Response::ListProcesses(vec![])));
+
+	// start a process that will keep running until we tell it to stop (by closing stdin)
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "cat".to_string(),
+		args: vec!["-".to_string()],
+		dir: Some("/tmp".to_string()),
+		envvars: vec![],
+		stdin: ExecStdin::Stream,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Merge,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// look at it from another connection
+	let mut socket2 = host_processor.connect();
+	let procs = exec::list(&mut socket2);
+	assert_that!(&procs.len(), eq(1));
+	let proc = &procs[0];
+	assert_that!(&proc.pid, eq(pid));
+	assert_that!(&proc.program, eq("cat".to_string()));
+	assert_that!(&proc.args, eq(vec!["-".to_string()]));
+	assert_that!(&proc.dir, eq("/tmp".to_string()));
+	assert_that!(&proc.start_time, gt(0));
+	assert_that!(&proc.running, eq(true));
+	assert_that!(&proc.stdio, eq(Some(ProcessStdio {
+		stdin: ExecStdin::Stream,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Merge
+	})));
+	assert_that!(&proc.connection.is_some(), eq(true));
+
+	// stop it
+	exec::close_stdin(&mut socket, pid);
+	thread::sleep(Duration::from_millis(200));
+
+	// finished processes are still listed for a while
+	let procs = exec::list(&mut socket2);
+	assert_that!(&procs.len(), eq(1));
+	assert_that!(&procs[0].running, eq(false));
+
+	host_processor.disconnect(socket2);
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_limits() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// send the exec request
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "ulimit -n; ulimit -v".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: Some(ExecLimits {
+			address_space: Some(512*1024*1024),
+			open_files: Some(16),
+			.. ExecLimits::default()
+		}),
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// get the stdout
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("16\n524288\n".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_limits_cpu_time() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// start a process that will spin forever
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "while :; do :; done".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: Some(ExecLimits {
+			cpu_time: Some(1),
+			.. ExecLimits::default()
+		}),
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// the limit should stop it
+	let (exit_code, limit) = exec::fin_limit(&mut socket, request_id);
+	assert_that!(&exit_code, eq(None));
+	assert_that!(&limit, eq(Some(LimitKind::CpuTime)));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_limits_invalid() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// ask for a cgroup, but the host processor has no cgroup folder configured
+	let reason = exec::launch_failure(&mut socket, ExecRequest {
+		program: "ls".to_string(),
+		args: vec![],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: Some(ExecLimits {
+			cgroup: Some(Default::default()),
+			.. ExecLimits::default()
+		}),
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	assert_that!(&reason.contains("no cgroup folder"), eq(true));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_timeout_wall_time() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// start a process that would run for a long time
+	let start = Instant::now();
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sleep".to_string(),
+		args: vec!["100".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: Some(ExecLimits {
+			wall_time: Some(500),
+			.. ExecLimits::default()
+		}),
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// the timeout should stop it
+	let (exit_code, limit) = exec::fin_limit(&mut socket, request_id);
+	assert_that!(&exit_code, eq(None));
+	assert_that!(&limit, eq(Some(LimitKind::WallTime)));
+	assert_that!(&start.elapsed(), gt(Duration::from_millis(500)));
+	assert_that!(&start.elapsed(), lt(Duration::from_secs(5)));
+
+	// the status should show the timeout too
+	let (_exit, _usage, limit) = exec::exited(&mut socket, pid);
+	assert_that!(&limit, eq(Some(LimitKind::WallTime)));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_timeout_wall_time_escalate() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_args(&["--timeout-grace", "1"]);
+	let mut socket = host_processor.connect();
+
+	// start a process group that ignores SIGTERM
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "trap '' TERM; sleep 100".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: Some(ExecLimits {
+			wall_time: Some(500),
+			.. ExecLimits::default()
+		}),
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// the timeout should kill it eventually
+	let (exit_code, limit) = exec::fin_limit(&mut socket, request_id);
+	assert_that!(&exit_code, eq(None));
+	assert_that!(&limit, eq(Some(LimitKind::WallTime)));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_timeout_idle_time() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// start a process that writes something, then hangs
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "echo hi; sleep 100".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: Some(ExecLimits {
+			idle_time: Some(500),
+			.. ExecLimits::default()
+		}),
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	let (kind, chunk) = exec::console(&mut socket, request_id);
+	assert_that!(&kind, eq(ConsoleKind::Stdout));
+	assert_that!(&chunk, eq(b"hi\n".to_vec()));
+
+	// the timeout should stop it
+	let (exit_code, limit) = exec::fin_limit(&mut socket, request_id);
+	assert_that!(&exit_code, eq(None));
+	assert_that!(&limit, eq(Some(LimitKind::IdleTime)));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_timeout_idle_time_output() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start();
+	let mut socket = host_processor.connect();
+
+	// start a process that runs longer than the idle timeout, but keeps writing output
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "for i in 1 2 3 4 5 6; do echo $i; sleep 0.2; done".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: Some(ExecLimits {
+			idle_time: Some(800),
+			.. ExecLimits::default()
+		}),
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// the process should finish normally
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("1\n2\n3\n4\n5\n6\n".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_user() {
+	let _logging = logging::init_test();
+
+	if users::get_current_uid() != 0 {
+		info!("Not running as root, skipping test");
+		return;
+	}
+
+	let host_processor = HostProcessor::start_with_args(&["--allow-uid", "65534"]);
+	let mut socket = host_processor.connect();
+
+	// run as nobody, with the default groups
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "id -u; id -G".to_string()],
+		dir: Some("/tmp".to_string()),
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: Some(65534),
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+
+	// get the stdout
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("65534\n65534\n".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_user_self() {
+	let _logging = logging::init_test();
+
+	if users::get_current_uid() != 0 {
+		info!("Not running as root, skipping test");
+		return;
+	}
+
+	// run the host processor as nobody, in a folder it can write its socket to
+	let dir = PathBuf::from(SOCKET_DIR)
+		.join(format!("user-{}", rand::random::<u32>()));
+	fs::create_dir_all(&dir)
+		.unwrap();
+	fs::set_permissions(&dir, fs::Permissions::from_mode(0o777))
+		.unwrap();
+	// NOTE: nobody might not be able to see the host processor where it was built, so run a copy
+	let bin_path = dir.join("host-processor");
+	fs::copy(HostProcessor::bin_path(), &bin_path)
+		.unwrap();
+	let mut cmd = Command::new(&bin_path);
+	cmd.args(["--log", "trace"])
+		.current_dir(&dir)
+		.uid(65534)
+		.gid(65534);
+	let mut host_processor = HostProcessor::start_with_command(cmd);
+	host_processor.socket = Some(dir.join(format!("host-processor-{}", host_processor.proc.id())));
+	let mut socket = host_processor.connect();
+
+	// asking for the host processor's own user doesn't need root
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "id".to_string(),
+		args: vec!["-u".to_string()],
+		dir: Some("/tmp".to_string()),
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: Some(65534),
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("65534\n".to_string()));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+
+	fs::remove_dir_all(&dir)
+		.ok();
+}
+
+
+#[test]
+fn exec_user_not_allowed() {
+	let _logging = logging::init_test();
+
+	let host_processor = HostProcessor::start_with_args(&["--allow-uid", "65534"]);
+	let mut socket = host_processor.connect();
+
+	// uid isn't in the allow-list
+	let reason = exec::launch_failure(&mut socket, ExecRequest {
+		program: "id".to_string(),
+		args: vec![],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: Some(65533),
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	assert_that!(&reason.contains("uid 65533 is not allowed"), eq(true));
+
+	// supplementary group isn't in the allow-list
+	let reason = exec::launch_failure(&mut socket, ExecRequest {
+		program: "id".to_string(),
+		args: vec![],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: Some(65534),
+		gid: None,
+		gids: Some(vec![0]),
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	assert_that!(&reason.contains("supplementary gid 0 is not allowed"), eq(true));
+
+	host_processor.disconnect(socket);
+	host_processor.stop();
+}
+
+
+#[test]
+fn exec_persist_attach() {
 	let _logging = logging::init_test();
 
 	let host_processor = HostProcessor::start();
 	let mut socket = host_processor.connect();
 
-	// send the exec request
-	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
-		program: "ls".to_string(),
-		args: vec!["-al".to_string()],
+	// start a process that will write output after we disconnect
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "sleep 0.5; echo hello; echo world >&2".to_string()],
 		dir: None,
 		envvars: vec![],
 		stdin: ExecStdin::Ignore,
 		stdout: ExecStdout::Stream,
-		stderr: ExecStderr::Ignore,
-		stream_fin: true
+		stderr: ExecStderr::Stream,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: true,
+		lines: None,
+		env: None,
+		container: None
 	});
+	host_processor.disconnect(socket);
 
-	// get the stdout
+	// wait for the process to exit
+	thread::sleep(Duration::from_millis(1000));
+
+	// the process should still be tracked, but not running
+	let mut socket = host_processor.connect();
+	let (exit, _usage, _limit) = exec::exited(&mut socket, pid);
+	assert_that!(&exit, eq(Some(ProcessExit::Code(0))));
+
+	// attach to it and get the buffered outputs
+	let request_id = exec::attach(&mut socket, pid);
 	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
 	assert_that!(&exit_code, eq(Some(0)));
-	assert_that!(&stdout.len(), gt(0));
-	assert_that!(&stderr.len(), eq(0));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("hello\n".to_string()));
+	assert_that!(&String::from_utf8_lossy(stderr.as_ref()).to_string(), eq("world\n".to_string()));
+
+	// once everything is delivered, there's nothing left to attach to
+	let (response, _request_id) = request(&mut socket, Request::Attach { pid });
+	assert_that!(&response, eq(Response::Attach(AttachResponse::Failure {
+		reason: "Process already finished".to_string()
+	})));
 
 	host_processor.disconnect(socket);
 	host_processor.stop();
@@ -116,325 +3448,547 @@ fn exec_stdout_stream() {
 
 
 #[test]
-fn exec_stdout_write() {
+fn exec_persist_recover() {
 	let _logging = logging::init_test();
 
-	let host_processor = HostProcessor::start();
-	let mut socket = host_processor.connect();
+	let state_path = PathBuf::from(SOCKET_DIR).join("state");
+	fs::remove_file(&state_path)
+		.ok();
+	let state_arg = state_path.to_string_lossy().to_string();
 
-	let out_path = PathBuf::from(SOCKET_DIR).join("out");
+	let host_processor = HostProcessor::start_with_args(&["--state", &state_arg]);
+	let mut socket = host_processor.connect();
 
-	// send the exec request
-	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
-		program: "echo".to_string(),
-		args: vec!["hello".to_string()],
+	// start a process that will outlive the host processor
+	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sleep".to_string(),
+		args: vec!["2".to_string()],
 		dir: None,
 		envvars: vec![],
 		stdin: ExecStdin::Ignore,
-		stdout: ExecStdout::Write {
-			path: out_path.to_string_lossy().to_string()
-		},
+		stdout: ExecStdout::Ignore,
 		stderr: ExecStderr::Ignore,
-		stream_fin: true
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: true,
+		lines: None,
+		env: None,
+		container: None
 	});
 
-	// get the stdout
-	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
-	assert_that!(&exit_code, eq(Some(0)));
-	assert_that!(&stdout.len(), eq(0));
-	assert_that!(&stderr.len(), eq(0));
-	let out = fs::read_to_string(&out_path)
-		.unwrap();
-	assert_that!(&out.as_str(), eq("hello\n"));
+	host_processor.disconnect(socket);
+	host_processor.stop();
 
-	fs::remove_file(&out_path)
-		.ok();
+	// start a new host processor, which should find the process again
+	let host_processor = HostProcessor::start_with_args(&["--state", &state_arg]);
+	let mut socket = host_processor.connect();
+
+	let status = exec::status(&mut socket, pid);
+	assert_that!(&status.is_running(), eq(true));
+
+	// we can't know the exit code of a recovered process, but we should still find out when it finishes
+	let request_id = exec::attach(&mut socket, pid);
+	let exit_code = exec::fin(&mut socket, request_id);
+	assert_that!(&exit_code, eq(None));
 
 	host_processor.disconnect(socket);
 	host_processor.stop();
+
+	fs::remove_file(&state_path)
+		.ok();
 }
 
 
 #[test]
-fn exec_stderr_stream() {
+fn exec_persist_recover_output() {
 	let _logging = logging::init_test();
 
-	let host_processor = HostProcessor::start();
+	let state_path = PathBuf::from(SOCKET_DIR).join(format!("state-{}", rand::random::<u32>()));
+	let state_arg = state_path.to_string_lossy().to_string();
+	let flag_path = PathBuf::from(SOCKET_DIR).join(format!("flag-{}", rand::random::<u32>()));
+
+	let host_processor = HostProcessor::start_with_args(&["--state", &state_arg]);
 	let mut socket = host_processor.connect();
 
-	// send the exec request
-	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
-		program: "ls".to_string(),
-		args: vec!["/nope/probably/not/a/thing/right?".to_string()],
+	// start a process that writes while the host processor is running, while it's gone, and after it comes back
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), format!(
+			"echo one; while kill -0 {} 2> /dev/null; do sleep 0.1; done; echo two; echo two >&2; while [ ! -e {} ]; do sleep 0.1; done; echo three",
+			host_processor.proc.id(),
+			flag_path.to_string_lossy()
+		)],
 		dir: None,
 		envvars: vec![],
 		stdin: ExecStdin::Ignore,
-		stdout: ExecStdout::Ignore,
+		stdout: ExecStdout::Stream,
 		stderr: ExecStderr::Stream,
-		stream_fin: true
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: true,
+		lines: None,
+		env: None,
+		container: None
 	});
+	assert_that!(&exec::console(&mut socket, request_id), eq((ConsoleKind::Stdout, b"one\n".to_vec())));
 
-	// get the stdout
+	host_processor.disconnect(socket);
+	host_processor.stop();
+
+	// the process should survive writing while no one is reading
+	thread::sleep(Duration::from_millis(500));
+	assert_that!(&is_alive(pid), eq(true));
+
+	// start a new host processor, which should pick up the output where the last one left off
+	let host_processor = HostProcessor::start_with_args(&["--state", &state_arg]);
+	let mut socket = host_processor.connect();
+
+	let request_id = exec::attach(&mut socket, pid);
+	fs::write(&flag_path, b"")
+		.unwrap();
 	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
-	assert_that!(&exit_code, eq(Some(2)));
-	assert_that!(&stdout.len(), eq(0));
-	assert_that!(&stderr.len(), gt(0));
+	assert_that!(&stdout, eq(b"two\nthree\n".to_vec()));
+	assert_that!(&stderr, eq(b"two\n".to_vec()));
+	assert_that!(&exit_code, eq(None));
 
 	host_processor.disconnect(socket);
 	host_processor.stop();
+
+	// the named pipes should be cleaned up
+	let leftovers = fs::read_dir(SOCKET_DIR)
+		.unwrap()
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.file_name().to_string_lossy().starts_with(&format!("{}.", state_path.file_name().unwrap().to_string_lossy())))
+		.count();
+	assert_that!(&leftovers, eq(0));
+
+	fs::remove_file(&state_path)
+		.ok();
+	fs::remove_file(&flag_path)
+		.ok();
 }
 
 
 #[test]
-fn exec_stderr_write() {
+fn shutdown_terminate() {
 	let _logging = logging::init_test();
 
-	let host_processor = HostProcessor::start();
+	let host_processor = HostProcessor::start_with_args(&["--shutdown", "terminate", "--shutdown-deadline", "2"]);
 	let mut socket = host_processor.connect();
 
-	let err_path = PathBuf::from(SOCKET_DIR).join("err");
-
-	// send the exec request
-	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
-		program: "ls".to_string(),
-		args: vec!["/nope/probably/not/a/thing/right?".to_string()],
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sleep".to_string(),
+		args: vec!["60".to_string()],
 		dir: None,
 		envvars: vec![],
 		stdin: ExecStdin::Ignore,
 		stdout: ExecStdout::Ignore,
-		stderr: ExecStderr::Write {
-			path: err_path.to_string_lossy().to_string()
-		},
-		stream_fin: true
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
 	});
+	assert_that!(&is_alive(pid), eq(true));
 
-	// get the stderr
-	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
-	assert_that!(&exit_code, eq(Some(2)));
-	assert_that!(&stdout.len(), eq(0));
-	assert_that!(&stderr.len(), eq(0));
-	let err = fs::read_to_string(&err_path)
-		.unwrap();
-	assert_that!(&err.len(), gt(0));
-
-	fs::remove_file(&err_path)
-		.ok();
+	// shutting down should stop the process, and we should still get the fin
+	host_processor.interrupt();
+	let exit_code = exec::fin(&mut socket, request_id);
+	assert_that!(&exit_code, eq(None));
 
-	host_processor.disconnect(socket);
-	host_processor.stop();
+	let exit = host_processor.wait();
+	assert_that!(&exit.success(), eq(true));
+	assert_that!(&is_alive(pid), eq(false));
 }
 
 
 #[test]
-fn exec_stderr_merge() {
+fn shutdown_terminate_escalate() {
 	let _logging = logging::init_test();
 
-	let host_processor = HostProcessor::start();
+	let host_processor = HostProcessor::start_with_args(&["--shutdown", "terminate", "--shutdown-deadline", "1"]);
 	let mut socket = host_processor.connect();
 
-	let out_path = PathBuf::from(SOCKET_DIR).join("out");
-
-	// send the exec request
-	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
-		program: "ls".to_string(),
-		args: vec!["/nope/probably/not/a/thing/right?".to_string()],
+	// start a process that ignores SIGTERM
+	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "trap '' TERM; echo ready; sleep 60".to_string()],
 		dir: None,
 		envvars: vec![],
 		stdin: ExecStdin::Ignore,
-		stdout: ExecStdout::Write {
-			path: out_path.to_string_lossy().to_string()
-		},
-		stderr: ExecStderr::Merge,
-		stream_fin: true
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
 	});
 
-	// get the output
-	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
-	assert_that!(&exit_code, eq(Some(2)));
-	assert_that!(&stdout.len(), eq(0));
-	assert_that!(&stderr.len(), eq(0));
-	let out = fs::read_to_string(&out_path)
-		.unwrap();
-	assert_that!(&out.len(), gt(0));
+	// don't shut down until the trap is in place
+	let (kind, _) = exec::console(&mut socket, request_id);
+	assert_that!(&kind, eq(ConsoleKind::Stdout));
 
-	fs::remove_file(&out_path)
-		.ok();
+	// shutting down should get around to SIGKILL
+	let start = Instant::now();
+	host_processor.interrupt();
+	let exit_code = exec::fin(&mut socket, request_id);
+	assert_that!(&exit_code, eq(None));
+	assert_that!(&start.elapsed(), gt(Duration::from_millis(900)));
 
-	host_processor.disconnect(socket);
-	host_processor.stop();
+	let exit = host_processor.wait();
+	assert_that!(&exit.success(), eq(true));
+	assert_that!(&is_alive(pid), eq(false));
 }
 
 
 #[test]
-fn exec_stdin() {
+fn shutdown_detach() {
 	let _logging = logging::init_test();
 
-	let host_processor = HostProcessor::start();
+	let handoff_path = PathBuf::from(SOCKET_DIR).join(format!("handoff-{}", rand::random::<u32>()));
+	let handoff_arg = handoff_path.to_string_lossy().to_string();
+
+	let host_processor = HostProcessor::start_with_args(&["--shutdown", "detach", "--handoff", &handoff_arg]);
 	let mut socket = host_processor.connect();
 
-	// send the exec request
 	let (pid, request_id) = exec::launch(&mut socket, ExecRequest {
-		program: "cat".to_string(),
-		args: vec!["-".to_string()],
+		program: "sleep".to_string(),
+		args: vec!["60".to_string()],
 		dir: None,
 		envvars: vec![],
-		stdin: ExecStdin::Stream,
+		stdin: ExecStdin::Ignore,
 		stdout: ExecStdout::Stream,
 		stderr: ExecStderr::Stream,
-		stream_fin: true
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
 	});
 
-	// send chunks to stdin
-	exec::write_stdin(&mut socket, pid, b"hi");
-	exec::close_stdin(&mut socket, pid);
+	// and some processes that keep writing to their outputs, which need someone to read them after we exit
+	let mut writer_socket = host_processor.connect();
+	let writer_path = PathBuf::from(SOCKET_DIR).join(format!("detached-{}.log", rand::random::<u32>()));
+	let writer = |stdout, stderr| ExecRequest {
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "while true; do echo out; echo err >&2; sleep 0.1; done".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout,
+		stderr,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	};
+	let (file_pid, _) = exec::launch(&mut writer_socket, writer(
+		ExecStdout::Write {
+			path: writer_path.to_string_lossy().to_string()
+		},
+		ExecStderr::Merge
+	));
+	let (stream_pid, _) = exec::launch(&mut writer_socket, writer(ExecStdout::Stream, ExecStderr::Stream));
 
-	// get the stdout
-	let (stdout, stderr, exit_code) = exec::outputs(&mut socket, request_id);
-	assert_that!(&exit_code, eq(Some(0)));
-	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("hi".to_string()));
-	assert_that!(&stderr.len(), eq(0));
+	// shutting down should leave the process running, and tell us so
+	host_processor.interrupt();
+	let response = socket.read_framed()
+		.unwrap();
+	let response = ResponseEnvelope::decode(response)
+		.unwrap();
+	assert_that!(&response.id, eq(request_id));
+	assert_that!(&response.response, eq(Response::ProcessEvent(ProcessEvent::Detached)));
 
-	host_processor.disconnect(socket);
-	host_processor.stop();
+	// then hang up
+	assert_that!(&socket.read_framed().is_err(), eq(true));
+
+	let exit = host_processor.wait();
+	assert_that!(&exit.success(), eq(true));
+	assert_that!(&is_alive(pid), eq(true));
+
+	// the handoff file should have the process, in the state file format
+	let handoff = fs::read_to_string(&handoff_path)
+		.unwrap();
+	assert_that!(&handoff.starts_with(&format!("{} ", pid)), eq(true));
+
+	// the writers should survive writing after we're gone, and file outputs should keep getting written
+	let size = fs::metadata(&writer_path)
+		.unwrap()
+		.len();
+	thread::sleep(Duration::from_millis(1000));
+	assert_that!(&is_alive(file_pid), eq(true));
+	assert_that!(&is_alive(stream_pid), eq(true));
+	assert_that!(&(fs::metadata(&writer_path).unwrap().len() > size), eq(true));
+
+	// cleanup
+	signal::kill(Pid::from_raw(pid as i32), Signal::SIGKILL)
+		.unwrap();
+	for pid in [file_pid, stream_pid] {
+		signal::killpg(Pid::from_raw(pid as i32), Signal::SIGKILL)
+			.unwrap();
+	}
+	fs::remove_file(&handoff_path)
+		.ok();
+	fs::remove_file(&writer_path)
+		.ok();
 }
 
 
 #[test]
-fn exec_dir() {
+fn systemd_socket_activation() {
 	let _logging = logging::init_test();
 
-	let host_processor = HostProcessor::start();
+	fs::create_dir_all(SOCKET_DIR)
+		.unwrap();
+	let socket_path = PathBuf::from(SOCKET_DIR).join(format!("host-processor-systemd-{}", rand::random::<u32>()));
+	let listener = UnixListener::bind(&socket_path)
+		.unwrap();
+
+	let host_processor = HostProcessor::start_socket_activated(&listener, &[]);
+	drop(listener);
 	let mut socket = host_processor.connect();
 
-	// send the exec request
+	let (response, _request_id) = request(&mut socket, Request::Ping);
+	assert_that!(&response, eq(Response::Pong));
+
+	// processes shouldn't inherit the socket, or think they were socket-activated too
 	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
-		program: "pwd".to_string(),
-		args: vec![],
-		dir: Some("/tmp".to_string()),
+		program: "sh".to_string(),
+		args: vec!["-c".to_string(), "echo ${LISTEN_FDS:-none}; [ -e /proc/self/fd/3 ] && echo fd3 || echo nofd3".to_string()],
+		dir: None,
 		envvars: vec![],
 		stdin: ExecStdin::Ignore,
 		stdout: ExecStdout::Stream,
 		stderr: ExecStderr::Ignore,
-		stream_fin: true
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
 	});
-
-	// get the stdout
 	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
 	assert_that!(&exit_code, eq(Some(0)));
-	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("/tmp\n".to_string()));
+	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("none\nnofd3\n".to_string()));
 
 	host_processor.disconnect(socket);
 	host_processor.stop();
+
+	// systemd owns the socket file, so the host processor should leave it alone
+	assert_that!(&socket_path.exists(), eq(true));
+	fs::remove_file(&socket_path)
+		.unwrap();
 }
 
 
 #[test]
-fn exec_envvars() {
+fn systemd_notify() {
 	let _logging = logging::init_test();
 
-	let host_processor = HostProcessor::start();
-	let mut socket = host_processor.connect();
+	fs::create_dir_all(SOCKET_DIR)
+		.unwrap();
+	let notify_path = PathBuf::from(SOCKET_DIR).join(format!("notify-{}", rand::random::<u32>()));
+	let notify = UnixDatagram::bind(&notify_path)
+		.unwrap();
+	notify.set_read_timeout(Some(Duration::from_secs(5)))
+		.unwrap();
 
-	// send the exec request
-	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
-		program: "sh".to_string(),
-		args: vec!["-c".to_string(), "echo $TEST1 $TEST2".to_string()],
-		dir: Some("/tmp".to_string()),
-		envvars: vec![
-			("TEST1".to_string(), "foo".to_string()),
-			("TEST2".to_string(), "bar".to_string())
-		],
-		stdin: ExecStdin::Ignore,
-		stdout: ExecStdout::Stream,
-		stderr: ExecStderr::Ignore,
-		stream_fin: true
-	});
+	let mut cmd = HostProcessor::command(&[]);
+	cmd.env("NOTIFY_SOCKET", &notify_path);
+	let host_processor = HostProcessor::start_with_command(cmd);
 
-	// get the stdout
-	let (stdout, _, exit_code) = exec::outputs(&mut socket, request_id);
-	assert_that!(&exit_code, eq(Some(0)));
-	assert_that!(&String::from_utf8_lossy(stdout.as_ref()).to_string(), eq("foo bar\n".to_string()));
+	let mut buf = [0u8; 256];
+	let size = notify.recv(&mut buf)
+		.unwrap();
+	assert_that!(&String::from_utf8_lossy(&buf[.. size]).to_string(), eq("READY=1".to_string()));
 
+	// the socket should be ready as soon as we're notified
+	assert_that!(&host_processor.socket_path().exists(), eq(true));
+	let mut socket = host_processor.connect();
+	let (response, _request_id) = request(&mut socket, Request::Ping);
+	assert_that!(&response, eq(Response::Pong));
 	host_processor.disconnect(socket);
+
 	host_processor.stop();
+
+	let size = notify.recv(&mut buf)
+		.unwrap();
+	assert_that!(&String::from_utf8_lossy(&buf[.. size]).to_string(), eq("STOPPING=1".to_string()));
+
+	fs::remove_file(&notify_path)
+		.ok();
 }
 
 
 #[test]
-fn exec_status() {
+fn stats() {
 	let _logging = logging::init_test();
 
 	let host_processor = HostProcessor::start();
 	let mut socket = host_processor.connect();
 
-	let (response, _request_id) = request(&mut socket, Request::Status { pid: 5 });
-	assert_that!(&response, eq(Response::Status(false)));
+	// make sure the other connection is up and running before counting it
+	let mut other_socket = host_processor.connect();
+	let (response, _request_id) = request(&mut other_socket, Request::Ping);
+	assert_that!(&response, eq(Response::Pong));
+
+	let stats = get_stats(&mut socket);
+	assert_that!(&stats.version, eq(env!("CARGO_PKG_VERSION").to_string()));
+	assert_that!(&stats.connections, eq(2));
+	assert_that!(&stats.live_processes, eq(0));
+	assert_that!(&stats.total_processes, eq(0));
 
-	// start a process that will keep running until we tell it to stop (by closing stdin)
+	// stream some output
+	let (_pid, request_id) = exec::launch(&mut socket, ExecRequest {
+		program: "echo".to_string(),
+		args: vec!["hello".to_string()],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Stream,
+		stderr: ExecStderr::Ignore,
+		stream_fin: true,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
+	let (_stdout, _stderr, exit_code) = exec::outputs(&mut socket, request_id);
+	assert_that!(&exit_code, eq(Some(0)));
+
+	// start a process that keeps running
 	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
-		program: "cat".to_string(),
-		args: vec!["-".to_string()],
+		program: "sleep".to_string(),
+		args: vec!["10".to_string()],
 		dir: None,
 		envvars: vec![],
-		stdin: ExecStdin::Stream,
+		stdin: ExecStdin::Ignore,
 		stdout: ExecStdout::Ignore,
 		stderr: ExecStderr::Ignore,
-		stream_fin: false
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
 	});
 
-	let (response, _request_id) = request(&mut socket, Request::Status { pid });
-	assert_that!(&response, eq(Response::Status(true)));
+	// fail to launch a process
+	exec::launch_failure(&mut socket, ExecRequest {
+		program: "/not/a/program".to_string(),
+		args: vec![],
+		dir: None,
+		envvars: vec![],
+		stdin: ExecStdin::Ignore,
+		stdout: ExecStdout::Ignore,
+		stderr: ExecStderr::Ignore,
+		stream_fin: false,
+		limits: None,
+		uid: None,
+		gid: None,
+		gids: None,
+		persist: false,
+		lines: None,
+		env: None,
+		container: None
+	});
 
-	// stop it
-	exec::close_stdin(&mut socket, pid);
+	// send a request that makes no sense
+	socket.write_framed([0, 0, 0, 42, 0, 0, 0, 99])
+		.unwrap();
+	let response = ResponseEnvelope::decode(socket.read_framed().unwrap())
+		.unwrap();
+	assert_that!(&matches!(response.response, Response::Error { .. }), eq(true));
 
-	// wait a bit for the process to exit
-	thread::sleep(Duration::from_millis(200));
+	let stats = get_stats(&mut socket);
+	assert_that!(&stats.live_processes, eq(1));
+	assert_that!(&stats.total_processes, eq(2));
+	assert_that!(&stats.bytes_streamed, eq("hello\n".len() as u64));
+	assert_that!(&stats.decode_errors, eq(1));
+	assert_that!(&stats.exec_failures, eq(1));
+	assert_that!(&stats.uptime, gt(0));
 
-	let (response, _request_id) = request(&mut socket, Request::Status { pid });
-	assert_that!(&response, eq(Response::Status(false)));
+	exec::signal(&mut socket, pid, KillSignal::Kill);
 
+	host_processor.disconnect(other_socket);
 	host_processor.disconnect(socket);
 	host_processor.stop();
 }
 
 
-#[test]
-fn exec_kill() {
-	let _logging = logging::init_test();
-
-	let host_processor = HostProcessor::start();
-	let mut socket = host_processor.connect();
-
-	let (response, _request_id) = request(&mut socket, Request::Status { pid: 5 });
-	assert_that!(&response, eq(Response::Status(false)));
+fn get_stats(socket: &mut UnixStream) -> HostStats {
+	let (response, _request_id) = request(socket, Request::Stats);
+	let Response::Stats(stats) = response
+		else { panic!("unexpected response: {:?}", response); };
+	stats
+}
 
-	// start a process that will keep running until we tell it to stop (by sending SIGTERM)
-	let (pid, _request_id) = exec::launch(&mut socket, ExecRequest {
-		program: "cat".to_string(),
-		args: vec!["-".to_string()],
-		dir: None,
-		envvars: vec![],
-		stdin: ExecStdin::Stream,
-		stdout: ExecStdout::Ignore,
-		stderr: ExecStderr::Ignore,
-		stream_fin: false
-	});
 
-	let (response, _request_id) = request(&mut socket, Request::Status { pid });
-	assert_that!(&response, eq(Response::Status(true)));
+#[test]
+fn stats_metrics_file() {
+	let _logging = logging::init_test();
 
-	// stop it
-	exec::kill(&mut socket, pid);
+	let path = PathBuf::from(SOCKET_DIR).join(format!("metrics-{}.prom", rand::random::<u32>()));
+	let path_arg = path.to_string_lossy().to_string();
 
-	// wait a bit for the process to exit
-	thread::sleep(Duration::from_millis(200));
+	let host_processor = HostProcessor::start_with_args(&["--metrics-file", &path_arg, "--metrics-interval", "1"]);
+	let socket = host_processor.connect();
 
-	let (response, _request_id) = request(&mut socket, Request::Status { pid });
-	assert_that!(&response, eq(Response::Status(false)));
+	// wait for the connection to show up in the file
+	let start = Instant::now();
+	let metrics = loop {
+		let metrics = fs::read_to_string(&path)
+			.unwrap_or_default();
+		if metrics.contains("host_processor_connections 1\n") || start.elapsed() > Duration::from_secs(5) {
+			break metrics;
+		}
+		thread::sleep(Duration::from_millis(100));
+	};
+	info!("metrics:\n{}", metrics);
+	assert_that!(&metrics.contains("host_processor_connections 1\n"), eq(true));
+	assert_that!(&metrics.contains("# TYPE host_processor_processes_launched_total counter\n"), eq(true));
+	assert_that!(&metrics.contains(&format!("host_processor_info{{version=\"{}\"}} 1\n", env!("CARGO_PKG_VERSION"))), eq(true));
 
 	host_processor.disconnect(socket);
 	host_processor.stop();
+
+	fs::remove_file(&path)
+		.ok();
 }
 
 
@@ -536,7 +4090,8 @@ const SOCKET_DIR: &str = "/tmp/nextpyp-host-processor";
 
 
 struct HostProcessor {
-	proc: Child
+	proc: Child,
+	socket: Option<PathBuf>
 }
 
 impl HostProcessor {
@@ -559,31 +4114,101 @@ impl HostProcessor {
 	}
 
 	fn start() -> Self {
+		Self::start_with_args(&[])
+	}
+
+	fn start_with_config(config: &str) -> Self {
+
+		fs::create_dir_all(SOCKET_DIR)
+			.expect("Failed to create socket folder");
+
+		let path = PathBuf::from(SOCKET_DIR)
+			.join(format!("config-{}.toml", rand::random::<u32>()));
+		fs::write(&path, config)
+			.expect("Failed to write config file");
+
+		let host_processor = Self::start_with_args(&["--config", path.to_str().unwrap()]);
+
+		// NOTE: the host processor only reads the config file at startup, so we can delete it once it's listening
+		host_processor.wait_for_socket();
+		fs::remove_file(&path)
+			.unwrap();
+
+		host_processor
+	}
+
+	fn start_with_socket(socket: &str, args: &[&str]) -> Self {
+		let mut host_processor = Self::start_with_args(&[&["--socket", socket], args].concat());
+		host_processor.socket = Some(PathBuf::from(SOCKET_DIR).join(socket));
+		host_processor
+	}
+
+	fn start_with_args(args: &[&str]) -> Self {
+		Self::start_with_command(Self::command(args))
+	}
+
+	/// Starts the host processor the way systemd would with socket activation:
+	/// with a socket that's already listening at fd 3, and LISTEN_PID and LISTEN_FDS in the environment
+	fn start_socket_activated(listener: &UnixListener, args: &[&str]) -> Self {
+
+		// NOTE: we can't know the pid before spawning the process, so let a shell set LISTEN_PID, then exec
+		let mut cmd = Command::new("sh");
+		cmd.args(["-c", "LISTEN_PID=$$ LISTEN_FDS=1 exec \"$0\" \"$@\""])
+			.arg(Self::bin_path())
+			.args(Self::command(args).get_args());
+		cmd.current_dir(SOCKET_DIR);
+
+		let fd = listener.as_raw_fd();
+		unsafe {
+			cmd.pre_exec(move || {
+				// NOTE: dup2() clears close-on-exec, unless the fd was already 3
+				if libc::dup2(fd, 3) == -1 || libc::fcntl(3, libc::F_SETFD, 0) == -1 {
+					return Err(std::io::Error::last_os_error());
+				}
+				Ok(())
+			});
+		}
+
+		let mut host_processor = Self::start_with_command(cmd);
+		host_processor.socket = listener.local_addr()
+			.ok()
+			.and_then(|addr| addr.as_pathname().map(PathBuf::from));
+		host_processor
+	}
+
+	fn command(args: &[&str]) -> Command {
+		let mut cmd = Command::new(Self::bin_path());
+		cmd.args(["--log", "trace"])
+			.args(args)
+			.current_dir(SOCKET_DIR);
+		cmd
+	}
+
+	fn start_with_command(mut cmd: Command) -> Self {
 
 		debug!("Starting host processor ...");
 
 		fs::create_dir_all(SOCKET_DIR)
 			.expect("Failed to create socket folder");
 
-		let proc = Command::new(Self::bin_path())
-			.args(["--log", "trace"])
-			.current_dir(SOCKET_DIR)
-			.spawn()
+		let proc = cmd.spawn()
 			.expect("Failed to spawn process");
 
 		Self {
-			proc
+			proc,
+			socket: None
 		}
 	}
 
 	fn socket_path(&self) -> PathBuf {
-		PathBuf::from(SOCKET_DIR)
-			.join(format!("host-processor-{}", self.proc.id()))
+		match &self.socket {
+			Some(path) => path.clone(),
+			None => PathBuf::from(SOCKET_DIR)
+				.join(format!("host-processor-{}", self.proc.id()))
+		}
 	}
 
-	fn connect(&self) -> UnixStream {
-
-		// wait for the socket file to appear, if needed
+	fn wait_for_socket(&self) {
 		let socket_path = self.socket_path();
 		for _ in 0 .. 10 {
 			if socket_path.exists() {
@@ -592,6 +4217,12 @@ impl HostProcessor {
 				thread::sleep(Duration::from_millis(100));
 			}
 		}
+	}
+
+	fn connect(&self) -> UnixStream {
+
+		// wait for the socket file to appear, if needed
+		self.wait_for_socket();
 
 		UnixStream::connect(self.socket_path())
 			.expect("Failed to connect to socket")
@@ -608,9 +4239,14 @@ impl HostProcessor {
 			.expect("Failed to send signal to host processor");
 	}
 
-	fn stop(mut self) -> ExitStatus {
+	fn stop(self) -> ExitStatus {
 		debug!("Stopping host processor ...");
 		self.interrupt();
+		self.wait()
+	}
+
+	/// Waits for the host processor to exit, eg after an interrupt()
+	fn wait(mut self) -> ExitStatus {
 		let exit = self.proc.wait()
 			.expect("Failed to wait for process");
 		debug!("Host processor stopped");
@@ -630,9 +4266,13 @@ impl Drop for HostProcessor {
 
 
 fn send(socket: &mut UnixStream, request: Request) -> u32 {
+	send_with_id(socket, 5, request)
+}
+
+
+fn send_with_id(socket: &mut UnixStream, request_id: u32, request: Request) -> u32 {
 
 	// encode the request
-	let request_id = 5;
 	let request = RequestEnvelope {
 		id: request_id,
 		request
@@ -648,9 +4288,38 @@ fn send(socket: &mut UnixStream, request: Request) -> u32 {
 }
 
 
+/// Sends the whole frame in one message, so the file descriptors go along with it
+fn send_with_fds(socket: &mut UnixStream, request: Request, fds: &[RawFd]) -> u32 {
+
+	let request_id = 5;
+
+	// encode and frame the request
+	let msg = RequestEnvelope {
+		id: request_id,
+		request
+	}.encode()
+		.unwrap();
+	let mut frame = (msg.len() as u32).to_be_bytes()
+		.to_vec();
+	frame.extend(msg);
+
+	// send it
+	let size = socket::sendmsg::<()>(socket.as_raw_fd(), &[IoSlice::new(&frame)], &[ControlMessage::ScmRights(fds)], MsgFlags::empty(), None)
+		.unwrap();
+	assert_that!(&size, eq(frame.len()));
+
+	request_id
+}
+
+
 fn request(socket: &mut UnixStream, request: Request) -> (Response, u32) {
 
 	let request_id = send(socket, request);
+	(receive(socket, request_id), request_id)
+}
+
+
+fn receive(socket: &mut UnixStream, request_id: u32) -> Response {
 
 	// wait for a response
 	let response = socket.read_framed()
@@ -662,20 +4331,21 @@ fn request(socket: &mut UnixStream, request: Request) -> (Response, u32) {
 
 	assert_that!(&response.id, eq(request_id));
 
-	(response.response, request_id)
+	response.response
 }
 
 
 
 mod exec {
 
+	use std::os::fd::RawFd;
 	use std::os::unix::net::UnixStream;
 
 	use galvanic_assert::{assert_that, matchers::*};
 	use tracing::info;
 
 	use host_processor::framing::{ReadFramed, WriteFramed};
-	use host_processor::proto::{ConsoleKind, ExecRequest, ExecResponse, KillSignal, ProcessEvent, Request, RequestEnvelope, Response, ResponseEnvelope};
+	use host_processor::proto::{AttachResponse, ConsoleKind, ExecPipelineRequest, ExecRequest, ExecResponse, KillResponse, KillSignal, LimitKind, ProcessEvent, ProcessExit, ProcessInfo, ProcessStatus, Request, RequestEnvelope, ResourceUsage, Response, ResponseEnvelope, StdinResponse, WaitResponse};
 
 
 	pub fn launch(socket: &mut UnixStream, request: ExecRequest) -> (u32, u32) {
@@ -695,6 +4365,132 @@ mod exec {
 	}
 
 
+	pub fn launch_with_fds(socket: &mut UnixStream, request: ExecRequest, fds: &[RawFd]) -> (u32, u32) {
+
+		let request_id = super::send_with_fds(socket, Request::Exec(request), fds);
+
+		let response = super::receive(socket, request_id);
+		let Response::Exec(ExecResponse::Success { pid }) = response
+			else { panic!("unexpected response: {:?}", response); };
+
+		info!(pid, "exec launched");
+		(pid, request_id)
+	}
+
+
+	pub fn launch_failure(socket: &mut UnixStream, request: ExecRequest) -> String {
+
+		let (response, _request_id) = super::request(socket, Request::Exec(request));
+
+		let Response::Exec(response) = response
+			else { panic!("unexpected response: {:?}", response); };
+
+		match response {
+			ExecResponse::Success { pid } => panic!("exec launched, but should have failed: pid={}", pid),
+			ExecResponse::Failure { reason } => {
+				info!(reason, "exec failed");
+				reason
+			}
+		}
+	}
+
+
+	pub fn launch_pipeline(socket: &mut UnixStream, request: ExecPipelineRequest) -> (u32, u32) {
+
+		let (response, request_id) = super::request(socket, Request::ExecPipeline(request));
+
+		let Response::Exec(response) = response
+			else { panic!("unexpected response: {:?}", response); };
+
+		match response {
+			ExecResponse::Success { pid } => {
+				info!(pid, "exec pipeline launched");
+				(pid, request_id)
+			}
+			ExecResponse::Failure { reason } => panic!("exec pipeline failed: {}", reason)
+		}
+	}
+
+
+	pub fn launch_pipeline_with_fds(socket: &mut UnixStream, request: ExecPipelineRequest, fds: &[RawFd]) -> (u32, u32) {
+
+		let request_id = super::send_with_fds(socket, Request::ExecPipeline(request), fds);
+
+		let response = super::receive(socket, request_id);
+		let Response::Exec(ExecResponse::Success { pid }) = response
+			else { panic!("unexpected response: {:?}", response); };
+
+		info!(pid, "exec pipeline launched");
+		(pid, request_id)
+	}
+
+
+	pub fn launch_pipeline_failure(socket: &mut UnixStream, request: ExecPipelineRequest) -> String {
+
+		let (response, _request_id) = super::request(socket, Request::ExecPipeline(request));
+
+		let Response::Exec(response) = response
+			else { panic!("unexpected response: {:?}", response); };
+
+		match response {
+			ExecResponse::Success { pid } => panic!("exec pipeline launched, but should have failed: pid={}", pid),
+			ExecResponse::Failure { reason } => {
+				info!(reason, "exec pipeline failed");
+				reason
+			}
+		}
+	}
+
+
+	pub fn attach(socket: &mut UnixStream, pid: u32) -> u32 {
+
+		let (response, request_id) = super::request(socket, Request::Attach { pid });
+
+		assert_that!(&response, eq(Response::Attach(AttachResponse::Success)));
+		info!(pid, "exec attached");
+
+		request_id
+	}
+
+
+	pub fn status(socket: &mut UnixStream, pid: u32) -> ProcessStatus {
+
+		let (response, _request_id) = super::request(socket, Request::Status { pid });
+
+		let Response::Status(status) = response
+			else { panic!("unexpected response: {:?}", response); };
+		status
+	}
+
+
+	pub fn wait(socket: &mut UnixStream, pid: u32, timeout: Option<u32>) -> WaitResponse {
+
+		let (response, _request_id) = super::request(socket, Request::Wait { pid, timeout });
+
+		let Response::Wait(response) = response
+			else { panic!("unexpected response: {:?}", response); };
+		response
+	}
+
+
+	pub fn exited(socket: &mut UnixStream, pid: u32) -> (Option<ProcessExit>, Option<ResourceUsage>, Option<LimitKind>) {
+		match status(socket, pid) {
+			ProcessStatus::Exited { exit, usage, limit, .. } => (exit, usage, limit),
+			status => panic!("unexpected status: {:?}", status)
+		}
+	}
+
+
+	pub fn list(socket: &mut UnixStream) -> Vec<ProcessInfo> {
+
+		let (response, _request_id) = super::request(socket, Request::ListProcesses);
+
+		let Response::ListProcesses(procs) = response
+			else { panic!("unexpected response: {:?}", response); };
+		procs
+	}
+
+
 	pub fn write_stdin(socket: &mut UnixStream, pid: u32, chunk: impl Into<Vec<u8>>) {
 
 		let chunk = chunk.into();
@@ -704,7 +4500,8 @@ mod exec {
 			id: 0,
 			request: Request::WriteStdin {
 				pid,
-				chunk
+				chunk,
+				ack: false
 			}
 		};
 		let msg = request.encode()
@@ -721,7 +4518,8 @@ mod exec {
 		let request = RequestEnvelope {
 			id: 0,
 			request: Request::CloseStdin {
-				pid
+				pid,
+				ack: false
 			}
 		};
 		let msg = request.encode()
@@ -733,6 +4531,68 @@ mod exec {
 	}
 
 
+	pub fn write_stdin_acked(socket: &mut UnixStream, pid: u32, chunk: impl Into<Vec<u8>>) -> StdinResponse {
+
+		let (response, _request_id) = super::request(socket, Request::WriteStdin {
+			pid,
+			chunk: chunk.into(),
+			ack: true
+		});
+
+		let Response::Stdin(response) = response
+			else { panic!("unexpected response: {:?}", response); };
+		response
+	}
+
+
+	pub fn close_stdin_acked(socket: &mut UnixStream, pid: u32) -> StdinResponse {
+
+		let (response, _request_id) = super::request(socket, Request::CloseStdin {
+			pid,
+			ack: true
+		});
+
+		let Response::Stdin(response) = response
+			else { panic!("unexpected response: {:?}", response); };
+		response
+	}
+
+
+	pub fn resize_pty(socket: &mut UnixStream, pid: u32, rows: u16, cols: u16) {
+
+		let request = RequestEnvelope {
+			id: 0,
+			request: Request::ResizePty {
+				pid,
+				rows,
+				cols
+			}
+		};
+		let msg = request.encode()
+			.unwrap();
+		socket.write_framed(msg)
+			.unwrap();
+
+		info!("exec resizing terminal: {}x{}", cols, rows);
+	}
+
+
+	pub fn console(socket: &mut UnixStream, request_id: u32) -> (ConsoleKind,Vec<u8>) {
+
+		// wait for the next response
+		let response = socket.read_framed()
+			.unwrap();
+		let response = ResponseEnvelope::decode(response)
+			.unwrap();
+
+		assert_that!(&response.id, eq(request_id));
+
+		let Response::ProcessEvent(ProcessEvent::Console { kind, chunk }) = response.response
+			else { panic!("unexpected response: {:?}", response); };
+		(kind, chunk)
+	}
+
+
 	pub fn outputs(socket: &mut UnixStream, request_id: u32) -> (Vec<u8>,Vec<u8>,Option<i32>) {
 
 		let mut stdout = Vec::<u8>::new();
@@ -760,16 +4620,73 @@ mod exec {
 					}.extend(chunk);
 				}
 
-				ProcessEvent::Fin { exit_code } => {
+				ProcessEvent::Fin { exit_code, .. } => {
 					info!(code = ?exit_code, "exec process exited");
 					return (stdout, stderr, exit_code);
 				}
+
+				event => panic!("unexpected process event: {:?}", event)
+			}
+		}
+	}
+
+
+	pub struct Line {
+		pub kind: ConsoleKind,
+		pub timestamp: u64,
+		pub line: String,
+		pub partial: bool,
+		pub truncated: bool
+	}
+
+
+	pub fn lines(socket: &mut UnixStream, request_id: u32) -> (Vec<Line>,Option<i32>) {
+
+		let mut lines = Vec::<Line>::new();
+
+		loop {
+
+			// wait for the next response
+			let response = socket.read_framed()
+				.unwrap();
+			let response = ResponseEnvelope::decode(response)
+				.unwrap();
+
+			assert_that!(&response.id, eq(request_id));
+
+			// get the console line
+			let Response::ProcessEvent(event) = response.response
+				else { panic!("unexpected response: {:?}", response); };
+			match event {
+
+				ProcessEvent::ConsoleLine { kind, timestamp, line, partial, truncated } => {
+					lines.push(Line {
+						kind,
+						timestamp,
+						line: String::from_utf8_lossy(line.as_ref()).to_string(),
+						partial,
+						truncated
+					});
+				}
+
+				ProcessEvent::Fin { exit_code, .. } => {
+					info!(code = ?exit_code, "exec process exited");
+					return (lines, exit_code);
+				}
+
+				event => panic!("unexpected process event: {:?}", event)
 			}
 		}
 	}
 
 
 	pub fn fin(socket: &mut UnixStream, request_id: u32) -> Option<i32> {
+		let (exit_code, _limit) = fin_limit(socket, request_id);
+		exit_code
+	}
+
+
+	pub fn fin_limit(socket: &mut UnixStream, request_id: u32) -> (Option<i32>, Option<LimitKind>) {
 
 		// wait for the next response
 		let response = socket.read_framed()
@@ -783,20 +4700,40 @@ mod exec {
 		let Response::ProcessEvent(event) = response.response
 			else { panic!("unexpected response: {:?}", response); };
 		match event {
-			ProcessEvent::Fin { exit_code } => {
-				info!(code = ?exit_code, "exec process exited");
-				exit_code
+			ProcessEvent::Fin { exit_code, limit, .. } => {
+				info!(code = ?exit_code, ?limit, "exec process exited");
+				(exit_code, limit)
 			},
 			_ => panic!("unexpected exec response: {:?}", event)
 		}
 	}
 
 
+	pub fn fin_stages(socket: &mut UnixStream, request_id: u32) -> (Option<i32>, Vec<Option<ProcessExit>>) {
+
+		let response = super::receive(socket, request_id);
+		let Response::ProcessEvent(ProcessEvent::Fin { exit_code, stages, .. }) = response
+			else { panic!("unexpected response: {:?}", response); };
+
+		info!(code = ?exit_code, ?stages, "exec pipeline exited");
+		(exit_code, stages)
+	}
+
+
 	pub fn kill(socket: &mut UnixStream, pid: u32) {
-		super::send(socket, Request::Kill {
-			signal: KillSignal::Interrupt,
+		signal(socket, pid, KillSignal::Interrupt);
+	}
+
+
+	pub fn signal(socket: &mut UnixStream, pid: u32, signal: KillSignal) {
+
+		let (response, _request_id) = super::request(socket, Request::Kill {
+			signal,
 			pid,
 			process_group: false
 		});
+
+		assert_that!(&response, eq(Response::Kill(KillResponse::Success)));
+		info!(pid, signal = signal.name(), "exec signaled");
 	}
 }